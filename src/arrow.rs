@@ -0,0 +1,371 @@
+//! Arrow and Parquet Export
+//!
+//! Type [`SegmentBatchBuilder`] accumulates [`Extraction`]s into Apache Arrow record batches
+//! with one column per segment, so that barcode and UMI distributions can be analysed with
+//! Arrow-based tools (e.g. Polars, pandas).  The schema is derived from the
+//! [`MultiReadStructure`]: each segment's column is named by its kind ([`SegmentType::name`])
+//! and its one-based index among the segments of that kind across all reads (e.g.
+//! `sample_barcode_1`, `sample_barcode_2`), or by its label if it has one (e.g. `i7` for
+//! `8B{i7}`), with an optional `<name>_quals` column holding its qualities.  Bases and qualities
+//! are written in the orientation of their read (see
+//! [`crate::extraction::ExtractedSegment::oriented_bases`]).
+//!
+//! [`write_parquet`] writes a stream of extractions to a Parquet file.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::{arrow::SegmentBatchBuilder, MultiReadStructure, SegmentType};
+//!
+//! let mrs = MultiReadStructure::from_str("4M+T 3B").unwrap();
+//! let kinds = [SegmentType::MolecularBarcode, SegmentType::SampleBarcode];
+//! let mut builder = SegmentBatchBuilder::with_kinds(&mrs, &kinds, false).unwrap();
+//! builder.append(&mrs.extract(&[b"AAAACCCC", b"GGG"]).unwrap()).unwrap();
+//! let batch = builder.finish().unwrap();
+//! assert_eq!(batch.num_rows(), 1);
+//! assert_eq!(batch.schema().field(0).name(), "molecular_barcode_1");
+//! assert_eq!(batch.schema().field(1).name(), "sample_barcode_1");
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::StringBuilder;
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use strum::IntoEnumIterator;
+
+use crate::extraction::Extraction;
+use crate::multi_read_structure::MultiReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// The number of extractions per record batch written by [`write_parquet`].
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// A column holding the bases (and optionally qualities) of one segment.
+#[derive(Debug)]
+struct SegmentColumn {
+    /// The name of the bases column.
+    name: String,
+    /// The builder for the bases column.
+    bases: StringBuilder,
+    /// The builder for the qualities column, if qualities are included.
+    quals: Option<StringBuilder>,
+}
+
+/// Builds Arrow record batches of extracted segments.  See
+/// [the module level documentation](self) for more.
+#[derive(Debug)]
+pub struct SegmentBatchBuilder {
+    /// The schema of the record batches.
+    schema: SchemaRef,
+    /// The columns, in schema order.
+    columns: Vec<SegmentColumn>,
+    /// The column index for each included segment, keyed by read index and segment offset.
+    column_indices: HashMap<(usize, usize), usize>,
+    /// The number of extractions appended since the last batch was finished.
+    len: usize,
+}
+
+impl SegmentBatchBuilder {
+    /// Builds a new [`SegmentBatchBuilder`] with a column for every segment in the read
+    /// structures, and a qualities column for each if `include_quals` is true.
+    ///
+    /// # Errors
+    ///
+    /// - If two columns would have the same name.
+    pub fn new(
+        read_structures: &MultiReadStructure,
+        include_quals: bool,
+    ) -> Result<Self, ReadStructureError> {
+        let kinds: Vec<SegmentType> = SegmentType::iter().collect();
+        Self::with_kinds(read_structures, &kinds, include_quals)
+    }
+
+    /// Builds a new [`SegmentBatchBuilder`] with a column for each segment of the given kinds,
+    /// and a qualities column for each if `include_quals` is true.
+    ///
+    /// # Errors
+    ///
+    /// - If two columns would have the same name, e.g. segments in different reads with the same
+    ///   label, or a label that is also the name of an unlabelled segment's column.
+    pub fn with_kinds(
+        read_structures: &MultiReadStructure,
+        kinds: &[SegmentType],
+        include_quals: bool,
+    ) -> Result<Self, ReadStructureError> {
+        let mut counts: HashMap<SegmentType, usize> = HashMap::new();
+        let mut fields = Vec::new();
+        let mut columns = Vec::new();
+        let mut column_indices = HashMap::new();
+        for (read_index, rs) in read_structures.iter().enumerate() {
+            for (segment, label) in rs.labelled_segments() {
+                let count = counts.entry(segment.kind).or_insert(0);
+                *count += 1;
+                if !kinds.contains(&segment.kind) {
+                    continue;
+                }
+                let name = label.map_or_else(
+                    || format!("{}_{}", segment.kind.name(), count),
+                    ToOwned::to_owned,
+                );
+                fields.push(Field::new(&name, DataType::Utf8, false));
+                let quals = if include_quals {
+                    fields.push(Field::new(format!("{}_quals", name), DataType::Utf8, true));
+                    Some(StringBuilder::new())
+                } else {
+                    None
+                };
+                column_indices.insert((read_index, segment.offset), columns.len());
+                columns.push(SegmentColumn { name, bases: StringBuilder::new(), quals });
+            }
+        }
+        let mut names = HashSet::new();
+        if let Some(field) = fields.iter().find(|field| !names.insert(field.name())) {
+            return Err(ReadStructureError::Arrow(format!(
+                "more than one column named: {}",
+                field.name()
+            )));
+        }
+        Ok(SegmentBatchBuilder {
+            schema: Arc::new(Schema::new(fields)),
+            columns,
+            column_indices,
+            len: 0,
+        })
+    }
+
+    /// Returns the schema of the record batches.
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Returns the number of extractions appended since the last batch was finished.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no extractions have been appended since the last batch was finished.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends the segments of an extraction as a new row.  Segments of kinds not included in
+    /// the schema are ignored; qualities are written as nulls if the extraction has none.
+    ///
+    /// # Errors
+    ///
+    /// - If the extraction is missing a segment in the schema.
+    pub fn append(&mut self, extraction: &Extraction) -> Result<(), ReadStructureError> {
+        // Match every segment to its column before appending so a failure leaves no partial row
+        let mut matched = vec![None; self.columns.len()];
+        for segment in extraction.iter() {
+            if let Some(&index) =
+                self.column_indices.get(&(segment.read_index, segment.segment.offset))
+            {
+                matched[index] = Some(segment);
+            }
+        }
+        if let Some(missing) = matched.iter().position(Option::is_none) {
+            return Err(ReadStructureError::ExtractionMissingSegment(
+                self.columns[missing].name.clone(),
+            ));
+        }
+        for (column, segment) in self.columns.iter_mut().zip(matched.into_iter().flatten()) {
+            column.bases.append_value(String::from_utf8_lossy(&segment.oriented_bases()));
+            if let Some(quals) = column.quals.as_mut() {
+                match segment.oriented_quals() {
+                    Some(q) => quals.append_value(String::from_utf8_lossy(&q)),
+                    None => quals.append_null(),
+                }
+            }
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Returns a record batch of the extractions appended since the last batch was finished.
+    ///
+    /// # Errors
+    ///
+    /// - If the record batch could not be built.
+    pub fn finish(&mut self) -> Result<RecordBatch, ReadStructureError> {
+        let mut arrays: Vec<ArrayRef> = Vec::new();
+        for column in &mut self.columns {
+            arrays.push(Arc::new(column.bases.finish()));
+            if let Some(quals) = column.quals.as_mut() {
+                arrays.push(Arc::new(quals.finish()));
+            }
+        }
+        self.len = 0;
+        RecordBatch::try_new(self.schema.clone(), arrays)
+            .map_err(|e| ReadStructureError::Arrow(e.to_string()))
+    }
+}
+
+/// Writes extractions to Parquet with the schema of the given builder, in record batches of
+/// `batch_size` extractions.
+///
+/// # Errors
+///
+/// - If any extraction does not match the builder's schema.
+/// - If writing fails.
+pub fn write_parquet<'a, W, I>(
+    writer: W,
+    mut builder: SegmentBatchBuilder,
+    extractions: I,
+    batch_size: usize,
+) -> Result<(), ReadStructureError>
+where
+    W: Write + Send,
+    I: IntoIterator<Item = Extraction<'a>>,
+{
+    let to_err = |e: parquet::errors::ParquetError| ReadStructureError::Arrow(e.to_string());
+    let mut parquet = ArrowWriter::try_new(writer, builder.schema(), None).map_err(to_err)?;
+    for extraction in extractions {
+        builder.append(&extraction)?;
+        if builder.len() >= batch_size.max(1) {
+            parquet.write(&builder.finish()?).map_err(to_err)?;
+        }
+    }
+    if !builder.is_empty() {
+        parquet.write(&builder.finish()?).map_err(to_err)?;
+    }
+    parquet.close().map_err(to_err)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::str::FromStr;
+
+    use arrow_array::{Array, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    use crate::arrow::{write_parquet, SegmentBatchBuilder};
+    use crate::multi_read_structure::MultiReadStructure;
+    use crate::orientation::Orientation;
+    use crate::segment_type::SegmentType;
+
+    fn names(builder: &SegmentBatchBuilder) -> Vec<String> {
+        builder.schema().fields().iter().map(|f| f.name().clone()).collect()
+    }
+
+    fn column<'a>(batch: &'a arrow_array::RecordBatch, name: &str) -> &'a StringArray {
+        batch.column_by_name(name).unwrap().as_any().downcast_ref::<StringArray>().unwrap()
+    }
+
+    #[test]
+    fn test_schema() {
+        let mrs = MultiReadStructure::from_str("8B4M+T 8B 4M+T").unwrap();
+        let builder = SegmentBatchBuilder::new(&mrs, true).unwrap();
+        assert_eq!(
+            names(&builder),
+            vec![
+                "sample_barcode_1",
+                "sample_barcode_1_quals",
+                "molecular_barcode_1",
+                "molecular_barcode_1_quals",
+                "template_1",
+                "template_1_quals",
+                "sample_barcode_2",
+                "sample_barcode_2_quals",
+                "molecular_barcode_2",
+                "molecular_barcode_2_quals",
+                "template_2",
+                "template_2_quals",
+            ]
+        );
+        let builder =
+            SegmentBatchBuilder::with_kinds(&mrs, &[SegmentType::MolecularBarcode], false).unwrap();
+        assert_eq!(names(&builder), vec!["molecular_barcode_1", "molecular_barcode_2"]);
+
+        let mrs = MultiReadStructure::from_str("8B{i7}8B+T{r1}").unwrap();
+        let builder = SegmentBatchBuilder::new(&mrs, true).unwrap();
+        assert_eq!(
+            names(&builder),
+            vec!["i7", "i7_quals", "sample_barcode_2", "sample_barcode_2_quals", "r1", "r1_quals"]
+        );
+    }
+
+    #[test]
+    fn test_schema_duplicate_names() {
+        for read_structures in
+            ["8B{i7}+T 8B{i7}", "8B+T 8B{sample_barcode_1}", "8B{i7}+T{i7_quals}"]
+        {
+            let mrs = MultiReadStructure::from_str(read_structures).unwrap();
+            let err = SegmentBatchBuilder::new(&mrs, true).unwrap_err();
+            assert!(err.to_string().contains("more than one column named"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_build_batches() {
+        let mrs = MultiReadStructure::from_str("2M+T 3B").unwrap();
+        let mut builder = SegmentBatchBuilder::with_kinds(
+            &mrs,
+            &[SegmentType::MolecularBarcode, SegmentType::SampleBarcode],
+            true,
+        )
+        .unwrap();
+        let extraction = mrs.extract_with_quals(&[(b"ACGG", b"IIII"), (b"TTT", b"#+5")]).unwrap();
+        builder.append(&extraction).unwrap();
+        builder.append(&mrs.extract(&[b"GGAAAA", b"CCC"]).unwrap()).unwrap();
+        assert_eq!(builder.len(), 2);
+        let batch = builder.finish().unwrap();
+        assert!(builder.is_empty());
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(column(&batch, "molecular_barcode_1").value(0), "AC");
+        assert_eq!(column(&batch, "molecular_barcode_1").value(1), "GG");
+        assert_eq!(column(&batch, "sample_barcode_1").value(1), "CCC");
+        assert_eq!(column(&batch, "sample_barcode_1_quals").value(0), "#+5");
+        assert!(column(&batch, "sample_barcode_1_quals").is_null(1));
+
+        // Reverse complemented reads are written as oriented
+        let mrs = mrs
+            .with_orientations(vec![Orientation::Forward, Orientation::ReverseComplement])
+            .unwrap();
+        let mut builder =
+            SegmentBatchBuilder::with_kinds(&mrs, &[SegmentType::SampleBarcode], true).unwrap();
+        builder
+            .append(&mrs.extract_with_quals(&[(b"ACGG", b"IIII"), (b"TTT", b"#+5")]).unwrap())
+            .unwrap();
+        let batch = builder.finish().unwrap();
+        assert_eq!(column(&batch, "sample_barcode_1").value(0), "AAA");
+        assert_eq!(column(&batch, "sample_barcode_1_quals").value(0), "5+#");
+    }
+
+    #[test]
+    fn test_append_mismatched_extraction() {
+        let mrs = MultiReadStructure::from_str("2M+T 3B").unwrap();
+        let other = MultiReadStructure::from_str("+T").unwrap();
+        let mut builder = SegmentBatchBuilder::new(&mrs, false).unwrap();
+        assert!(builder.append(&other.extract(&[b"ACGT"]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_write_parquet() {
+        let mrs = MultiReadStructure::from_str("4M+T").unwrap();
+        let reads: Vec<Vec<u8>> = (0..5).map(|i| format!("AAA{}TTTT", i).into_bytes()).collect();
+        let extractions = reads.iter().map(|r| mrs.extract(&[r.as_slice()]).unwrap());
+        let path =
+            std::env::temp_dir().join(format!("read-structure-{}.parquet", std::process::id()));
+        let builder = SegmentBatchBuilder::new(&mrs, false).unwrap();
+        write_parquet(File::create(&path).unwrap(), builder, extractions, 2).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+        assert_eq!(column(&batches[0], "molecular_barcode_1").value(1), "AAA1");
+        assert_eq!(column(&batches[0], "template_1").value(0), "TTTT");
+        std::fs::remove_file(path).unwrap();
+    }
+}
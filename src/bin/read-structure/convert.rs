@@ -0,0 +1,97 @@
+//! The `convert` subcommand: translates read structures between the notations used by other
+//! tools.
+
+use std::io::Write;
+
+use clap::Args;
+use read_structure::dialect::{format, parse, resolve_read_lengths, Dialect};
+use read_structure::ReadStructureError;
+
+/// Arguments for the `convert` subcommand.
+#[derive(Debug, Args)]
+pub struct Convert {
+    /// The dialect to convert from: fgbio, bcl-convert, bcl2fastq, kallisto, or starsolo.
+    #[arg(short, long, default_value_t = Dialect::Fgbio)]
+    from: Dialect,
+
+    /// The dialect to convert to: fgbio, bcl-convert, bcl2fastq, kallisto, or starsolo.
+    #[arg(short, long)]
+    to: Dialect,
+
+    /// The comma-separated length of each read in cycles, to give variable length segments a
+    /// fixed length.
+    #[arg(short, long, value_delimiter = ',')]
+    read_lengths: Vec<usize>,
+
+    /// The read structures to convert (e.g. `8M143T 8B 8B +T`, `U8Y143;I8;I8;Y151`, or
+    /// `--soloCBposition 0_0_0_15 --soloUMIposition 0_16_0_27`).
+    #[arg(required = true, num_args = 1.., allow_hyphen_values = true, trailing_var_arg = true)]
+    value: Vec<String>,
+}
+
+impl Convert {
+    /// Converts the read structures, writing them in the target dialect.
+    ///
+    /// # Errors
+    ///
+    /// - If the value is not valid in the source dialect.
+    /// - If the read lengths do not match the read structures.
+    /// - If the target dialect cannot represent the read structures.
+    pub fn execute<W: Write>(&self, out: &mut W) -> Result<bool, ReadStructureError> {
+        let mut read_structures = parse(&self.value.join(" "), self.from)?;
+        if !self.read_lengths.is_empty() {
+            read_structures = resolve_read_lengths(&read_structures, &self.read_lengths)?;
+        }
+        writeln!(out, "{}", format(&read_structures, self.to)?)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::Convert;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        convert: Convert,
+    }
+
+    fn convert(args: &[&str]) -> Result<String, String> {
+        let cli = Cli::try_parse_from(std::iter::once("convert").chain(args.iter().copied()))
+            .map_err(|e| e.to_string())?;
+        let mut out = Vec::new();
+        cli.convert.execute(&mut out).map_err(|e| e.to_string())?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_convert_command() {
+        let out = convert(&["--to", "bcl-convert", "-r", "151,8,8,151", "8M+T", "8B", "8B", "+T"]);
+        assert_eq!(out.unwrap(), "U8Y143;I8;I8;Y151\n");
+        let out = convert(&[
+            "--from",
+            "starsolo",
+            "--to",
+            "kallisto",
+            "--soloCBposition",
+            "0_0_0_15",
+            "--soloUMIposition",
+            "0_16_0_27",
+        ]);
+        assert_eq!(out.unwrap(), "0,0,16:0,16,28:1,0,0\n");
+        let out = convert(&["--from", "bcl2fastq", "--to", "fgbio", "Y*,I8,Y*"]);
+        assert_eq!(out.unwrap(), "+T 8B +T\n");
+    }
+
+    #[test]
+    fn test_convert_command_unsupported() {
+        let err = convert(&["--to", "bcl-convert", "16C12M", "+T"]).unwrap_err();
+        assert_eq!(err, "bcl-convert cannot represent cellular barcode segment 16C in read 1");
+        let err = convert(&["--to", "bcl2fastq", "-r", "151", "8M+T", "8B"]).unwrap_err();
+        assert_eq!(err, "Mismatching number of reads and read structures: 1, 2");
+        assert!(convert(&["--to", "cellranger", "+T"]).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! The `demux` subcommand: assigns the reads in one or more FASTQs to samples by their sample
+//! barcode segments, writing the reads for each sample to their own FASTQs.
+//!
+//! For each sample (and for unmatched reads), the segments are written as with `extract`: each
+//! template segment as its own read (e.g. `<output>/<sample>.template.1.fastq`), and all segments
+//! of any other type concatenated into a single read.  Per-sample and unmatched metrics are
+//! written to `<output>/demux_metrics.txt`.
+//!
+//! With `--workflow`, the i5 index read is reverse complemented before matching if the
+//! instrument sequences it as the reverse complement of the (forward strand) sample sheet.  Reads
+//! are always written as sequenced.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::Args;
+use read_structure::demux::{
+    Demultiplexer, DemuxMetrics, DemuxOptions, SampleMetrics, SampleSheet, UNMATCHED_SAMPLE_ID,
+};
+use read_structure::orientation::InstrumentWorkflow;
+use read_structure::{MultiReadStructure, ReadStructure, ReadStructureError, SegmentType};
+
+use crate::extract::output_reads;
+use crate::files::{compression_pool, invalid_input, open_input, FastqInputs, Output};
+
+/// The name of the metrics file written to the output directory.
+const METRICS_FILE_NAME: &str = "demux_metrics.txt";
+
+/// Arguments for the `demux` subcommand.
+#[derive(Debug, Args)]
+pub struct Demux {
+    /// The input FASTQs, optionally gzipped, one per read structure (`-` for standard input).
+    #[arg(short, long, required = true, num_args = 1..)]
+    inputs: Vec<PathBuf>,
+
+    /// The read structures, one per input FASTQ.
+    #[arg(short, long, required = true, num_args = 1..)]
+    read_structures: Vec<ReadStructure>,
+
+    /// The sample sheet: a comma or tab delimited file with `Sample_ID` and either `barcode` or
+    /// `index` and `index2` columns, or an Illumina sample sheet.
+    #[arg(short, long)]
+    sample_sheet: PathBuf,
+
+    /// The directory to write the per-sample FASTQs and metrics to.
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// The maximum number of mismatches between the observed and expected barcodes.
+    #[arg(long, default_value_t = DemuxOptions::default().max_mismatches)]
+    max_mismatches: usize,
+
+    /// The minimum difference in mismatches between the best and next best samples.
+    #[arg(long, default_value_t = DemuxOptions::default().min_mismatch_delta)]
+    min_mismatch_delta: usize,
+
+    /// The maximum number of no-calls in the observed barcode.
+    #[arg(long, default_value_t = DemuxOptions::default().max_no_calls)]
+    max_no_calls: usize,
+
+    /// The instrument workflow, used to orient the i5 index read (e.g. `novaseq-x`).  Defaults
+    /// to matching all reads as sequenced.
+    #[arg(short, long)]
+    workflow: Option<InstrumentWorkflow>,
+
+    /// The segment types to write (e.g. `T M`).  Defaults to templates only.
+    #[arg(short, long, num_args = 1..)]
+    kinds: Vec<SegmentType>,
+
+    /// Gzip compress the output FASTQs.
+    #[arg(short = 'z', long)]
+    gzip: bool,
+
+    /// The gzip compression level.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(0..=9))]
+    compression_level: u32,
+
+    /// The number of threads to use for compression.  Defaults to the number of CPUs.
+    #[arg(short, long)]
+    threads: Option<usize>,
+}
+
+impl Demux {
+    /// Demultiplexes the input FASTQs into per-sample FASTQs and writes the metrics.
+    ///
+    /// # Errors
+    ///
+    /// - If the number of inputs and read structures differ.
+    /// - If the sample sheet is invalid or its barcodes do not fit the read structures.
+    /// - If no segments have the requested types.
+    /// - If two output reads would have the same name, or two outputs the same path.
+    /// - If the inputs are malformed, have differing numbers of records, or have records whose
+    ///   names differ.
+    /// - If any read is too short for its read structure.
+    /// - If reading or writing fails.
+    pub fn execute<W: Write>(&self, out: &mut W) -> Result<bool, ReadStructureError> {
+        let mut mrs = MultiReadStructure::new(self.read_structures.clone())?;
+        if let Some(workflow) = self.workflow {
+            mrs = workflow.apply(&mrs);
+        }
+        let mut inputs = FastqInputs::open(&self.inputs, &mrs)?;
+        let options = DemuxOptions {
+            max_mismatches: self.max_mismatches,
+            min_mismatch_delta: self.min_mismatch_delta,
+            max_no_calls: self.max_no_calls,
+        };
+        let sample_sheet = SampleSheet::from_reader(open_input(&self.sample_sheet)?)?;
+        let demux = Demultiplexer::new(sample_sheet, mrs.clone(), options)?;
+        let kinds =
+            if self.kinds.is_empty() { vec![SegmentType::Template] } else { self.kinds.clone() };
+        let reads = output_reads(&mrs, &kinds)?;
+        if reads.is_empty() {
+            return Err(invalid_input(&format!("no segments of the requested types in: {}", mrs)));
+        }
+
+        let pool = compression_pool(self.gzip, self.threads, self.compression_level);
+        let extension = if self.gzip { "fastq.gz" } else { "fastq" };
+        let sample_ids = demux
+            .sample_sheet()
+            .samples()
+            .iter()
+            .map(|s| s.sample_id.as_str())
+            .chain(std::iter::once(UNMATCHED_SAMPLE_ID));
+        // The output paths for each sample, in sample sheet order, followed by unmatched reads.
+        let paths: Vec<Vec<PathBuf>> = sample_ids
+            .map(|sample_id| {
+                reads
+                    .iter()
+                    .map(|read| {
+                        self.output.join(format!("{}.{}.{}", sample_id, read.label, extension))
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut unique = HashSet::new();
+        if let Some(path) = paths.iter().flatten().find(|path| !unique.insert(*path)) {
+            return Err(invalid_input(&format!(
+                "more than one output written to: {}",
+                path.display()
+            )));
+        }
+        fs::create_dir_all(&self.output)?;
+        let mut outputs = paths
+            .iter()
+            .map(|paths| {
+                paths
+                    .iter()
+                    .map(|path| Output::create(path, pool.as_ref()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut metrics = DemuxMetrics::new(demux.sample_sheet());
+        let (mut bases, mut quals) = (Vec::new(), Vec::new());
+        let unmatched = outputs.len() - 1;
+        inputs.for_each_extraction(|records, extraction| {
+            let assignment = demux.assign_extraction(extraction);
+            metrics.add(&assignment);
+            let sample_outputs = &mut outputs[assignment.sample.unwrap_or(unmatched)];
+            for (read, output) in reads.iter().zip(sample_outputs.iter_mut()) {
+                read.write(output, records[0].base_id(), extraction, &mut bases, &mut quals)?;
+            }
+            Ok(())
+        })?;
+        for output in outputs.into_iter().flatten() {
+            output.finish()?;
+        }
+
+        let metrics = metrics.metrics();
+        let mut writer = io::BufWriter::new(fs::File::create(self.output.join(METRICS_FILE_NAME))?);
+        write_metrics(&mut writer, &metrics)?;
+        writer.flush()?;
+
+        let total: u64 = metrics.iter().map(|m| m.templates).sum();
+        let matched = total - metrics.last().map_or(0, |m| m.templates);
+        writeln!(
+            out,
+            "Assigned {} of {} templates to {} samples",
+            matched,
+            total,
+            metrics.len() - 1
+        )?;
+        Ok(true)
+    }
+}
+
+/// Writes the metrics as a tab delimited table with a header line.
+fn write_metrics<W: Write>(writer: &mut W, metrics: &[SampleMetrics]) -> io::Result<()> {
+    writeln!(
+        writer,
+        "sample_id\tbarcode\ttemplates\tperfect_matches\tone_mismatch_matches\t\
+         fraction_of_templates\tratio_to_best\tratio_to_mean"
+    )?;
+    for m in metrics {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}",
+            m.sample_id,
+            m.barcode,
+            m.templates,
+            m.perfect_matches,
+            m.one_mismatch_matches,
+            m.fraction_of_templates,
+            m.ratio_to_best,
+            m.ratio_to_mean
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    use read_structure::orientation::InstrumentWorkflow;
+    use read_structure::{ReadStructure, SegmentType};
+
+    use super::Demux;
+
+    const R1: &str = "@q1/1\nAAAAGGTTTT\n+\nABCDEFGHIJ\n@q2/1\nCCCCAAAAAA\n+\nJIHGFEDCBA\n\
+                      @q3/1\nCCCAAAAAAA\n+\nJIHGFEDCBA\n@q4/1\nTTTTAAAAAA\n+\nJIHGFEDCBA\n";
+    const R2: &str = "@q1/2\nGGGGCCCCCC\n+\n0123456789\n@q2/2\nCCCCGGGGGG\n+\n9876543210\n\
+                      @q3/2\nCCCCGGGGGG\n+\n9876543210\n@q4/2\nCCCCGGGGGG\n+\n9876543210\n";
+    const SAMPLE_SHEET: &str = "Sample_ID,barcode\ns1,AAAA\ns2,CCCC\n";
+
+    /// Returns a fresh temporary directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "read-structure-demux-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("r1.fq"), R1).unwrap();
+        fs::write(dir.join("r2.fq"), R2).unwrap();
+        fs::write(dir.join("samples.csv"), SAMPLE_SHEET).unwrap();
+        dir
+    }
+
+    fn demux(dir: &Path) -> Demux {
+        Demux {
+            inputs: vec![dir.join("r1.fq"), dir.join("r2.fq")],
+            read_structures: vec![
+                ReadStructure::from_str("4B+T").unwrap(),
+                ReadStructure::from_str("+T").unwrap(),
+            ],
+            sample_sheet: dir.join("samples.csv"),
+            output: dir.join("out"),
+            max_mismatches: 1,
+            min_mismatch_delta: 2,
+            max_no_calls: 2,
+            workflow: None,
+            kinds: vec![],
+            gzip: false,
+            compression_level: 5,
+            threads: Some(2),
+        }
+    }
+
+    #[test]
+    fn test_demux() {
+        let dir = temp_dir("samples");
+        let mut out = Vec::new();
+        demux(&dir).execute(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Assigned 3 of 4 templates to 2 samples\n");
+        let read = |name: &str| fs::read_to_string(dir.join("out").join(name)).unwrap();
+        assert_eq!(read("s1.template.1.fastq"), "@q1\nGGTTTT\n+\nEFGHIJ\n");
+        assert_eq!(read("s1.template.2.fastq"), "@q1\nGGGGCCCCCC\n+\n0123456789\n");
+        let names: Vec<String> =
+            read("s2.template.1.fastq").lines().step_by(4).map(String::from).collect();
+        assert_eq!(names, vec!["@q2", "@q3"]);
+        assert_eq!(read("unmatched.template.1.fastq"), "@q4\nAAAAAA\n+\nFEDCBA\n");
+
+        let metrics = read("demux_metrics.txt");
+        let lines: Vec<&str> = metrics.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("sample_id\tbarcode\ttemplates\tperfect_matches\t"));
+        assert_eq!(lines[2], "s2\tCCCC\t2\t1\t1\t0.500000\t1.000000\t1.333333");
+        assert_eq!(lines[3], "unmatched\tNNNN\t1\t0\t0\t0.250000\t0.500000\t0.666667");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_demux_kinds_and_errors() {
+        let dir = temp_dir("errors");
+        let mut cmd = demux(&dir);
+        cmd.kinds = vec![SegmentType::SampleBarcode];
+        cmd.max_mismatches = 0;
+        cmd.execute(&mut Vec::new()).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join("out").join(name)).unwrap();
+        assert_eq!(read("s2.sample_barcode.fastq"), "@q2\nCCCC\n+\nJIHG\n");
+        assert_eq!(read("unmatched.sample_barcode.fastq").lines().count(), 8);
+
+        // Barcodes do not fit the read structure
+        fs::write(dir.join("samples.csv"), "Sample_ID,barcode\ns1,AAAAA\n").unwrap();
+        let err = demux(&dir).execute(&mut Vec::new()).unwrap_err();
+        assert!(
+            err.to_string().starts_with("Invalid sample sheet: barcodes of length 5"),
+            "{}",
+            err
+        );
+
+        // Sample s1 with read s2.t, and sample s1.s2 with read t, would share an output
+        fs::write(dir.join("samples.csv"), "Sample_ID,barcode\ns1,AAAA\ns1.s2,CCCC\n").unwrap();
+        let mut cmd = demux(&dir);
+        cmd.read_structures = vec![
+            ReadStructure::from_str("4B+T{s2.t}").unwrap(),
+            ReadStructure::from_str("+T{t}").unwrap(),
+        ];
+        let err = cmd.execute(&mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains("more than one output written to"), "{}", err);
+        assert!(!dir.join("out").join("s1.s2.t.fastq").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_demux_workflow() {
+        let dir = temp_dir("workflow");
+        fs::write(dir.join("samples.csv"), "Sample_ID,index,index2\ns1,AAAA,CCCC\n").unwrap();
+        let mut cmd = demux(&dir);
+        cmd.read_structures = vec![
+            ReadStructure::from_str("4B+T").unwrap(),
+            ReadStructure::from_str("4B+T").unwrap(),
+        ];
+        cmd.max_mismatches = 0;
+        let mut out = Vec::new();
+        cmd.execute(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Assigned 0 of 4 templates to 1 samples\n");
+
+        // The i5 read is sequenced as GGGG, the reverse complement of the sample sheet
+        cmd.workflow = Some(InstrumentWorkflow::NovaSeqX);
+        let mut out = Vec::new();
+        cmd.execute(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Assigned 1 of 4 templates to 1 samples\n");
+        let read = |name: &str| fs::read_to_string(dir.join("out").join(name)).unwrap();
+        assert_eq!(read("s1.template.2.fastq"), "@q1\nCCCCCC\n+\n456789\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
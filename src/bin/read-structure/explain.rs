@@ -0,0 +1,160 @@
+//! The `explain` subcommand: prints a table of the segments in one or more read structures, with
+//! the label, kind, length, offset and cycles of each segment.
+
+use std::io::Write;
+use std::str::FromStr;
+
+use clap::Args;
+use read_structure::{MultiReadStructure, ReadStructureError, ANY_LENGTH_STR};
+use serde::Serialize;
+
+/// Arguments for the `explain` subcommand.
+#[derive(Debug, Args)]
+pub struct Explain {
+    /// The read structures to explain, one per read (e.g. `8M+T +T`).
+    #[arg(required = true)]
+    read_structures: Vec<String>,
+
+    /// Write the results as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+/// A description of a single segment.
+#[derive(Debug, Serialize)]
+struct SegmentRow {
+    /// The one-based index of the read the segment belongs to.
+    read: usize,
+    /// The one-based index of the segment within its read.
+    index: usize,
+    /// The segment as it appears in the read structure, without its label.
+    segment: String,
+    /// The label of the segment, if any.
+    label: Option<String>,
+    /// The name of the segment type (see [`read_structure::SegmentType::name`]).
+    kind: String,
+    /// The length of the segment, or `None` if variable length.
+    length: Option<usize>,
+    /// The zero-based offset of the segment within its read.
+    offset: usize,
+    /// The one-based first cycle of the segment.
+    start_cycle: usize,
+    /// The one-based inclusive last cycle of the segment, or `None` if variable length.
+    end_cycle: Option<usize>,
+}
+
+impl Explain {
+    /// Prints the segment table.
+    ///
+    /// # Errors
+    ///
+    /// - If any read structure could not be parsed.
+    pub fn execute<W: Write>(&self, out: &mut W) -> Result<bool, ReadStructureError> {
+        let mrs = MultiReadStructure::from_str(&self.read_structures.join(" "))?;
+        let rows: Vec<SegmentRow> = mrs
+            .iter()
+            .enumerate()
+            .flat_map(|(read_index, rs)| {
+                rs.labelled_segments().enumerate().map(move |(index, (segment, label))| {
+                    SegmentRow {
+                        read: read_index + 1,
+                        index: index + 1,
+                        segment: segment.to_string(),
+                        label: label.map(ToOwned::to_owned),
+                        kind: segment.kind.name().to_owned(),
+                        length: segment.length,
+                        offset: segment.offset(),
+                        start_cycle: segment.offset() + 1,
+                        end_cycle: segment.length.map(|len| segment.offset() + len),
+                    }
+                })
+            })
+            .collect();
+
+        if self.json {
+            serde_json::to_writer_pretty(&mut *out, &rows)
+                .map_err(|e| ReadStructureError::Io(e.into()))?;
+            writeln!(out)?;
+        } else {
+            let header = ["read", "segment", "label", "kind", "length", "offset", "cycles"];
+            let table: Vec<[String; 7]> = rows
+                .iter()
+                .map(|row| {
+                    [
+                        row.read.to_string(),
+                        row.segment.clone(),
+                        row.label.clone().unwrap_or_default(),
+                        row.kind.clone(),
+                        row.length.map_or_else(|| ANY_LENGTH_STR.to_owned(), |l| l.to_string()),
+                        row.offset.to_string(),
+                        match row.end_cycle {
+                            Some(end) => format!("{}-{}", row.start_cycle, end),
+                            None => format!("{}-", row.start_cycle),
+                        },
+                    ]
+                })
+                .collect();
+            let mut widths = header.map(str::len);
+            for cells in &table {
+                for (width, cell) in widths.iter_mut().zip(cells) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+            let header = header.map(str::to_owned);
+            for cells in std::iter::once(&header).chain(&table) {
+                let line: Vec<String> = cells
+                    .iter()
+                    .zip(widths)
+                    .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                    .collect();
+                writeln!(out, "{}", line.join("  ").trim_end())?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Explain;
+
+    fn run(read_structures: &[&str], json: bool) -> String {
+        let cmd = Explain {
+            read_structures: read_structures.iter().map(|s| (*s).to_owned()).collect(),
+            json,
+        };
+        let mut out = Vec::new();
+        assert!(cmd.execute(&mut out).unwrap());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_explain_table() {
+        let out = run(&["8M+T", "10B{i7}"], false);
+        assert_eq!(
+            out,
+            "read  segment  label  kind               length  offset  cycles\n\
+             1     8M              molecular_barcode  8       0       1-8\n\
+             1     +T              template           +       8       9-\n\
+             2     10B      i7     sample_barcode     10      0       1-10\n"
+        );
+    }
+
+    #[test]
+    fn test_explain_json() {
+        let out = run(&["8M+T"], true);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json[0]["kind"], "molecular_barcode");
+        assert!(json[0]["label"].is_null());
+        assert_eq!(json[0]["end_cycle"], 8);
+        assert_eq!(json[1]["start_cycle"], 9);
+        assert!(json[1]["length"].is_null());
+        assert!(json[1]["end_cycle"].is_null());
+    }
+
+    #[test]
+    fn test_explain_invalid() {
+        let cmd = Explain { read_structures: vec!["8X".to_owned()], json: false };
+        assert!(cmd.execute(&mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,345 @@
+//! The `extract` subcommand: splits the reads in one or more FASTQs into their segments,
+//! writing one FASTQ per segment type.
+//!
+//! Each template segment is written as its own read (e.g. `<prefix>.template.1.fastq` and
+//! `<prefix>.template.2.fastq` for paired-end reads), while all segments of any other type are
+//! concatenated into a single read (e.g. `<prefix>.molecular_barcode.fastq`).  A labelled
+//! segment is always written as its own read, named by its label (e.g. `<prefix>.i7.fastq` for
+//! `8B{i7}`).  With
+//! `--interleaved`, or when writing to standard output, the reads for each input record are
+//! instead written consecutively to a single FASTQ, in the same order.
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::Args;
+use read_structure::fastq::write_record;
+use read_structure::{
+    Extraction, MultiReadStructure, ReadStructure, ReadStructureError, SegmentType,
+};
+
+use crate::files::{compression_pool, invalid_input, FastqInputs, Output, STDIO_PATH};
+
+/// Arguments for the `extract` subcommand.
+#[derive(Debug, Args)]
+pub struct Extract {
+    /// The input FASTQs, optionally gzipped, one per read structure (`-` for standard input).
+    #[arg(short, long, required = true, num_args = 1..)]
+    inputs: Vec<PathBuf>,
+
+    /// The read structures, one per input FASTQ.
+    #[arg(short, long, required = true, num_args = 1..)]
+    read_structures: Vec<ReadStructure>,
+
+    /// The prefix of the output FASTQs, or `-` to write interleaved reads to standard output.
+    #[arg(short, long)]
+    output: String,
+
+    /// Write all reads to a single interleaved FASTQ.
+    #[arg(long)]
+    interleaved: bool,
+
+    /// The segment types to write (e.g. `T M`).  Defaults to all but skips.
+    #[arg(short, long, num_args = 1..)]
+    kinds: Vec<SegmentType>,
+
+    /// Gzip compress the output FASTQs.
+    #[arg(short = 'z', long)]
+    gzip: bool,
+
+    /// The gzip compression level.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(0..=9))]
+    compression_level: u32,
+
+    /// The number of threads to use for compression.  Defaults to the number of CPUs.
+    #[arg(short, long)]
+    threads: Option<usize>,
+}
+
+/// A read written to the output, built from one or more segments of the same kind.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct OutputRead {
+    /// The name of the read, used in output file names.
+    pub(crate) label: String,
+    /// The indices of the segments in each extraction that make up the read.
+    pub(crate) segments: Vec<usize>,
+}
+
+impl OutputRead {
+    /// Writes the read for one extraction as a FASTQ record named `name`, concatenating the bases
+    /// and qualities of its segments as sequenced.  `bases` and `quals` are scratch buffers.
+    ///
+    /// # Errors
+    ///
+    /// - If writing fails.
+    pub(crate) fn write<W: Write>(
+        &self,
+        writer: &mut W,
+        name: &[u8],
+        extraction: &Extraction<'_>,
+        bases: &mut Vec<u8>,
+        quals: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let segments = extraction.segments();
+        bases.clear();
+        quals.clear();
+        for segment in self.segments.iter().map(|&i| &segments[i]) {
+            bases.extend_from_slice(segment.bases);
+            quals.extend_from_slice(segment.quals.unwrap_or_default());
+        }
+        write_record(writer, name, bases, quals)
+    }
+}
+
+/// Returns the output reads for the given segment types: one per labelled or template segment,
+/// and one per other segment type with unlabelled segments present.
+///
+/// # Errors
+///
+/// - If two output reads have the same name, e.g. segments in different reads with the same
+///   label, or a label that is also the name of an unlabelled output read.
+pub(crate) fn output_reads(
+    mrs: &MultiReadStructure,
+    kinds: &[SegmentType],
+) -> Result<Vec<OutputRead>, ReadStructureError> {
+    let segments: Vec<(SegmentType, Option<&str>)> = mrs
+        .iter()
+        .flat_map(|rs| rs.labelled_segments().map(|(segment, label)| (segment.kind, label)))
+        .collect();
+    let mut reads = Vec::new();
+    for kind in kinds {
+        let mut templates = 0;
+        let mut unlabelled = Vec::new();
+        for (index, (_, label)) in segments.iter().enumerate().filter(|(_, (k, _))| k == kind) {
+            match label {
+                Some(label) => {
+                    reads.push(OutputRead { label: (*label).to_owned(), segments: vec![index] });
+                }
+                None if *kind == SegmentType::Template => {
+                    templates += 1;
+                    reads.push(OutputRead {
+                        label: format!("{}.{}", kind.name(), templates),
+                        segments: vec![index],
+                    });
+                }
+                None => unlabelled.push(index),
+            }
+        }
+        if !unlabelled.is_empty() {
+            reads.push(OutputRead { label: kind.name().to_owned(), segments: unlabelled });
+        }
+    }
+    let mut labels = HashSet::new();
+    if let Some(read) = reads.iter().find(|read| !labels.insert(read.label.as_str())) {
+        return Err(invalid_input(&format!("more than one output read named: {}", read.label)));
+    }
+    Ok(reads)
+}
+
+impl Extract {
+    /// Splits the input FASTQs into the output FASTQs.
+    ///
+    /// # Errors
+    ///
+    /// - If the number of inputs and read structures differ.
+    /// - If no segments have the requested types.
+    /// - If two output reads would have the same name.
+    /// - If the inputs are malformed, have differing numbers of records, or have records whose
+    ///   names differ.
+    /// - If any read is too short for its read structure.
+    /// - If reading or writing fails.
+    pub fn execute<W: Write>(&self, out: &mut W) -> Result<bool, ReadStructureError> {
+        let mrs = MultiReadStructure::new(self.read_structures.clone())?;
+        let mut inputs = FastqInputs::open(&self.inputs, &mrs)?;
+        let kinds = if self.kinds.is_empty() {
+            vec![
+                SegmentType::Template,
+                SegmentType::SampleBarcode,
+                SegmentType::MolecularBarcode,
+                SegmentType::CellularBarcode,
+            ]
+        } else {
+            self.kinds.clone()
+        };
+        let reads = output_reads(&mrs, &kinds)?;
+        if reads.is_empty() {
+            return Err(invalid_input(&format!("no segments of the requested types in: {}", mrs)));
+        }
+
+        let pool = compression_pool(self.gzip, self.threads, self.compression_level);
+        let extension = if self.gzip { "fastq.gz" } else { "fastq" };
+        let mut outputs = if self.output == STDIO_PATH {
+            vec![Output::create(self.output.as_ref(), pool.as_ref())?]
+        } else if self.interleaved {
+            let path = format!("{}.{}", self.output, extension);
+            vec![Output::create(path.as_ref(), pool.as_ref())?]
+        } else {
+            reads
+                .iter()
+                .map(|read| {
+                    let path = format!("{}.{}.{}", self.output, read.label, extension);
+                    Output::create(path.as_ref(), pool.as_ref())
+                })
+                .collect::<io::Result<Vec<_>>>()?
+        };
+
+        let (mut bases, mut quals) = (Vec::new(), Vec::new());
+        let last_output = outputs.len() - 1;
+        let count = inputs.for_each_extraction(|records, extraction| {
+            for (index, read) in reads.iter().enumerate() {
+                let output = &mut outputs[index.min(last_output)];
+                read.write(output, records[0].base_id(), extraction, &mut bases, &mut quals)?;
+            }
+            Ok(())
+        })?;
+        for output in outputs {
+            output.finish()?;
+        }
+
+        if self.output != STDIO_PATH {
+            writeln!(out, "Extracted {} records into {} reads", count, reads.len())?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    use flate2::read::MultiGzDecoder;
+    use read_structure::{ReadStructure, SegmentType};
+
+    use super::Extract;
+
+    const R1: &str = "@q1/1\nAACCGGTTTT\n+\nABCDEFGHIJ\n@q2/1\nTTGGCCAAAA\n+\nJIHGFEDCBA\n";
+    const R2: &str = "@q1/2\nGGGGCCCCCC\n+\n0123456789\n@q2/2\nCCCCGGGGGG\n+\n9876543210\n";
+
+    /// Returns a fresh temporary directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "read-structure-extract-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("r1.fq"), R1).unwrap();
+        fs::write(dir.join("r2.fq"), R2).unwrap();
+        dir
+    }
+
+    fn extract(dir: &Path, read_structures: &[&str]) -> Extract {
+        Extract {
+            inputs: vec![dir.join("r1.fq"), dir.join("r2.fq")],
+            read_structures: read_structures
+                .iter()
+                .map(|rs| ReadStructure::from_str(rs).unwrap())
+                .collect(),
+            output: dir.join("out").to_str().unwrap().to_owned(),
+            interleaved: false,
+            kinds: vec![],
+            gzip: false,
+            compression_level: 5,
+            threads: Some(2),
+        }
+    }
+
+    #[test]
+    fn test_extract_per_segment_type() {
+        let dir = temp_dir("split");
+        let mut out = Vec::new();
+        extract(&dir, &["2M2S+T", "4B+T"]).execute(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Extracted 2 records into 4 reads\n");
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(
+            read("out.template.1.fastq"),
+            "@q1\nGGTTTT\n+\nEFGHIJ\n@q2\nCCAAAA\n+\nFEDCBA\n"
+        );
+        assert_eq!(
+            read("out.template.2.fastq"),
+            "@q1\nCCCCCC\n+\n456789\n@q2\nGGGGGG\n+\n543210\n"
+        );
+        assert_eq!(read("out.sample_barcode.fastq"), "@q1\nGGGG\n+\n0123\n@q2\nCCCC\n+\n9876\n");
+        assert_eq!(read("out.molecular_barcode.fastq"), "@q1\nAA\n+\nAB\n@q2\nTT\n+\nJI\n");
+        assert!(!dir.join("out.skip.fastq").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_labelled_segments() {
+        let dir = temp_dir("labelled");
+        extract(&dir, &["2M{umi}2M+T{r1}", "4B+T"]).execute(&mut Vec::new()).unwrap();
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("out.umi.fastq"), "@q1\nAA\n+\nAB\n@q2\nTT\n+\nJI\n");
+        assert_eq!(read("out.molecular_barcode.fastq"), "@q1\nCC\n+\nCD\n@q2\nGG\n+\nHG\n");
+        assert!(read("out.r1.fastq").starts_with("@q1\nGGTTTT\n"));
+        assert!(read("out.template.1.fastq").starts_with("@q1\nCCCCCC\n"));
+        assert!(!dir.join("out.template.2.fastq").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_duplicate_labels() {
+        let dir = temp_dir("duplicate-labels");
+        for read_structures in [["2B{idx}+T", "2B{idx}+T"], ["2M+T", "2M{template.1}+T"]] {
+            let err = extract(&dir, &read_structures).execute(&mut Vec::new()).unwrap_err();
+            assert!(err.to_string().contains("more than one output read named"), "{}", err);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_interleaved_gzip() {
+        let dir = temp_dir("interleaved");
+        let mut cmd = extract(&dir, &["2M+T", "+T"]);
+        cmd.interleaved = true;
+        cmd.gzip = true;
+        cmd.kinds = vec![SegmentType::MolecularBarcode, SegmentType::Template];
+        cmd.execute(&mut Vec::new()).unwrap();
+        let mut contents = String::new();
+        let file = fs::File::open(dir.join("out.fastq.gz")).unwrap();
+        MultiGzDecoder::new(file).read_to_string(&mut contents).unwrap();
+        let names: Vec<&str> = contents.lines().step_by(4).collect();
+        assert_eq!(names, vec!["@q1", "@q1", "@q1", "@q2", "@q2", "@q2"]);
+        let bases: Vec<&str> = contents.lines().skip(1).step_by(4).collect();
+        assert_eq!(bases[..3], ["AA", "CCGGTTTT", "GGGGCCCCCC"]);
+
+        // Gzipped inputs are read transparently
+        fs::rename(dir.join("out.fastq.gz"), dir.join("r1.fq")).unwrap();
+        let mut cmd = extract(&dir, &["+T"]);
+        cmd.inputs.pop();
+        cmd.output = dir.join("again").to_str().unwrap().to_owned();
+        cmd.execute(&mut Vec::new()).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("again.template.1.fastq")).unwrap(), contents);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_errors() {
+        let dir = temp_dir("errors");
+        // Read too short for the read structure
+        assert!(extract(&dir, &["20T", "+T"]).execute(&mut Vec::new()).is_err());
+        // Number of inputs and read structures differ
+        let mut cmd = extract(&dir, &["+T", "+T"]);
+        cmd.read_structures.pop();
+        assert!(cmd.execute(&mut Vec::new()).is_err());
+        // Read names differ
+        fs::write(dir.join("r2.fq"), R2.replace("q2/2", "q3/2")).unwrap();
+        let err = extract(&dir, &["+T", "+T"]).execute(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "FASTQ read names do not match: q2/1, q3/2");
+        // Differing numbers of records
+        fs::write(dir.join("r2.fq"), &R2[..R2.len() / 2]).unwrap();
+        assert!(extract(&dir, &["+T", "+T"]).execute(&mut Vec::new()).is_err());
+        // No segments of the requested type
+        let mut cmd = extract(&dir, &["+T", "+T"]);
+        cmd.kinds = vec![SegmentType::CellularBarcode];
+        assert!(cmd.execute(&mut Vec::new()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
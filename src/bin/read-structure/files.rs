@@ -0,0 +1,202 @@
+//! Opening input and output files, transparently handling gzip and standard input/output, and
+//! reading FASTQs in lockstep.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::MultiGzDecoder;
+use read_structure::fastq::{FastqReader, FastqRecord};
+use read_structure::{Extraction, MultiReadStructure, ReadStructureError};
+
+use crate::gzip::{CompressionPool, GzWriter};
+
+/// The path that refers to standard input or standard output.
+pub const STDIO_PATH: &str = "-";
+
+/// The magic bytes at the start of every gzip member.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The size of the buffers used for reading and writing.
+const BUFFER_SIZE: usize = 1 << 16;
+
+/// Opens the file at `path` (or standard input if `-`) for reading, decompressing it if it
+/// starts with the gzip magic bytes.
+///
+/// # Errors
+///
+/// - If the file could not be opened or read.
+pub fn open_input(path: &Path) -> io::Result<Box<dyn BufRead + Send>> {
+    let raw: Box<dyn io::Read + Send> = if path.as_os_str() == STDIO_PATH {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let mut reader = BufReader::with_capacity(BUFFER_SIZE, raw);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::with_capacity(BUFFER_SIZE, MultiGzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Returns a compression pool with the given number of threads (defaulting to the number of
+/// CPUs) and compression level if `gzip` is true, otherwise `None`.  The pool must outlive the
+/// outputs compressing on it.
+pub fn compression_pool(gzip: bool, threads: Option<usize>, level: u32) -> Option<CompressionPool> {
+    gzip.then(|| {
+        let threads = threads.unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        });
+        CompressionPool::new(threads, level)
+    })
+}
+
+/// Returns an error for invalid input.
+pub fn invalid_input(message: &str) -> ReadStructureError {
+    ReadStructureError::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+/// FASTQs read in lockstep, one per read structure, with a record from each per template.
+pub struct FastqInputs {
+    /// The read structures, one per FASTQ.
+    read_structures: MultiReadStructure,
+    /// The reader for each FASTQ.
+    readers: Vec<FastqReader<Box<dyn BufRead + Send>>>,
+    /// The current record from each FASTQ.
+    records: Vec<FastqRecord>,
+}
+
+impl FastqInputs {
+    /// Opens the FASTQs at `paths`, optionally gzipped, one per read structure.
+    ///
+    /// # Errors
+    ///
+    /// - If the number of paths and read structures differ.
+    /// - If any file could not be opened.
+    pub fn open(
+        paths: &[PathBuf],
+        read_structures: &MultiReadStructure,
+    ) -> Result<Self, ReadStructureError> {
+        if paths.len() != read_structures.number_of_reads() {
+            return Err(ReadStructureError::MismatchingNumberOfReads {
+                expected: read_structures.number_of_reads(),
+                actual: paths.len(),
+            });
+        }
+        let readers = paths
+            .iter()
+            .map(|path| open_input(path).map(FastqReader::new))
+            .collect::<io::Result<Vec<_>>>()?;
+        let records = vec![FastqRecord::default(); readers.len()];
+        Ok(FastqInputs { read_structures: read_structures.clone(), readers, records })
+    }
+
+    /// Extracts the segments of each template with the read structures, calling `f` with the
+    /// records and the extraction.  Returns the number of templates.
+    ///
+    /// # Errors
+    ///
+    /// - If the inputs are malformed, have differing numbers of records, or have records whose
+    ///   names differ.
+    /// - If any read is too short for its read structure.
+    /// - If `f` fails.
+    pub fn for_each_extraction<F>(&mut self, mut f: F) -> Result<u64, ReadStructureError>
+    where
+        F: FnMut(&[FastqRecord], &Extraction<'_>) -> Result<(), ReadStructureError>,
+    {
+        let mut count = 0;
+        while self.read_records()? {
+            let pairs: Vec<(&[u8], &[u8])> =
+                self.records.iter().map(|r| (r.bases.as_slice(), r.quals.as_slice())).collect();
+            let extraction = self.read_structures.extract_with_quals(&pairs)?;
+            f(&self.records, &extraction)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Reads the next record from every reader.  Returns `false` once all readers are exhausted.
+    ///
+    /// # Errors
+    ///
+    /// - If reading fails.
+    /// - If some readers are exhausted before others.
+    /// - If the records' names differ.
+    fn read_records(&mut self) -> Result<bool, ReadStructureError> {
+        let mut found = Vec::with_capacity(self.readers.len());
+        for (reader, record) in self.readers.iter_mut().zip(self.records.iter_mut()) {
+            found.push(reader.read_record(record)?);
+        }
+        if found.iter().all(|f| !f) {
+            return Ok(false);
+        }
+        if found.iter().any(|f| !f) {
+            return Err(invalid_input("input FASTQs have differing numbers of records"));
+        }
+        let records = &self.records;
+        if let Some(other) = records.iter().find(|r| !r.same_id(&records[0])) {
+            return Err(ReadStructureError::FastqReadNamesMismatch {
+                first: String::from_utf8_lossy(records[0].id()).into_owned(),
+                other: String::from_utf8_lossy(other.id()).into_owned(),
+            });
+        }
+        Ok(true)
+    }
+}
+
+/// An output file (or standard output), optionally gzip compressed.
+pub enum Output {
+    /// Uncompressed output.
+    Plain(BufWriter<Box<dyn Write + Send>>),
+    /// Gzip compressed output.
+    Gzip(GzWriter),
+}
+
+impl Output {
+    /// Creates the file at `path` (or standard output if `-`), gzip compressing on `pool` if
+    /// given.
+    ///
+    /// # Errors
+    ///
+    /// - If the file could not be created.
+    pub fn create(path: &Path, pool: Option<&CompressionPool>) -> io::Result<Self> {
+        let raw: Box<dyn Write + Send> = if path.as_os_str() == STDIO_PATH {
+            Box::new(io::stdout())
+        } else {
+            Box::new(File::create(path)?)
+        };
+        Ok(match pool {
+            Some(pool) => Output::Gzip(pool.writer(raw)),
+            None => Output::Plain(BufWriter::with_capacity(BUFFER_SIZE, raw)),
+        })
+    }
+
+    /// Writes any remaining output.
+    ///
+    /// # Errors
+    ///
+    /// - If writing fails.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut writer) => writer.flush(),
+            Output::Gzip(writer) => writer.finish(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(writer) => writer.write(buf),
+            Output::Gzip(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(writer) => writer.flush(),
+            Output::Gzip(writer) => writer.flush(),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Multithreaded gzip compression.
+//!
+//! Output is split into blocks that are compressed independently on a [`CompressionPool`] shared
+//! by all outputs, then written in order as consecutive gzip members.  Standard gzip readers
+//! decompress concatenated members as a single stream.
+
+use std::io::{self, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+/// The number of uncompressed bytes in each independently compressed block.
+const BLOCK_SIZE: usize = 1 << 20;
+
+/// A block to compress, and where to send the compressed result.
+type Job = (Vec<u8>, SyncSender<io::Result<Vec<u8>>>);
+
+/// A pool of threads compressing blocks for one or more [`GzWriter`]s.
+#[derive(Debug)]
+pub struct CompressionPool {
+    /// Sends blocks to the worker threads; dropped to shut the workers down.
+    jobs: Option<SyncSender<Job>>,
+    /// The worker threads.
+    workers: Vec<JoinHandle<()>>,
+    /// The number of worker threads.
+    threads: usize,
+}
+
+impl CompressionPool {
+    /// Builds a new [`CompressionPool`] with the given number of threads (at least one) and
+    /// compression level (0-9).
+    pub fn new(threads: usize, level: u32) -> Self {
+        let threads = threads.max(1);
+        let (jobs, receiver) = sync_channel::<Job>(threads * 2);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    let job = receiver.lock().expect("compression pool poisoned").recv();
+                    let Ok((block, result)) = job else { break };
+                    // The writer may have gone away after an error; nothing left to do then.
+                    let _ = result.send(compress(&block, level));
+                })
+            })
+            .collect();
+        CompressionPool { jobs: Some(jobs), workers, threads }
+    }
+
+    /// Returns a new [`GzWriter`] compressing to `inner` on this pool.  All writers must be
+    /// finished or dropped before the pool is dropped.
+    pub fn writer<W: Write + Send + 'static>(&self, inner: W) -> GzWriter {
+        let (blocks, pending) = sync_channel::<Receiver<io::Result<Vec<u8>>>>(self.threads * 2);
+        let io_thread = thread::spawn(move || write_blocks(inner, &pending));
+        GzWriter {
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            jobs: self.jobs.clone().expect("compression pool shut down"),
+            blocks: Some(blocks),
+            io_thread: Some(io_thread),
+            blocks_sent: 0,
+        }
+    }
+}
+
+impl Drop for CompressionPool {
+    fn drop(&mut self) {
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Compresses a block as a single gzip member.
+fn compress(block: &[u8], level: u32) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(block.len() / 2), Compression::new(level));
+    encoder.write_all(block)?;
+    encoder.finish()
+}
+
+/// Writes compressed blocks to `inner` in the order they were submitted.
+fn write_blocks<W: Write>(
+    mut inner: W,
+    pending: &Receiver<Receiver<io::Result<Vec<u8>>>>,
+) -> io::Result<()> {
+    for block in pending {
+        let compressed = block.recv().map_err(|_| broken_pipe("compression thread exited"))??;
+        inner.write_all(&compressed)?;
+    }
+    inner.flush()
+}
+
+/// Returns an error for a compression or output thread that exited unexpectedly.
+fn broken_pipe(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, message)
+}
+
+/// A writer that gzip compresses its output on a [`CompressionPool`].
+///
+/// Call [`GzWriter::finish`] to write any remaining output and observe errors; dropping the
+/// writer finishes it but ignores errors.
+#[derive(Debug)]
+pub struct GzWriter {
+    /// The uncompressed bytes of the current block.
+    buffer: Vec<u8>,
+    /// Sends blocks to the pool for compression.
+    jobs: SyncSender<Job>,
+    /// Sends compressed results, in order, to the output thread.
+    blocks: Option<SyncSender<Receiver<io::Result<Vec<u8>>>>>,
+    /// The thread writing compressed blocks to the underlying writer.
+    io_thread: Option<JoinHandle<io::Result<()>>>,
+    /// The number of blocks sent for compression.
+    blocks_sent: usize,
+}
+
+impl GzWriter {
+    /// Compresses and writes any buffered output, then waits for all output to be written.
+    ///
+    /// # Errors
+    ///
+    /// - If compressing or writing any block failed.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.close()
+    }
+
+    /// Sends the buffered output to be compressed.
+    fn send_block(&mut self) -> io::Result<()> {
+        let block = std::mem::replace(&mut self.buffer, Vec::with_capacity(BLOCK_SIZE));
+        let (result, compressed) = sync_channel(1);
+        self.jobs.send((block, result)).map_err(|_| broken_pipe("compression pool shut down"))?;
+        let sent = self.blocks.as_ref().map(|blocks| blocks.send(compressed).is_ok());
+        if sent != Some(true) {
+            // The output thread exited early; surface its error.
+            return Err(self.join().err().unwrap_or_else(|| broken_pipe("output thread exited")));
+        }
+        self.blocks_sent += 1;
+        Ok(())
+    }
+
+    /// Sends any remaining output and waits for the output thread.  Always writes at least one
+    /// gzip member so that empty outputs are still valid gzip.
+    fn close(&mut self) -> io::Result<()> {
+        if self.io_thread.is_none() {
+            return Ok(());
+        }
+        let sent = if !self.buffer.is_empty() || self.blocks_sent == 0 {
+            self.send_block()
+        } else {
+            Ok(())
+        };
+        let joined = self.join();
+        sent.and(joined)
+    }
+
+    /// Closes the channel to the output thread and waits for it to finish.
+    fn join(&mut self) -> io::Result<()> {
+        self.blocks.take();
+        match self.io_thread.take() {
+            Some(io_thread) => {
+                io_thread.join().unwrap_or_else(|_| Err(broken_pipe("output thread panicked")))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Write for GzWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == BLOCK_SIZE {
+            self.send_block()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for GzWriter {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    use flate2::read::MultiGzDecoder;
+
+    use super::{CompressionPool, BLOCK_SIZE};
+
+    /// A writer whose contents can be inspected after it is moved into a [`super::GzWriter`].
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn decompress(shared: &Shared) -> Vec<u8> {
+        let compressed = shared.0.lock().unwrap().clone();
+        let mut decompressed = Vec::new();
+        MultiGzDecoder::new(&compressed[..]).read_to_end(&mut decompressed).unwrap();
+        decompressed
+    }
+
+    #[test]
+    fn test_round_trip_multiple_blocks_and_writers() {
+        let pool = CompressionPool::new(3, 1);
+        let data: Vec<u8> = (0..(BLOCK_SIZE * 3 + 17)).map(|i| b"ACGT"[i % 7 % 4]).collect();
+        let (first, second) = (Shared::default(), Shared::default());
+        let mut first_writer = pool.writer(first.clone());
+        let mut second_writer = pool.writer(second.clone());
+        for chunk in data.chunks(1000) {
+            first_writer.write_all(chunk).unwrap();
+            second_writer.write_all(&chunk[..1]).unwrap();
+        }
+        first_writer.finish().unwrap();
+        second_writer.finish().unwrap();
+        assert_eq!(decompress(&first), data);
+        assert_eq!(decompress(&second), data.chunks(1000).map(|c| c[0]).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_empty_output_is_valid_gzip() {
+        let pool = CompressionPool::new(1, 6);
+        let out = Shared::default();
+        pool.writer(out.clone()).finish().unwrap();
+        assert!(!out.0.lock().unwrap().is_empty());
+        assert!(decompress(&out).is_empty());
+    }
+}
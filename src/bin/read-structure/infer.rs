@@ -0,0 +1,268 @@
+//! The `infer` subcommand: proposes read structures for FASTQs of unknown structure.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use read_structure::fastq::FastqReader;
+use read_structure::infer::{
+    infer, BarcodeList, Candidate, InferenceOptions, KnownSequence, ReadProfile,
+    DEFAULT_MAX_CANDIDATES, DEFAULT_MAX_READS,
+};
+use read_structure::{ReadStructureError, SegmentType};
+use serde::Serialize;
+
+use crate::files::open_input;
+
+/// Arguments for the `infer` subcommand.
+#[derive(Debug, Args)]
+pub struct Infer {
+    /// The input FASTQs, optionally gzipped (`-` for standard input).
+    #[arg(short, long, required = true, num_args = 1..)]
+    inputs: Vec<PathBuf>,
+
+    /// The number of reads to sample from the start of each FASTQ.
+    #[arg(long, default_value_t = DEFAULT_MAX_READS)]
+    max_reads: usize,
+
+    /// Files of expected cell barcodes (e.g. a whitelist), one per line, optionally gzipped.
+    #[arg(long, num_args = 1..)]
+    cell_barcodes: Vec<PathBuf>,
+
+    /// Files of expected sample barcodes, one per line, optionally gzipped.
+    #[arg(long, num_args = 1..)]
+    sample_barcodes: Vec<PathBuf>,
+
+    /// Additional adapter or linker sequences to search for, as `name=sequence`.
+    #[arg(long, num_args = 1.., value_parser = parse_known_sequence)]
+    known_sequences: Vec<KnownSequence>,
+
+    /// Do not propose the read structures of known chemistries.
+    #[arg(long)]
+    no_chemistries: bool,
+
+    /// The maximum number of candidates to report per FASTQ.
+    #[arg(short = 'n', long, default_value_t = DEFAULT_MAX_CANDIDATES)]
+    candidates: usize,
+
+    /// Write the results as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+/// Parses a known sequence given as `name=sequence`.
+fn parse_known_sequence(value: &str) -> Result<KnownSequence, String> {
+    match value.split_once('=') {
+        Some((name, sequence)) if !name.is_empty() && !sequence.is_empty() => {
+            Ok(KnownSequence::new(name, sequence.as_bytes()))
+        }
+        _ => Err(format!("expected name=sequence, found: {}", value)),
+    }
+}
+
+/// The candidates proposed for one FASTQ.
+#[derive(Debug, Serialize)]
+struct InputCandidates {
+    /// The FASTQ.
+    input: String,
+    /// The number of reads sampled.
+    reads: usize,
+    /// The length of the shortest read.
+    min_length: usize,
+    /// The length of the longest read.
+    max_length: usize,
+    /// The candidates, most confident first.
+    candidates: Vec<CandidateRow>,
+}
+
+/// A single candidate.
+#[derive(Debug, Serialize)]
+struct CandidateRow {
+    /// The read structure.
+    read_structure: String,
+    /// The confidence in the read structure.
+    confidence: f64,
+    /// The evidence for each segment.
+    segments: Vec<SegmentRow>,
+}
+
+/// The evidence for a single segment.
+#[derive(Debug, Serialize)]
+struct SegmentRow {
+    /// The segment.
+    segment: String,
+    /// The confidence in the segment.
+    confidence: f64,
+    /// The evidence for the segment.
+    evidence: Vec<String>,
+}
+
+impl From<Candidate> for CandidateRow {
+    fn from(candidate: Candidate) -> Self {
+        CandidateRow {
+            read_structure: candidate.read_structure.to_string(),
+            confidence: candidate.confidence,
+            segments: candidate
+                .segments
+                .into_iter()
+                .map(|s| SegmentRow {
+                    segment: s.segment.to_string(),
+                    confidence: s.confidence,
+                    evidence: s.evidence,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Reads a barcode list, named after its file.
+fn read_barcode_list(path: &Path, kind: SegmentType) -> Result<BarcodeList, ReadStructureError> {
+    let name = path
+        .file_name()
+        .map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned());
+    BarcodeList::from_reader(&name, kind, open_input(path)?)
+}
+
+impl Infer {
+    /// Samples reads from each FASTQ and reports the candidate read structures.
+    ///
+    /// # Errors
+    ///
+    /// - If any FASTQ or barcode list could not be read.
+    /// - If any FASTQ has no reads.
+    pub fn execute<W: Write>(&self, out: &mut W) -> Result<bool, ReadStructureError> {
+        let mut options = InferenceOptions {
+            chemistries: !self.no_chemistries,
+            max_candidates: self.candidates,
+            ..InferenceOptions::default()
+        };
+        options.known_sequences.extend(self.known_sequences.iter().cloned());
+        for path in &self.cell_barcodes {
+            options.barcode_lists.push(read_barcode_list(path, SegmentType::CellularBarcode)?);
+        }
+        for path in &self.sample_barcodes {
+            options.barcode_lists.push(read_barcode_list(path, SegmentType::SampleBarcode)?);
+        }
+
+        let mut results = Vec::with_capacity(self.inputs.len());
+        for path in &self.inputs {
+            let reads = FastqReader::new(open_input(path)?)
+                .take(self.max_reads)
+                .map(|r| r.map(|r| r.bases))
+                .collect::<Result<Vec<_>, _>>()?;
+            let profile = ReadProfile::from_reads(reads, self.max_reads);
+            let candidates = infer(&profile, &options)?;
+            results.push(InputCandidates {
+                input: path.display().to_string(),
+                reads: profile.number_of_reads(),
+                min_length: profile.min_length(),
+                max_length: profile.max_length(),
+                candidates: candidates.into_iter().map(CandidateRow::from).collect(),
+            });
+        }
+
+        if self.json {
+            serde_json::to_writer_pretty(&mut *out, &results)
+                .map_err(|e| ReadStructureError::Io(e.into()))?;
+            writeln!(out)?;
+        } else {
+            for result in &results {
+                writeln!(
+                    out,
+                    "{}: {} reads of {} cycles",
+                    result.input,
+                    result.reads,
+                    if result.min_length == result.max_length {
+                        result.min_length.to_string()
+                    } else {
+                        format!("{}-{}", result.min_length, result.max_length)
+                    }
+                )?;
+                for (rank, candidate) in result.candidates.iter().enumerate() {
+                    writeln!(
+                        out,
+                        "  {}. {} (confidence {:.2})",
+                        rank + 1,
+                        candidate.read_structure,
+                        candidate.confidence
+                    )?;
+                    for segment in &candidate.segments {
+                        writeln!(
+                            out,
+                            "       {:<6} {:.2}  {}",
+                            segment.segment,
+                            segment.confidence,
+                            segment.evidence.join("; ")
+                        )?;
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{parse_known_sequence, Infer};
+
+    fn infer(dir: &Path, json: bool) -> Infer {
+        Infer {
+            inputs: vec![dir.join("r1.fq")],
+            max_reads: 1000,
+            cell_barcodes: vec![],
+            sample_barcodes: vec![dir.join("barcodes.txt")],
+            known_sequences: vec![parse_known_sequence("linker=GATTACA").unwrap()],
+            no_chemistries: true,
+            candidates: 2,
+            json,
+        }
+    }
+
+    #[test]
+    fn test_infer_command() {
+        let dir = std::env::temp_dir().join(format!("read-structure-infer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let barcodes = ["ACGTACGT", "TTGCAAGC", "GACTTCAG", "CATGGTCA"];
+        let mut fastq = String::new();
+        for i in 0..200 {
+            let template: String = (0..20u64)
+                .map(|j| {
+                    let hash = (i as u64 * 31 + j).wrapping_mul(2_654_435_761) >> 16;
+                    ["A", "C", "G", "T"][(hash % 4) as usize]
+                })
+                .collect();
+            let qualities = "I".repeat(35);
+            fastq +=
+                &format!("@q{}\n{}GATTACA{}\n+\n{}\n", i, barcodes[i % 4], template, qualities);
+        }
+        fs::write(dir.join("r1.fq"), fastq).unwrap();
+        fs::write(dir.join("barcodes.txt"), barcodes.join("\n")).unwrap();
+
+        let mut out = Vec::new();
+        assert!(infer(&dir, false).execute(&mut out).unwrap());
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].ends_with("r1.fq: 200 reads of 35 cycles"), "{}", out);
+        assert!(lines[1].starts_with("  1. 8B7S+T (confidence "), "{}", out);
+        assert!(lines[2].contains("cycles 1-8: 100% of reads match barcode list 'barcodes.txt'"));
+        assert!(lines[3].contains("cycles 9-15: 100% of reads match linker"));
+
+        let mut out = Vec::new();
+        infer(&dir, true).execute(&mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json[0]["candidates"][0]["read_structure"], "8B7S+T");
+        assert_eq!(json[0]["candidates"][0]["segments"][1]["segment"], "7S");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse_known_sequence() {
+        assert_eq!(parse_known_sequence("tso=ttt").unwrap().sequence, b"TTT");
+        assert!(parse_known_sequence("tso").is_err());
+        assert!(parse_known_sequence("=ACGT").is_err());
+    }
+}
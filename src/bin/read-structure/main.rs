@@ -0,0 +1,72 @@
+//! The `read-structure` command line tool.
+//!
+//! Built with the `cli` feature, it exposes the library to users who do not write Rust.  Each
+//! subcommand lives in its own module and writes its results to standard output.
+
+use std::io::Write;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand};
+use read_structure::ReadStructureError;
+
+mod convert;
+mod demux;
+mod explain;
+mod extract;
+mod files;
+mod gzip;
+mod infer;
+mod validate;
+
+/// Work with read structures from the command line.
+#[derive(Debug, Parser)]
+#[command(name = "read-structure", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// The subcommands of the `read-structure` tool.
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Validate read structures, highlighting the position of any parse errors.
+    Validate(validate::Validate),
+    /// Print a table of the segments in one or more read structures.
+    Explain(explain::Explain),
+    /// Split FASTQs into one FASTQ per segment type.
+    Extract(extract::Extract),
+    /// Propose read structures for FASTQs of unknown structure.
+    Infer(infer::Infer),
+    /// Convert read structures to and from the notations of other tools.
+    Convert(convert::Convert),
+    /// Assign reads to samples by their sample barcodes, writing per-sample FASTQs and metrics.
+    Demux(demux::Demux),
+}
+
+impl Command {
+    /// Runs the subcommand, writing results to `out`.  Returns `Ok(false)` if the subcommand
+    /// ran but found problems with its input.
+    fn execute<W: Write>(&self, out: &mut W) -> Result<bool, ReadStructureError> {
+        match self {
+            Command::Validate(cmd) => cmd.execute(out),
+            Command::Explain(cmd) => cmd.execute(out),
+            Command::Extract(cmd) => cmd.execute(out),
+            Command::Infer(cmd) => cmd.execute(out),
+            Command::Convert(cmd) => cmd.execute(out),
+            Command::Demux(cmd) => cmd.execute(out),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // Not locked up front: `extract` may write to standard output from another thread.
+    match cli.command.execute(&mut std::io::stdout()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! The `validate` subcommand: parses each read structure and reports any errors, with the
+//! erroneous portion highlighted.
+
+use std::io::Write;
+use std::str::FromStr;
+
+use clap::Args;
+use read_structure::{ReadStructure, ReadStructureError};
+use serde::Serialize;
+
+/// Arguments for the `validate` subcommand.
+#[derive(Debug, Args)]
+pub struct Validate {
+    /// The read structures to validate (e.g. `76T8B8B76T`).
+    #[arg(required = true)]
+    read_structures: Vec<String>,
+
+    /// Write the results as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+/// The result of validating a single read structure.
+#[derive(Debug, Serialize)]
+struct Validation {
+    /// The read structure as given.
+    input: String,
+    /// The read structure as parsed, if valid.
+    read_structure: Option<String>,
+    /// The error message, if invalid.
+    error: Option<String>,
+    /// The normalized (upper-cased, whitespace removed) read structure the error positions
+    /// refer to, if the error identifies an erroneous portion.
+    normalized: Option<String>,
+    /// The zero-based start of the erroneous portion, if any.
+    error_start: Option<usize>,
+    /// The zero-based exclusive end of the erroneous portion, if any.
+    error_end: Option<usize>,
+}
+
+impl Validation {
+    /// Validates a single read structure.
+    fn new(input: &str) -> Self {
+        let mut validation = Validation {
+            input: input.to_owned(),
+            read_structure: None,
+            error: None,
+            normalized: None,
+            error_start: None,
+            error_end: None,
+        };
+        match ReadStructure::from_str(input) {
+            Ok(rs) => validation.read_structure = Some(rs.to_string()),
+            Err(e) => {
+                if let Some(parts) = e.error_message_parts() {
+                    let start = parts.prefix().chars().count();
+                    let end = start + parts.error().chars().count();
+                    validation.normalized =
+                        Some(format!("{}{}{}", parts.prefix(), parts.error(), parts.suffix()));
+                    validation.error_start = Some(start);
+                    validation.error_end = Some(end);
+                }
+                validation.error = Some(e.to_string());
+            }
+        }
+        validation
+    }
+
+    /// Returns true if the read structure was valid.
+    fn is_valid(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl Validate {
+    /// Validates the read structures, returning `Ok(false)` if any were invalid.
+    pub fn execute<W: Write>(&self, out: &mut W) -> Result<bool, ReadStructureError> {
+        let validations: Vec<Validation> =
+            self.read_structures.iter().map(|rs| Validation::new(rs)).collect();
+        if self.json {
+            serde_json::to_writer_pretty(&mut *out, &validations)
+                .map_err(|e| ReadStructureError::Io(e.into()))?;
+            writeln!(out)?;
+        } else {
+            for validation in &validations {
+                match (&validation.error, &validation.normalized) {
+                    (None, _) => writeln!(out, "{}: valid", validation.input)?,
+                    (Some(error), normalized) => {
+                        writeln!(out, "{}: invalid: {}", validation.input, error)?;
+                        if let (Some(normalized), Some(start), Some(end)) =
+                            (normalized, validation.error_start, validation.error_end)
+                        {
+                            writeln!(out, "    {}", normalized)?;
+                            writeln!(out, "    {}{}", " ".repeat(start), "^".repeat(end - start))?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(validations.iter().all(Validation::is_valid))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Validate;
+
+    fn run(read_structures: &[&str], json: bool) -> (bool, String) {
+        let cmd = Validate {
+            read_structures: read_structures.iter().map(|s| (*s).to_owned()).collect(),
+            json,
+        };
+        let mut out = Vec::new();
+        let ok = cmd.execute(&mut out).unwrap();
+        (ok, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_validate_valid() {
+        let (ok, out) = run(&["76T8B8B76T", "8m+t"], false);
+        assert!(ok);
+        assert_eq!(out, "76T8B8B76T: valid\n8m+t: valid\n");
+    }
+
+    #[test]
+    fn test_validate_highlights_error() {
+        let (ok, out) = run(&["76T", "23t2TT23T"], false);
+        assert!(!ok);
+        assert_eq!(
+            out,
+            "76T: valid\n\
+             23t2TT23T: invalid: Read structure missing length information: 23T2T[T]23T\n    \
+             23T2TT23T\n    \
+             \x20    ^\n"
+        );
+    }
+
+    #[test]
+    fn test_validate_json() {
+        let (ok, out) = run(&["10T", "10X"], true);
+        assert!(!ok);
+        let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(json[0]["read_structure"], "10T");
+        assert!(json[0]["error"].is_null());
+        assert_eq!(json[1]["error_start"], 0);
+        assert_eq!(json[1]["error_end"], 3);
+        assert_eq!(json[1]["normalized"], "10X");
+    }
+}
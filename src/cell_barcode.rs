@@ -0,0 +1,513 @@
+//! Cell Barcode Correction
+//!
+//! Type [`Whitelist`] holds the expected cell barcodes of an assay (e.g. the 10x Genomics
+//! `3M-february-2018.txt.gz` list of ~6.8 million barcodes), each encoded as a 2-bit packed
+//! `u64` in a sorted vector, so that whitelists of millions of barcodes need only eight bytes
+//! per barcode.
+//!
+//! Type [`BarcodeCorrector`] corrects observed cell barcodes against a whitelist in the manner
+//! of 10x Genomics' Cell Ranger:
+//!
+//! - a barcode on the whitelist is kept as is;
+//! - otherwise, each whitelisted barcode one substitution away is weighted by its prior
+//!   abundance (the number of reads observed with exactly that barcode, plus a pseudocount) and
+//!   the probability of a sequencing error at the substituted base (from its quality); and
+//! - the most likely neighbour is returned if its posterior probability (its weight divided by
+//!   the sum of all neighbours' weights) is at least [`CorrectionOptions::min_posterior`].
+//!
+//! Prior abundances are accumulated with [`BarcodeCorrector::observe`], typically in a first
+//! pass over (a sample of) the reads.  Qualities are Phred+33 encoded, as in FASTQ.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::ReadStructure;
+//! use read_structure::cell_barcode::{BarcodeCorrector, CorrectionOptions, Whitelist};
+//!
+//! let whitelist = Whitelist::from_reader(&b"AAAACCCC\nAAAAGGGG\nTTTTTTTT\n"[..]).unwrap();
+//! let mut corrector = BarcodeCorrector::new(whitelist, CorrectionOptions::default());
+//! corrector.observe(b"AAAACCCC");
+//!
+//! let rs = ReadStructure::from_str("4C2S4C+T").unwrap();
+//! let extraction = rs.extract_with_quals(b"AAAAGGACCCACGT", b"IIIIII#IIIIIII").unwrap();
+//! let correction = corrector.correct_extraction(&extraction).unwrap().unwrap();
+//! assert_eq!(correction.barcode, b"AAAACCCC");
+//! assert!(correction.corrected);
+//! assert!(correction.posterior > 0.975);
+//! ```
+
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::extraction::Extraction;
+use crate::ReadStructureError;
+
+/// The longest barcode that can be 2-bit encoded in a `u64`.
+pub const MAX_BARCODE_LENGTH: usize = 32;
+
+/// The default minimum posterior probability to correct a barcode, as used by Cell Ranger.
+pub const DEFAULT_MIN_POSTERIOR: f64 = 0.975;
+
+/// The default pseudocount added to the observed count of every whitelisted barcode.
+pub const DEFAULT_PSEUDOCOUNT: f64 = 1.0;
+
+/// The bases in the order of their 2-bit encoding.
+const BASES: [u8; 4] = *b"ACGT";
+
+/// Returns the 2-bit encoding of a base, or `None` if it is not `A`, `C`, `G` or `T`.
+fn encode_base(base: u8) -> Option<u64> {
+    match base.to_ascii_uppercase() {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    }
+}
+
+/// Returns the 2-bit packed encoding of a barcode, with the first base in the most significant
+/// bits, or `None` if the barcode is longer than [`MAX_BARCODE_LENGTH`] or contains bases other
+/// than `A`, `C`, `G` or `T`.
+pub fn encode(barcode: &[u8]) -> Option<u64> {
+    if barcode.len() > MAX_BARCODE_LENGTH {
+        return None;
+    }
+    barcode.iter().try_fold(0, |code, &base| Some((code << 2) | encode_base(base)?))
+}
+
+/// Returns the barcode of the given length with the given 2-bit packed encoding.
+pub fn decode(code: u64, length: usize) -> Vec<u8> {
+    (0..length).rev().map(|i| BASES[((code >> (2 * i)) & 3) as usize]).collect()
+}
+
+/// A list of expected cell barcodes, all of the same length.  See
+/// [the module level documentation](self) for more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Whitelist {
+    /// The 2-bit encoded barcodes, sorted and without duplicates.
+    codes: Vec<u64>,
+    /// The length of the barcodes.
+    barcode_length: usize,
+}
+
+impl Whitelist {
+    /// Builds a new [`Whitelist`].  Duplicate barcodes are ignored.
+    ///
+    /// # Errors
+    ///
+    /// - If no barcodes are given.
+    /// - If any barcode is longer than [`MAX_BARCODE_LENGTH`], contains bases other than `A`,
+    ///   `C`, `G` or `T`, or differs in length from the others.
+    pub fn new<'a, I: IntoIterator<Item = &'a [u8]>>(
+        barcodes: I,
+    ) -> Result<Self, ReadStructureError> {
+        let mut whitelist = Whitelist { codes: Vec::new(), barcode_length: 0 };
+        for barcode in barcodes {
+            whitelist.push(barcode)?;
+        }
+        whitelist.finish()
+    }
+
+    /// Reads a [`Whitelist`] with one barcode per line.  Only the first whitespace delimited
+    /// field of each line is used, and blank lines and lines starting with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// - If reading fails.
+    /// - If the barcodes are invalid (see [`Whitelist::new`]).
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, ReadStructureError> {
+        let mut whitelist = Whitelist { codes: Vec::new(), barcode_length: 0 };
+        for line in reader.lines() {
+            let line = line?;
+            match line.split_whitespace().next() {
+                Some(barcode) if !barcode.starts_with('#') => whitelist.push(barcode.as_bytes())?,
+                _ => (),
+            }
+        }
+        whitelist.finish()
+    }
+
+    /// Reads a [`Whitelist`] from a file with one barcode per line (see
+    /// [`Whitelist::from_reader`]).  Gzip compressed files are decompressed when the `gzip`
+    /// feature is enabled.
+    ///
+    /// # Errors
+    ///
+    /// - If the file could not be read, or is gzip compressed and the `gzip` feature is not
+    ///   enabled.
+    /// - If the barcodes are invalid (see [`Whitelist::new`]).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ReadStructureError> {
+        let mut reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        if !reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            return Whitelist::from_reader(reader);
+        }
+        #[cfg(feature = "gzip")]
+        {
+            Whitelist::from_reader(std::io::BufReader::new(flate2::bufread::MultiGzDecoder::new(
+                reader,
+            )))
+        }
+        #[cfg(not(feature = "gzip"))]
+        {
+            Err(ReadStructureError::WhitelistInvalid(
+                "reading gzip compressed whitelists requires the `gzip` feature".to_owned(),
+            ))
+        }
+    }
+
+    /// Encodes and adds a barcode while building the whitelist.
+    fn push(&mut self, barcode: &[u8]) -> Result<(), ReadStructureError> {
+        let invalid = |message: &str| {
+            ReadStructureError::WhitelistInvalid(format!(
+                "{}: {}",
+                message,
+                String::from_utf8_lossy(barcode)
+            ))
+        };
+        if self.codes.is_empty() {
+            self.barcode_length = barcode.len();
+        } else if barcode.len() != self.barcode_length {
+            return Err(invalid("barcodes differ in length"));
+        }
+        self.codes.push(encode(barcode).ok_or_else(|| invalid("invalid barcode"))?);
+        Ok(())
+    }
+
+    /// Sorts and removes duplicate barcodes once all have been added.
+    fn finish(mut self) -> Result<Self, ReadStructureError> {
+        if self.codes.is_empty() || self.barcode_length == 0 {
+            return Err(ReadStructureError::WhitelistInvalid("no barcodes".to_owned()));
+        }
+        self.codes.sort_unstable();
+        self.codes.dedup();
+        self.codes.shrink_to_fit();
+        Ok(self)
+    }
+
+    /// Returns the number of barcodes.
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    /// Returns true if there are no barcodes, which [`Whitelist::new`] does not allow.
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Returns the length of the barcodes.
+    pub fn barcode_length(&self) -> usize {
+        self.barcode_length
+    }
+
+    /// Returns the index of the barcode in the whitelist (in sorted order), if present.
+    pub fn index_of(&self, barcode: &[u8]) -> Option<usize> {
+        if barcode.len() != self.barcode_length {
+            return None;
+        }
+        self.index_of_code(encode(barcode)?)
+    }
+
+    /// Returns true if the barcode is in the whitelist.
+    pub fn contains(&self, barcode: &[u8]) -> bool {
+        self.index_of(barcode).is_some()
+    }
+
+    /// Returns the barcodes, in sorted order.
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.codes.iter().map(|&code| decode(code, self.barcode_length))
+    }
+
+    /// Returns the index of the 2-bit encoded barcode, if present.
+    fn index_of_code(&self, code: u64) -> Option<usize> {
+        self.codes.binary_search(&code).ok()
+    }
+}
+
+/// Thresholds for correcting barcodes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CorrectionOptions {
+    /// The minimum posterior probability to correct a barcode to a whitelisted neighbour.
+    pub min_posterior: f64,
+    /// The pseudocount added to the observed count of every whitelisted barcode.
+    pub pseudocount: f64,
+}
+
+impl Default for CorrectionOptions {
+    /// Returns the defaults of Cell Ranger: a posterior of 0.975 and a pseudocount of one.
+    fn default() -> Self {
+        CorrectionOptions { min_posterior: DEFAULT_MIN_POSTERIOR, pseudocount: DEFAULT_PSEUDOCOUNT }
+    }
+}
+
+/// A barcode on (or corrected to) the whitelist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Correction {
+    /// The whitelisted barcode.
+    pub barcode: Vec<u8>,
+    /// The posterior probability of the barcode: one for exact matches.
+    pub posterior: f64,
+    /// True if the observed barcode was corrected, false if it was on the whitelist.
+    pub corrected: bool,
+}
+
+/// Corrects observed cell barcodes to a [`Whitelist`].  See
+/// [the module level documentation](self) for more.
+#[derive(Debug, Clone)]
+pub struct BarcodeCorrector {
+    /// The expected barcodes.
+    whitelist: Whitelist,
+    /// The number of times each whitelisted barcode was observed, in whitelist order.
+    counts: Vec<u32>,
+    /// The thresholds for correcting barcodes.
+    options: CorrectionOptions,
+}
+
+impl BarcodeCorrector {
+    /// Builds a new [`BarcodeCorrector`], with no barcodes observed.
+    pub fn new(whitelist: Whitelist, options: CorrectionOptions) -> Self {
+        let counts = vec![0; whitelist.len()];
+        BarcodeCorrector { whitelist, counts, options }
+    }
+
+    /// Returns the expected barcodes.
+    pub fn whitelist(&self) -> &Whitelist {
+        &self.whitelist
+    }
+
+    /// Returns the thresholds for correcting barcodes.
+    pub fn options(&self) -> &CorrectionOptions {
+        &self.options
+    }
+
+    /// Records an observed barcode, increasing its prior abundance if it is on the whitelist.
+    /// Returns true if it was on the whitelist.
+    pub fn observe(&mut self, barcode: &[u8]) -> bool {
+        match self.whitelist.index_of(barcode) {
+            Some(index) => {
+                self.counts[index] = self.counts[index].saturating_add(1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of times the barcode was observed, or `None` if it is not on the
+    /// whitelist.
+    pub fn count(&self, barcode: &[u8]) -> Option<u32> {
+        self.whitelist.index_of(barcode).map(|index| self.counts[index])
+    }
+
+    /// Corrects an observed barcode with its (Phred+33) qualities.  Returns `None` if the barcode
+    /// is not on the whitelist and could not be confidently corrected.
+    ///
+    /// # Errors
+    ///
+    /// - If the bases and qualities differ in length.
+    pub fn correct(
+        &self,
+        bases: &[u8],
+        quals: &[u8],
+    ) -> Result<Option<Correction>, ReadStructureError> {
+        if bases.len() != quals.len() {
+            return Err(ReadStructureError::MismatchingBasesAndQualsLen {
+                bases_len: bases.len(),
+                quals_len: quals.len(),
+            });
+        }
+        if bases.len() != self.whitelist.barcode_length {
+            return Ok(None);
+        }
+        if self.whitelist.contains(bases) {
+            return Ok(Some(Correction {
+                barcode: bases.to_vec(),
+                posterior: 1.0,
+                corrected: false,
+            }));
+        }
+
+        // Encode the observed barcode with any no-calls as `A`; every base differing from a
+        // neighbour (including each no-call) is then a substitution.
+        let code = bases.iter().fold(0, |code, &base| (code << 2) | encode_base(base).unwrap_or(0));
+        let no_calls = bases.iter().filter(|&&b| encode_base(b).is_none()).count();
+        if no_calls > 1 {
+            return Ok(None);
+        }
+        let (mut best, mut best_likelihood, mut total) = (0, 0.0, 0.0);
+        for (i, (&base, &qual)) in bases.iter().zip(quals).enumerate() {
+            let observed = encode_base(base);
+            if no_calls == 1 && observed.is_some() {
+                continue;
+            }
+            let shift = 2 * (bases.len() - 1 - i);
+            let error = 10f64.powf(-f64::from(qual.saturating_sub(33)) / 10.0);
+            for substitute in 0..4 {
+                if Some(substitute) == observed {
+                    continue;
+                }
+                let neighbour = (code & !(3 << shift)) | (substitute << shift);
+                if let Some(index) = self.whitelist.index_of_code(neighbour) {
+                    let prior = f64::from(self.counts[index]) + self.options.pseudocount;
+                    let likelihood = prior * error;
+                    total += likelihood;
+                    if likelihood > best_likelihood {
+                        best_likelihood = likelihood;
+                        best = neighbour;
+                    }
+                }
+            }
+        }
+        if total <= 0.0 {
+            return Ok(None);
+        }
+        let posterior = best_likelihood / total;
+        Ok((posterior >= self.options.min_posterior).then(|| Correction {
+            barcode: decode(best, bases.len()),
+            posterior,
+            corrected: true,
+        }))
+    }
+
+    /// Corrects the cell barcode extracted from one or more reads: the concatenated bases and
+    /// qualities of all cellular barcode segments, in order, each in the orientation of its read
+    /// (see [`crate::extraction::ExtractedSegment::oriented_bases`]).
+    ///
+    /// # Errors
+    ///
+    /// - If the extraction has no cellular barcode segments, or was made without qualities.
+    pub fn correct_extraction(
+        &self,
+        extraction: &Extraction,
+    ) -> Result<Option<Correction>, ReadStructureError> {
+        let (mut bases, mut quals) = (Vec::new(), Vec::new());
+        for segment in extraction.cellular_barcodes() {
+            bases.extend_from_slice(&segment.oriented_bases());
+            quals.extend_from_slice(
+                &segment.oriented_quals().ok_or(ReadStructureError::ExtractionMissingQuals)?,
+            );
+        }
+        if bases.is_empty() {
+            return Err(ReadStructureError::ExtractionMissingSegment(
+                "cellular barcode".to_owned(),
+            ));
+        }
+        self.correct(&bases, &quals)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::cell_barcode::{decode, encode, BarcodeCorrector, CorrectionOptions, Whitelist};
+    use crate::read_structure::ReadStructure;
+
+    fn corrector(barcodes: &[&str]) -> BarcodeCorrector {
+        let whitelist = Whitelist::new(barcodes.iter().map(|b| b.as_bytes())).unwrap();
+        BarcodeCorrector::new(whitelist, CorrectionOptions::default())
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        assert_eq!(encode(b"ACGT"), Some(0b00_01_10_11));
+        assert_eq!(encode(b"acgt"), encode(b"ACGT"));
+        assert_eq!(encode(b"ACGN"), None);
+        assert_eq!(encode(&[b'T'; 33]), None);
+        let barcode = b"TTTTTTTTACGTACGTACGTACGTACGTACGT";
+        assert_eq!(decode(encode(barcode).unwrap(), barcode.len()), barcode);
+        assert_eq!(decode(encode(b"AAAC").unwrap(), 4), b"AAAC");
+    }
+
+    #[test]
+    fn test_whitelist() {
+        let whitelist = Whitelist::from_reader(&b"# comment\nTTTT\tx\n\nACGT\nacgt\n"[..]).unwrap();
+        assert_eq!(whitelist.len(), 2);
+        assert_eq!(whitelist.barcode_length(), 4);
+        assert!(whitelist.contains(b"ACGT"));
+        assert!(whitelist.contains(b"tttt"));
+        assert!(!whitelist.contains(b"TTTA"));
+        assert!(!whitelist.contains(b"TTTTT"));
+        assert_eq!(whitelist.iter().collect::<Vec<_>>(), vec![b"ACGT".to_vec(), b"TTTT".to_vec()]);
+
+        let err = |s: &[u8]| Whitelist::from_reader(s).unwrap_err().to_string();
+        assert_eq!(err(b""), "Invalid whitelist: no barcodes");
+        assert_eq!(err(b"ACGT\nACG\n"), "Invalid whitelist: barcodes differ in length: ACG");
+        assert_eq!(err(b"ACGN\n"), "Invalid whitelist: invalid barcode: ACGN");
+    }
+
+    #[test]
+    fn test_whitelist_from_path() {
+        let path = std::env::temp_dir()
+            .join(format!("read-structure-whitelist-{}.txt", std::process::id()));
+        std::fs::write(&path, "AAAA\nCCCC\n").unwrap();
+        assert_eq!(Whitelist::from_path(&path).unwrap().len(), 2);
+        #[cfg(feature = "gzip")]
+        {
+            use std::io::Write;
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(b"AAAA\nCCCC\nGGGG\n").unwrap();
+            std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+            assert_eq!(Whitelist::from_path(&path).unwrap().len(), 3);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_correct_exact_and_single_neighbour() {
+        let corrector = corrector(&["AAAACCCC", "GGGGTTTT"]);
+        let correction = corrector.correct(b"AAAACCCC", b"########").unwrap().unwrap();
+        assert_eq!((correction.posterior, correction.corrected), (1.0, false));
+
+        let correction = corrector.correct(b"AAAACCCA", b"IIIIIIII").unwrap().unwrap();
+        assert_eq!(correction.barcode, b"AAAACCCC");
+        assert_eq!((correction.posterior, correction.corrected), (1.0, true));
+        // A single no-call is corrected, but not two or a second mismatch
+        assert!(corrector.correct(b"AAAANCCC", b"IIII#III").unwrap().is_some());
+        assert!(corrector.correct(b"AAAANNCC", b"IIII##II").unwrap().is_none());
+        assert!(corrector.correct(b"AAAANCCA", b"IIII#III").unwrap().is_none());
+        assert!(corrector.correct(b"AAAATTCC", b"IIIIIIII").unwrap().is_none());
+        // Wrong length, or mismatching bases and qualities
+        assert!(corrector.correct(b"AAAACCC", b"IIIIIII").unwrap().is_none());
+        assert!(corrector.correct(b"AAAACCCC", b"IIII").is_err());
+    }
+
+    #[test]
+    fn test_correct_posterior() {
+        // AAAACCCA is one substitution from both AAAACCCC (at its last base) and AAAAACCA
+        let mut corrector = corrector(&["AAAACCCC", "AAAAACCA"]);
+        // Equal priors and qualities: ambiguous
+        assert!(corrector.correct(b"AAAACCCA", b"IIIIIIII").unwrap().is_none());
+        // A low quality last base favours AAAACCCC: 10^-0.2 / (10^-0.2 + 10^-4)
+        let correction = corrector.correct(b"AAAACCCA", b"IIIIIII#").unwrap().unwrap();
+        assert_eq!(correction.barcode, b"AAAACCCC");
+        assert!((correction.posterior - 0.999_842).abs() < 1e-6, "{}", correction.posterior);
+        // A far more abundant AAAAACCA favours it despite equal qualities
+        for _ in 0..99 {
+            assert!(corrector.observe(b"AAAAACCA"));
+        }
+        assert!(!corrector.observe(b"TTTTTTTT"));
+        assert_eq!(corrector.count(b"AAAAACCA"), Some(99));
+        assert_eq!(corrector.count(b"TTTTTTTT"), None);
+        let correction = corrector.correct(b"AAAACCCA", b"IIIIIIII").unwrap().unwrap();
+        assert_eq!(correction.barcode, b"AAAAACCA");
+        assert!((correction.posterior - 100.0 / 101.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_correct_extraction() {
+        let corrector = corrector(&["AAAACCCC"]);
+        let rs = ReadStructure::from_str("4C2S4C+T").unwrap();
+        let extraction = rs.extract_with_quals(b"AAAAGGCCCGTT", b"IIIIIIIIIIII").unwrap();
+        let correction = corrector.correct_extraction(&extraction).unwrap().unwrap();
+        assert_eq!(correction.barcode, b"AAAACCCC");
+        assert!(corrector.correct_extraction(&rs.extract(b"AAAAGGCCCGTT").unwrap()).is_err());
+        // Sequenced as AAAACCCA, stored reverse complemented
+        let rs = ReadStructure::from_str("8C").unwrap();
+        let extraction = rs.extract_reverse_strand_with_quals(b"TGGGTTTT", b"#IIIIIII").unwrap();
+        let correction = corrector.correct_extraction(&extraction).unwrap().unwrap();
+        assert_eq!((correction.barcode.as_slice(), correction.corrected), (&b"AAAACCCC"[..], true));
+        let rs = ReadStructure::from_str("+T").unwrap();
+        assert!(corrector.correct_extraction(&rs.extract_with_quals(b"A", b"I").unwrap()).is_err());
+    }
+}
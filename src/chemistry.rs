@@ -0,0 +1,184 @@
+//! Chemistry Presets
+//!
+//! Type [`Chemistry`] names the read layouts of common commercial assays, so that users need not
+//! write (and mistype) the read structures themselves.  Each preset describes every read
+//! produced by the sequencer, in sequencing order (R1, I1, I2, R2), as a
+//! [`MultiReadStructure`], along with the name of each read and which reads carry the cell
+//! barcode and UMI.
+//!
+//! Template reads are given an indefinite length (`+T`) so that presets apply regardless of
+//! the number of cycles sequenced.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::chemistry::Chemistry;
+//!
+//! let chemistry = Chemistry::from_str("10x-3prime-v3").unwrap();
+//! assert_eq!(chemistry.read_structures().to_string(), "16C12M 10B 10B +T");
+//! assert_eq!(chemistry.cell_barcode_read(), Some("R1"));
+//! ```
+
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, IntoStaticStr};
+
+use crate::multi_read_structure::MultiReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// A named chemistry preset.  See [the module level documentation](self) for more.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Display, EnumIter, IntoStaticStr, PartialEq, Eq, Hash)]
+pub enum Chemistry {
+    /// 10x Genomics Single Cell 3' v2: 16bp cell barcode and 10bp UMI in R1, single 8bp index.
+    #[strum(serialize = "10x-3prime-v2")]
+    TenX3PrimeV2,
+    /// 10x Genomics Single Cell 3' v3: 16bp cell barcode and 12bp UMI in R1, dual 10bp indices.
+    #[strum(serialize = "10x-3prime-v3")]
+    TenX3PrimeV3,
+    /// 10x Genomics GEM-X Single Cell 3' v4: 16bp cell barcode and 12bp UMI in R1, dual 10bp
+    /// indices.
+    #[strum(serialize = "10x-3prime-v4")]
+    TenX3PrimeV4,
+    /// 10x Genomics Single Cell 5' v2: 16bp cell barcode and 10bp UMI in R1, dual 10bp indices.
+    #[strum(serialize = "10x-5prime-v2")]
+    TenX5PrimeV2,
+    /// 10x Genomics Multiome gene expression library: 16bp cell barcode and 12bp UMI in R1,
+    /// dual 10bp indices.
+    #[strum(serialize = "10x-multiome-gex")]
+    TenXMultiomeGex,
+    /// 10x Genomics Multiome ATAC library: paired template reads, 8bp i7 index, and the 16bp
+    /// cell barcode following an 8bp spacer in the i5 index read.
+    #[strum(serialize = "10x-multiome-atac")]
+    TenXMultiomeAtac,
+    /// 10x Genomics Visium spatial gene expression: 16bp spatial barcode and 12bp UMI in R1,
+    /// dual 10bp indices.
+    #[strum(serialize = "10x-visium")]
+    TenXVisium,
+    /// Illumina paired-end with a single 8bp i7 index.
+    #[strum(serialize = "illumina-single-index-8")]
+    IlluminaSingleIndex8,
+    /// Illumina paired-end with dual 8bp indices.
+    #[strum(serialize = "illumina-dual-index-8")]
+    IlluminaDualIndex8,
+    /// Illumina paired-end with dual 10bp indices.
+    #[strum(serialize = "illumina-dual-index-10")]
+    IlluminaDualIndex10,
+    /// Twist UMI adapters: a 5bp UMI followed by a 2bp skip at the start of each template read,
+    /// dual 10bp indices.
+    #[strum(serialize = "twist-umi")]
+    TwistUmi,
+    /// IDT xGen UDI-UMI adapters: dual 8bp indices with a 9bp UMI following the i7 index.
+    #[strum(serialize = "idt-xgen-udi-umi")]
+    IdtXgenUdiUmi,
+}
+
+impl Chemistry {
+    /// Returns the name by which this preset is resolved with [`FromStr`].
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Returns the name of each read, in sequencing order.
+    pub fn read_names(&self) -> &'static [&'static str] {
+        match self {
+            Chemistry::TenX3PrimeV2 | Chemistry::IlluminaSingleIndex8 => &["R1", "I1", "R2"],
+            _ => &["R1", "I1", "I2", "R2"],
+        }
+    }
+
+    /// Returns the read structure of each read, in sequencing order.
+    pub fn read_structures(&self) -> MultiReadStructure {
+        let read_structures = match self {
+            Chemistry::TenX3PrimeV2 => "16C10M 8B +T",
+            Chemistry::TenX3PrimeV3
+            | Chemistry::TenX3PrimeV4
+            | Chemistry::TenXMultiomeGex
+            | Chemistry::TenXVisium => "16C12M 10B 10B +T",
+            Chemistry::TenX5PrimeV2 => "16C10M 10B 10B +T",
+            Chemistry::TenXMultiomeAtac => "+T 8B 8S16C +T",
+            Chemistry::IlluminaSingleIndex8 => "+T 8B +T",
+            Chemistry::IlluminaDualIndex8 => "+T 8B 8B +T",
+            Chemistry::IlluminaDualIndex10 => "+T 10B 10B +T",
+            Chemistry::TwistUmi => "5M2S+T 10B 10B 5M2S+T",
+            Chemistry::IdtXgenUdiUmi => "+T 8B9M 8B +T",
+        };
+        // Unwrap is safe since the presets are tested to parse
+        MultiReadStructure::from_str(read_structures).unwrap()
+    }
+
+    /// Returns the name of the read carrying the cell barcode, if any.
+    pub fn cell_barcode_read(&self) -> Option<&'static str> {
+        self.first_read_with_type(SegmentType::CellularBarcode)
+    }
+
+    /// Returns the name of the first read carrying a UMI, if any.
+    pub fn umi_read(&self) -> Option<&'static str> {
+        self.first_read_with_type(SegmentType::MolecularBarcode)
+    }
+
+    /// Returns the name of the first read containing a segment of the given kind.
+    fn first_read_with_type(&self, kind: SegmentType) -> Option<&'static str> {
+        self.read_structures().reads_with_type(kind).first().map(|i| self.read_names()[*i])
+    }
+}
+
+impl FromStr for Chemistry {
+    type Err = ReadStructureError;
+
+    /// Resolves a [`Chemistry`] by name, ignoring case and treating `_` the same as `-`.
+    ///
+    /// # Errors
+    ///
+    /// - If no preset has the given name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase().replace('_', "-");
+        Chemistry::iter()
+            .find(|c| c.name() == name)
+            .ok_or_else(|| ReadStructureError::ChemistryUnknown(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use strum::IntoEnumIterator;
+
+    use crate::chemistry::Chemistry;
+
+    #[test]
+    fn test_chemistry_round_trip() {
+        for chemistry in Chemistry::iter() {
+            assert_eq!(Chemistry::from_str(chemistry.name()).unwrap(), chemistry);
+            assert_eq!(
+                chemistry.read_structures().number_of_reads(),
+                chemistry.read_names().len(),
+                "{}",
+                chemistry
+            );
+        }
+    }
+
+    #[test]
+    fn test_chemistry_from_str() {
+        assert_eq!(Chemistry::from_str("10X_3PRIME_V2").unwrap(), Chemistry::TenX3PrimeV2);
+        assert_eq!(Chemistry::from_str(" twist-umi ").unwrap(), Chemistry::TwistUmi);
+        let err = Chemistry::from_str("10x-3prime-v9").unwrap_err();
+        assert_eq!(err.to_string(), "Unknown chemistry: 10x-3prime-v9");
+    }
+
+    #[test]
+    fn test_chemistry_metadata() {
+        assert_eq!(Chemistry::TenX3PrimeV3.cell_barcode_read(), Some("R1"));
+        assert_eq!(Chemistry::TenX3PrimeV3.umi_read(), Some("R1"));
+        assert_eq!(Chemistry::TenXMultiomeAtac.cell_barcode_read(), Some("I2"));
+        assert_eq!(Chemistry::TenXMultiomeAtac.umi_read(), None);
+        assert_eq!(Chemistry::IdtXgenUdiUmi.umi_read(), Some("I1"));
+        assert_eq!(Chemistry::IlluminaDualIndex8.cell_barcode_read(), None);
+        assert_eq!(Chemistry::TenX3PrimeV2.read_structures().to_string(), "16C10M 8B +T");
+    }
+}
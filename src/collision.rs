@@ -0,0 +1,298 @@
+//! Barcode Collision Analysis
+//!
+//! Function [`analyze`] checks, before a run, whether the barcodes of a list of samples (e.g.
+//! read with [`crate::demux::SampleSheet::read_samples`]) can be told apart given the sample
+//! barcode (`B`) segments of a [`MultiReadStructure`].  Unlike those of a
+//! [`crate::demux::SampleSheet`], the barcodes may differ in length and may be duplicated:
+//!
+//! - each part of each barcode (e.g. the i7 and i5 indices) is compared with the length of its
+//!   segment, and mismatches are reported.  Barcodes with a single part are split across the
+//!   segments in order.  Only the cycles that will be sequenced are compared: longer parts are
+//!   truncated to their segment, and shorter parts are padded with no-calls;
+//! - the Hamming distances between all pairs of barcodes are computed per segment and combined,
+//!   where a no-call in either barcode matches any base; and
+//! - the maximum safe number of mismatches is reported: a read can be within `m` mismatches of
+//!   two barcodes only if their distance is at most `2m`, so `m` is safe if every distance is
+//!   greater than `2m`.  Pairs of barcodes that may collide at a given number of mismatches are
+//!   reported as [`Conflict`]s.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::MultiReadStructure;
+//! use read_structure::collision::analyze;
+//! use read_structure::demux::SampleSheet;
+//!
+//! let sheet = "Sample_ID,index,index2\n\
+//!              s1,AAAAAAAACC,CCCCCCCC\n\
+//!              s2,AAAAAAAAGG,CCCCCCCC\n\
+//!              s3,GGGGGGGGTT,TTTTTTTT\n";
+//! let samples = SampleSheet::read_samples(sheet.as_bytes()).unwrap();
+//! let mrs = MultiReadStructure::from_str("+T 8B 8B +T").unwrap();
+//! let report = analyze(&mrs, &samples, 1).unwrap();
+//!
+//! // The i7 indices are 10 bases, but only 8 are sequenced, so s1 and s2 cannot be told apart
+//! assert_eq!(report.length_mismatches.len(), 3);
+//! assert_eq!(report.min_distance, Some(0));
+//! assert_eq!(report.max_safe_mismatches, None);
+//! assert_eq!(report.conflicts[0].first, "s1");
+//! assert_eq!(report.conflicts[0].second, "s2");
+//! ```
+
+use crate::demux::Sample;
+use crate::multi_read_structure::MultiReadStructure;
+use crate::ReadStructureError;
+
+/// A sample whose barcode parts differ in length from the sample barcode segments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthMismatch {
+    /// The ID of the sample.
+    pub sample_id: String,
+    /// The length of each part of the barcode, as split across the segments.
+    pub part_lengths: Vec<usize>,
+}
+
+/// A pair of samples whose barcodes may not be told apart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The ID of the first sample, in the order given.
+    pub first: String,
+    /// The ID of the second sample.
+    pub second: String,
+    /// The Hamming distance between the barcodes across all segments.
+    pub distance: usize,
+    /// The Hamming distance between the barcodes for each segment.
+    pub segment_distances: Vec<usize>,
+}
+
+/// The results of [`analyze`].  See [the module level documentation](self) for more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollisionReport {
+    /// The length of each sample barcode segment, in order.
+    pub segment_lengths: Vec<usize>,
+    /// The samples whose barcode parts differ in length from the segments.
+    pub length_mismatches: Vec<LengthMismatch>,
+    /// The minimum distance between any two barcodes for each segment, or `None` if there are
+    /// fewer than two samples.
+    pub segment_min_distances: Vec<Option<usize>>,
+    /// The minimum distance between any two barcodes across all segments, or `None` if there are
+    /// fewer than two samples.
+    pub min_distance: Option<usize>,
+    /// The maximum number of mismatches at which no read can match two barcodes, or `None` if
+    /// two barcodes are indistinguishable.  With fewer than two samples, the total length of
+    /// the segments.
+    pub max_safe_mismatches: Option<usize>,
+    /// The pairs of samples whose barcodes are within twice the given number of mismatches,
+    /// closest first.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Returns the number of positions at which two barcodes of the same length differ, where a
+/// no-call in either matches any base.
+pub fn distance(a: &[u8], b: &[u8]) -> usize {
+    let no_call = |base: &u8| base.eq_ignore_ascii_case(&b'N');
+    a.iter()
+        .zip(b)
+        .filter(|(a, b)| !no_call(a) && !no_call(b) && !a.eq_ignore_ascii_case(b))
+        .count()
+}
+
+/// Returns the parts of the sample's barcode, one per segment.  Barcodes whose number of parts
+/// differs from the number of segments are split across the segments in order, with any
+/// remaining bases in the last part.
+pub(crate) fn split_barcode<'a>(sample: &'a Sample, segment_lengths: &[usize]) -> Vec<&'a [u8]> {
+    let parts: Vec<&[u8]> = sample.parts().collect();
+    if parts.len() == segment_lengths.len() {
+        return parts;
+    }
+    let mut rest = sample.barcode.as_slice();
+    segment_lengths
+        .iter()
+        .enumerate()
+        .map(|(i, &length)| {
+            let end = if i == segment_lengths.len() - 1 { rest.len() } else { length };
+            let (part, remaining) = rest.split_at(end.min(rest.len()));
+            rest = remaining;
+            part
+        })
+        .collect()
+}
+
+/// Analyzes whether the barcodes of the samples can be told apart given the sample barcode
+/// segments of the read structures, reporting conflicts for demultiplexing with up to
+/// `max_mismatches` mismatches.  See [the module level documentation](self) for more.
+///
+/// # Errors
+///
+/// - If the read structures have no sample barcode segments, or any of variable length.
+pub fn analyze(
+    read_structures: &MultiReadStructure,
+    samples: &[Sample],
+    max_mismatches: usize,
+) -> Result<CollisionReport, ReadStructureError> {
+    let segment_lengths = read_structures
+        .sample_barcodes()
+        .map(|(_, segment)| segment.length)
+        .collect::<Option<Vec<usize>>>()
+        .filter(|lengths| !lengths.is_empty())
+        .ok_or_else(|| {
+            ReadStructureError::SampleBarcodeSegmentsInvalid(read_structures.to_string())
+        })?;
+
+    // The sequenced bases of each part of each barcode
+    let mut length_mismatches = Vec::new();
+    let mut barcodes: Vec<Vec<Vec<u8>>> = Vec::with_capacity(samples.len());
+    for sample in samples {
+        let parts = split_barcode(sample, &segment_lengths);
+        let part_lengths: Vec<usize> = parts.iter().map(|p| p.len()).collect();
+        if part_lengths != segment_lengths {
+            length_mismatches
+                .push(LengthMismatch { sample_id: sample.sample_id.clone(), part_lengths });
+        }
+        let sequenced = parts
+            .iter()
+            .zip(&segment_lengths)
+            .map(|(part, &length)| {
+                let mut bases = part[..part.len().min(length)].to_vec();
+                bases.resize(length, b'N');
+                bases
+            })
+            .collect();
+        barcodes.push(sequenced);
+    }
+
+    let mut segment_min_distances = vec![None; segment_lengths.len()];
+    let mut min_distance: Option<usize> = None;
+    let mut conflicts = Vec::new();
+    for i in 0..barcodes.len() {
+        for j in i + 1..barcodes.len() {
+            let segment_distances: Vec<usize> =
+                barcodes[i].iter().zip(&barcodes[j]).map(|(a, b)| distance(a, b)).collect();
+            for (min, &d) in segment_min_distances.iter_mut().zip(&segment_distances) {
+                *min = Some(min.map_or(d, |m: usize| m.min(d)));
+            }
+            let total = segment_distances.iter().sum();
+            min_distance = Some(min_distance.map_or(total, |m| m.min(total)));
+            if total <= 2 * max_mismatches {
+                conflicts.push(Conflict {
+                    first: samples[i].sample_id.clone(),
+                    second: samples[j].sample_id.clone(),
+                    distance: total,
+                    segment_distances,
+                });
+            }
+        }
+    }
+    conflicts.sort_by_key(|c| c.distance);
+
+    let max_safe_mismatches = match min_distance {
+        Some(0) => None,
+        Some(d) => Some((d - 1) / 2),
+        None => Some(segment_lengths.iter().sum()),
+    };
+    Ok(CollisionReport {
+        segment_lengths,
+        length_mismatches,
+        segment_min_distances,
+        min_distance,
+        max_safe_mismatches,
+        conflicts,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::collision::{analyze, distance, LengthMismatch};
+    use crate::demux::test::sheet;
+    use crate::demux::Sample;
+    use crate::multi_read_structure::MultiReadStructure;
+
+    fn mrs(s: &str) -> MultiReadStructure {
+        MultiReadStructure::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance(b"ACGT", b"ACGT"), 0);
+        assert_eq!(distance(b"ACGT", b"TGCA"), 4);
+        assert_eq!(distance(b"ACGN", b"ACGA"), 0);
+        assert_eq!(distance(b"acgt", b"ACGA"), 1);
+    }
+
+    #[test]
+    fn test_analyze_distances() {
+        let sheet = sheet(&["AAAA-CCCC", "AAAT-CCGG", "TTTT-GGGG"]);
+        let report = analyze(&mrs("+T 4B 4B +T"), sheet.samples(), 1).unwrap();
+        assert_eq!(report.segment_lengths, [4, 4]);
+        assert!(report.length_mismatches.is_empty());
+        assert_eq!(report.segment_min_distances, [Some(1), Some(2)]);
+        assert_eq!(report.min_distance, Some(3));
+        assert_eq!(report.max_safe_mismatches, Some(1));
+        assert!(report.conflicts.is_empty());
+
+        let report = analyze(&mrs("+T 4B 4B +T"), sheet.samples(), 2).unwrap();
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].first, "s1");
+        assert_eq!(report.conflicts[0].second, "s2");
+        assert_eq!(report.conflicts[0].distance, 3);
+        assert_eq!(report.conflicts[0].segment_distances, [1, 2]);
+
+        // A single part barcode is split across the segments
+        let sheet = self::sheet(&["AAAACCCC", "AAATCCGG"]);
+        let report = analyze(&mrs("4B+T 4B"), sheet.samples(), 0).unwrap();
+        assert_eq!(report.segment_min_distances, [Some(1), Some(2)]);
+    }
+
+    #[test]
+    fn test_analyze_length_mismatches() {
+        // Only the first 8 bases of each 10 base i7 index are sequenced
+        let sheet = sheet(&["AAAAAAAACC-CCCCCCCC", "AAAAAAAAGG-CCCCCCCC", "GGGGGGGGTT-TTTTTTTT"]);
+        let report = analyze(&mrs("+T 8B 8B +T"), sheet.samples(), 1).unwrap();
+        assert_eq!(
+            report.length_mismatches[0],
+            LengthMismatch { sample_id: "s1".to_owned(), part_lengths: vec![10, 8] }
+        );
+        assert_eq!(report.segment_min_distances, [Some(0), Some(0)]);
+        assert_eq!(report.min_distance, Some(0));
+        assert_eq!(report.max_safe_mismatches, None);
+        assert_eq!(report.conflicts[0].distance, 0);
+        assert_eq!(report.conflicts.len(), 1);
+
+        // Shorter barcodes are padded with no-calls
+        let sheet = self::sheet(&["AAAAAA", "AAAAAC"]);
+        let report = analyze(&mrs("8B+T"), sheet.samples(), 0).unwrap();
+        assert_eq!(report.length_mismatches.len(), 2);
+        assert_eq!(report.min_distance, Some(1));
+        assert_eq!(report.max_safe_mismatches, Some(0));
+
+        // A mixed pool of 8 and 10 base barcodes, with an exact duplicate
+        let samples = [
+            Sample::new("s1", b"AAAAAAAA"),
+            Sample::new("s2", b"AAAAAAAACC"),
+            Sample::new("s3", b"CCCCCCCC"),
+            Sample::new("s4", b"CCCCCCCC"),
+        ];
+        let report = analyze(&mrs("8B+T"), &samples, 0).unwrap();
+        assert_eq!(
+            report.length_mismatches,
+            [LengthMismatch { sample_id: "s2".to_owned(), part_lengths: vec![10] }]
+        );
+        let pairs: Vec<(&str, &str)> =
+            report.conflicts.iter().map(|c| (c.first.as_str(), c.second.as_str())).collect();
+        assert_eq!(pairs, [("s1", "s2"), ("s3", "s4")]);
+        assert_eq!(report.max_safe_mismatches, None);
+    }
+
+    #[test]
+    fn test_analyze_edge_cases() {
+        let report = analyze(&mrs("8B+T"), sheet(&["ACGTACGT"]).samples(), 1).unwrap();
+        assert_eq!(report.min_distance, None);
+        assert_eq!(report.segment_min_distances, [None]);
+        assert_eq!(report.max_safe_mismatches, Some(8));
+        assert!(analyze(&mrs("+T"), sheet(&["ACGT"]).samples(), 1).is_err());
+        assert!(analyze(&mrs("+B"), sheet(&["ACGT"]).samples(), 1).is_err());
+    }
+}
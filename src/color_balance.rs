@@ -0,0 +1,288 @@
+//! Index Color Balance
+//!
+//! Illumina instruments image each cycle in two channels (here called red and green), and need
+//! signal in both channels at every cycle to register clusters.  Function [`check`] maps each
+//! base of each planned barcode in a [`SampleSheet`] to the cycle it occupies, via the offsets
+//! of the sample barcode (`B`) segments of a [`MultiReadStructure`], and reports the fraction of
+//! barcodes with signal in each channel per cycle:
+//!
+//! - with [`ColorChemistry::TwoColor`] (e.g. NovaSeq, NextSeq, MiniSeq), `A` is read in both
+//!   channels, `C` in red only, `T` in green only, and `G` in neither.  A cycle where every
+//!   barcode has a `G` is dark, and fails registration.
+//! - with [`ColorChemistry::FourColor`] (e.g. MiSeq, HiSeq 2500), `A` and `C` are read in red,
+//!   and `G` and `T` in green.
+//!
+//! Barcodes are split across the segments as in [`crate::collision`], and reads with
+//! [`crate::orientation::Orientation::ReverseComplement`] are reverse complemented so that each
+//! base is placed in the cycle in which it is sequenced.  No-calls (`N`), including the padding
+//! of barcodes shorter than their segments, are not counted.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::MultiReadStructure;
+//! use read_structure::color_balance::{check, ColorChemistry, DEFAULT_MIN_FRACTION};
+//! use read_structure::demux::SampleSheet;
+//!
+//! let sheet = SampleSheet::from_reader("Sample_ID,index\ns1,GACT\ns2,GTCA\n".as_bytes()).unwrap();
+//! let mrs = MultiReadStructure::from_str("+T 4B +T").unwrap();
+//! let report = check(&mrs, &sheet, ColorChemistry::TwoColor, DEFAULT_MIN_FRACTION).unwrap();
+//!
+//! // Every barcode has a G (no signal) in the first cycle, and a C (red only) in the third
+//! let poor: Vec<usize> = report.poor_cycles().map(|c| c.cycle).collect();
+//! assert_eq!(poor, [1, 3]);
+//! assert!(report.cycles[0].is_dark());
+//! ```
+
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, IntoStaticStr};
+
+use crate::collision::split_barcode;
+use crate::demux::SampleSheet;
+use crate::multi_read_structure::MultiReadStructure;
+use crate::orientation::{reverse_complement, Orientation};
+use crate::ReadStructureError;
+
+/// The default minimum fraction of barcodes with signal in each channel at each cycle.
+pub const DEFAULT_MIN_FRACTION: f64 = 0.25;
+
+/// The imaging chemistry of an instrument.  See [the module level documentation](self) for more.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Display, EnumIter, IntoStaticStr, PartialEq, Eq, Hash)]
+pub enum ColorChemistry {
+    /// Two-color chemistry: `A` in both channels, `C` in red, `T` in green, and `G` dark.
+    #[strum(serialize = "two-color")]
+    TwoColor,
+    /// Four-color chemistry: `A` and `C` in red, `G` and `T` in green.
+    #[strum(serialize = "four-color")]
+    FourColor,
+}
+
+impl ColorChemistry {
+    /// Returns the name of the chemistry.
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Returns whether the base gives signal in the red and green channels respectively.
+    /// No-calls and other bases give no signal.
+    pub fn signal(&self, base: u8) -> (bool, bool) {
+        match (self, base.to_ascii_uppercase()) {
+            (ColorChemistry::TwoColor, b'A') => (true, true),
+            (ColorChemistry::TwoColor, b'C') | (ColorChemistry::FourColor, b'A' | b'C') => {
+                (true, false)
+            }
+            (ColorChemistry::TwoColor, b'T') | (ColorChemistry::FourColor, b'G' | b'T') => {
+                (false, true)
+            }
+            _ => (false, false),
+        }
+    }
+}
+
+impl FromStr for ColorChemistry {
+    type Err = ReadStructureError;
+
+    /// Resolves a [`ColorChemistry`] by name, ignoring case and treating `_` the same as `-`.
+    ///
+    /// # Errors
+    ///
+    /// - If no chemistry has the given name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase().replace('_', "-");
+        ColorChemistry::iter()
+            .find(|c| c.name() == name)
+            .ok_or_else(|| ReadStructureError::ColorChemistryUnknown(s.to_owned()))
+    }
+}
+
+/// The color balance of the barcodes at a single cycle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CycleBalance {
+    /// The index of the read containing the cycle.
+    pub read_index: usize,
+    /// The one-based cycle within the read.
+    pub cycle: usize,
+    /// The number of barcodes with a called (non-`N`) base at the cycle.
+    pub called: usize,
+    /// The fraction of called bases with signal in the red channel.
+    pub red_fraction: f64,
+    /// The fraction of called bases with signal in the green channel.
+    pub green_fraction: f64,
+}
+
+impl CycleBalance {
+    /// Returns true if no called base has signal in either channel.
+    pub fn is_dark(&self) -> bool {
+        self.called > 0 && self.red_fraction == 0.0 && self.green_fraction == 0.0
+    }
+
+    /// Returns true if both channels have signal in at least `min_fraction` of the called bases,
+    /// and in at least one.  Cycles without called bases are balanced.
+    pub fn is_balanced(&self, min_fraction: f64) -> bool {
+        self.called == 0
+            || [self.red_fraction, self.green_fraction]
+                .iter()
+                .all(|&f| f > 0.0 && f >= min_fraction)
+    }
+}
+
+/// The results of [`check`].  See [the module level documentation](self) for more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorBalanceReport {
+    /// The chemistry the barcodes were checked for.
+    pub chemistry: ColorChemistry,
+    /// The minimum fraction of barcodes with signal in each channel.
+    pub min_fraction: f64,
+    /// The balance at each barcode cycle, in sequencing order.
+    pub cycles: Vec<CycleBalance>,
+}
+
+impl ColorBalanceReport {
+    /// Returns the cycles with poor color balance.
+    pub fn poor_cycles(&self) -> impl Iterator<Item = &CycleBalance> {
+        self.cycles.iter().filter(|c| !c.is_balanced(self.min_fraction))
+    }
+
+    /// Returns true if every cycle has good color balance.
+    pub fn is_balanced(&self) -> bool {
+        self.poor_cycles().next().is_none()
+    }
+}
+
+/// Checks the color balance of the barcodes in a sample sheet at each cycle of the sample
+/// barcode segments of the read structures.  See [the module level documentation](self) for
+/// more.
+///
+/// # Errors
+///
+/// - If the read structures have no sample barcode segments, or any of variable length.
+pub fn check(
+    read_structures: &MultiReadStructure,
+    sample_sheet: &SampleSheet,
+    chemistry: ColorChemistry,
+    min_fraction: f64,
+) -> Result<ColorBalanceReport, ReadStructureError> {
+    let segments = read_structures
+        .sample_barcodes()
+        .map(|(read_index, segment)| segment.length.map(|l| (read_index, segment.offset(), l)))
+        .collect::<Option<Vec<_>>>()
+        .filter(|segments| !segments.is_empty())
+        .ok_or_else(|| {
+            ReadStructureError::SampleBarcodeSegmentsInvalid(read_structures.to_string())
+        })?;
+    let segment_lengths: Vec<usize> = segments.iter().map(|(_, _, length)| *length).collect();
+
+    // The (called, red, green) counts for each cycle of each segment
+    let mut counts: Vec<Vec<(usize, usize, usize)>> =
+        segment_lengths.iter().map(|&length| vec![(0, 0, 0); length]).collect();
+    for sample in sample_sheet.samples() {
+        let parts = split_barcode(sample, &segment_lengths);
+        for ((part, &(read_index, _, _)), counts) in parts.iter().zip(&segments).zip(&mut counts) {
+            let sequenced = match read_structures.orientation(read_index) {
+                Orientation::Forward => part.to_vec(),
+                Orientation::ReverseComplement => reverse_complement(part),
+            };
+            for (&base, count) in sequenced.iter().zip(counts.iter_mut()) {
+                if base.eq_ignore_ascii_case(&b'N') {
+                    continue;
+                }
+                let (red, green) = chemistry.signal(base);
+                count.0 += 1;
+                count.1 += usize::from(red);
+                count.2 += usize::from(green);
+            }
+        }
+    }
+
+    let fraction = |n: usize, d: usize| if d == 0 { 0.0 } else { n as f64 / d as f64 };
+    let cycles = segments
+        .iter()
+        .zip(counts)
+        .flat_map(|(&(read_index, offset, _), counts)| {
+            counts.into_iter().enumerate().map(move |(i, (called, red, green))| CycleBalance {
+                read_index,
+                cycle: offset + i + 1,
+                called,
+                red_fraction: fraction(red, called),
+                green_fraction: fraction(green, called),
+            })
+        })
+        .collect();
+    Ok(ColorBalanceReport { chemistry, min_fraction, cycles })
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::color_balance::{check, ColorChemistry};
+    use crate::demux::test::sheet;
+    use crate::multi_read_structure::MultiReadStructure;
+    use crate::orientation::Orientation;
+
+    fn poor_cycles(report: &crate::color_balance::ColorBalanceReport) -> Vec<(usize, usize)> {
+        report.poor_cycles().map(|c| (c.read_index, c.cycle)).collect()
+    }
+
+    #[test]
+    fn test_color_chemistry() {
+        assert_eq!(ColorChemistry::from_str("Two_Color").unwrap(), ColorChemistry::TwoColor);
+        assert_eq!(ColorChemistry::FourColor.to_string(), "four-color");
+        assert!(ColorChemistry::from_str("three-color").is_err());
+        assert_eq!(ColorChemistry::TwoColor.signal(b'g'), (false, false));
+        assert_eq!(ColorChemistry::TwoColor.signal(b'A'), (true, true));
+        assert_eq!(ColorChemistry::FourColor.signal(b'G'), (false, true));
+        assert_eq!(ColorChemistry::FourColor.signal(b'N'), (false, false));
+    }
+
+    #[test]
+    fn test_check_two_and_four_color() {
+        let mrs = MultiReadStructure::from_str("+T 2S4B +T").unwrap();
+        let sheet = sheet(&["GACT", "GTCA"]);
+        let report = check(&mrs, &sheet, ColorChemistry::TwoColor, 0.25).unwrap();
+        assert_eq!(report.cycles.len(), 4);
+        assert_eq!(report.cycles[1].read_index, 1);
+        assert_eq!(report.cycles[1].cycle, 4);
+        assert_eq!(report.cycles[1].red_fraction, 0.5);
+        assert_eq!(report.cycles[1].green_fraction, 1.0);
+        assert!(report.cycles[0].is_dark());
+        assert_eq!(poor_cycles(&report), [(1, 3), (1, 5)]);
+        assert!(!report.is_balanced());
+
+        let report = check(&mrs, &sheet, ColorChemistry::FourColor, 0.25).unwrap();
+        assert!(!report.cycles[0].is_dark());
+        assert_eq!(poor_cycles(&report), [(1, 3), (1, 5)]);
+        let report = check(&mrs, &self::sheet(&["ACGT", "GTAC"]), ColorChemistry::FourColor, 0.5);
+        assert!(report.unwrap().is_balanced());
+    }
+
+    #[test]
+    fn test_check_dual_index_orientation() {
+        let sheet = sheet(&["AAAA-TTNN", "CCCC-TTTN"]);
+        let mrs = MultiReadStructure::from_str("+T 4B 4B +T").unwrap();
+        let report = check(&mrs, &sheet, ColorChemistry::TwoColor, 0.25).unwrap();
+        assert_eq!(poor_cycles(&report), [(2, 1), (2, 2), (2, 3)]);
+        assert_eq!(report.cycles[6].called, 1);
+        assert_eq!(report.cycles[7].called, 0);
+        assert!(report.cycles[7].is_balanced(1.0));
+
+        // The i5 read is sequenced as the reverse complement of the sample sheet
+        let orientations = vec![
+            Orientation::Forward,
+            Orientation::Forward,
+            Orientation::ReverseComplement,
+            Orientation::Forward,
+        ];
+        let mrs = mrs.with_orientations(orientations).unwrap();
+        let report = check(&mrs, &sheet, ColorChemistry::TwoColor, 0.25).unwrap();
+        assert_eq!(report.cycles[4].called, 0);
+        assert!(poor_cycles(&report).is_empty());
+
+        let mrs = MultiReadStructure::from_str("+T +B").unwrap();
+        assert!(check(&mrs, &sheet, ColorChemistry::TwoColor, 0.25).is_err());
+    }
+}
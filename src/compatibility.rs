@@ -0,0 +1,191 @@
+//! Read Length Compatibility
+//!
+//! Type [`CompatibilityReport`] summarizes how a [`ReadStructure`] fits the lengths of observed
+//! reads, as returned by [`ReadStructure::check_compatibility`] (for read lengths) and
+//! [`ReadStructure::check_compatibility_histogram`] (for a histogram of read lengths): the reads
+//! too short for the fixed length segments, the segments that would be truncated or missing,
+//! the range of template lengths produced, and the reads for which an indefinite length (`+`)
+//! segment would be empty.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::ReadStructure;
+//!
+//! let rs = ReadStructure::from_str("8M10B+T").unwrap();
+//! let report = rs.check_compatibility([100, 100, 18, 12]);
+//! assert_eq!(report.fraction_shorter_than_fixed(), 0.25);
+//! assert_eq!(report.truncated_segments().count(), 2);
+//! assert_eq!(report.min_template_length, Some(0));
+//! assert_eq!(report.max_template_length, Some(82));
+//! assert!(report.indefinite_ever_empty());
+//! ```
+
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+
+/// How a single segment fits the observed reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentCompatibility {
+    /// The segment.
+    pub segment: ReadSegment,
+    /// The number of reads that end within the segment.
+    pub truncated: u64,
+    /// The number of reads that end before the segment starts.
+    pub missing: u64,
+}
+
+impl SegmentCompatibility {
+    /// Returns true if any read would truncate, or not reach, the segment.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated > 0 || self.missing > 0
+    }
+}
+
+/// The results of checking a read structure against observed read lengths.  See [the module
+/// level documentation](self) for more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompatibilityReport {
+    /// The number of reads.
+    pub reads: u64,
+    /// The number of reads shorter than the combined length of the fixed length segments.
+    pub shorter_than_fixed: u64,
+    /// How each segment fits the reads, in read structure order.
+    pub segments: Vec<SegmentCompatibility>,
+    /// The minimum number of template bases in any read, or `None` if there are no reads.
+    pub min_template_length: Option<usize>,
+    /// The maximum number of template bases in any read, or `None` if there are no reads.
+    pub max_template_length: Option<usize>,
+    /// The number of reads for which the indefinite length segment, if any, would be empty.
+    pub indefinite_empty: u64,
+}
+
+impl CompatibilityReport {
+    /// Builds the report for a read structure from read lengths paired with the number of reads
+    /// of each length.
+    pub(crate) fn new<I: IntoIterator<Item = (usize, u64)>>(
+        read_structure: &ReadStructure,
+        histogram: I,
+    ) -> Self {
+        let mut report = CompatibilityReport {
+            reads: 0,
+            shorter_than_fixed: 0,
+            segments: read_structure
+                .iter()
+                .map(|&segment| SegmentCompatibility { segment, truncated: 0, missing: 0 })
+                .collect(),
+            min_template_length: None,
+            max_template_length: None,
+            indefinite_empty: 0,
+        };
+        for (length, count) in histogram.into_iter().filter(|(_, count)| *count > 0) {
+            report.reads += count;
+            if length < read_structure.length_of_fixed_segments() {
+                report.shorter_than_fixed += count;
+            }
+            let mut template_length = 0;
+            for segment in &mut report.segments {
+                let start = segment.segment.offset();
+                let end = segment.segment.length.map_or(length, |l| start + l);
+                if length <= start {
+                    segment.missing += count;
+                } else if length < end {
+                    segment.truncated += count;
+                }
+                if length <= start && !segment.segment.has_length() {
+                    report.indefinite_empty += count;
+                }
+                if segment.segment.kind == SegmentType::Template {
+                    template_length += end.min(length).saturating_sub(start);
+                }
+            }
+            report.min_template_length = Some(
+                report.min_template_length.map_or(template_length, |m| m.min(template_length)),
+            );
+            report.max_template_length = Some(
+                report.max_template_length.map_or(template_length, |m| m.max(template_length)),
+            );
+        }
+        report
+    }
+
+    /// Returns the fraction of reads shorter than the combined length of the fixed length
+    /// segments, or zero if there are no reads.
+    pub fn fraction_shorter_than_fixed(&self) -> f64 {
+        if self.reads == 0 {
+            0.0
+        } else {
+            self.shorter_than_fixed as f64 / self.reads as f64
+        }
+    }
+
+    /// Returns the segments that any read would truncate, or not reach.
+    pub fn truncated_segments(&self) -> impl Iterator<Item = &SegmentCompatibility> {
+        self.segments.iter().filter(|s| s.is_truncated())
+    }
+
+    /// Returns true if the indefinite length segment would be empty for any read.
+    pub fn indefinite_ever_empty(&self) -> bool {
+        self.indefinite_empty > 0
+    }
+
+    /// Returns true if every segment is complete (and every indefinite length segment
+    /// non-empty) in every read.
+    pub fn is_compatible(&self) -> bool {
+        self.truncated_segments().next().is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::read_structure::ReadStructure;
+
+    #[test]
+    fn test_check_compatibility() {
+        let rs = ReadStructure::from_str("8M10B+T").unwrap();
+        let report = rs.check_compatibility([100, 150, 19]);
+        assert_eq!(report.reads, 3);
+        assert_eq!(report.shorter_than_fixed, 0);
+        assert!(report.is_compatible());
+        assert_eq!(report.min_template_length, Some(1));
+        assert_eq!(report.max_template_length, Some(132));
+        assert!(!report.indefinite_ever_empty());
+
+        let report = rs.check_compatibility([100, 18, 12, 4]);
+        assert_eq!(report.shorter_than_fixed, 2);
+        assert_eq!(report.fraction_shorter_than_fixed(), 0.5);
+        let truncated: Vec<(String, u64, u64)> = report
+            .truncated_segments()
+            .map(|s| (s.segment.to_string(), s.truncated, s.missing))
+            .collect();
+        assert_eq!(
+            truncated,
+            vec![("8M".to_owned(), 1, 0), ("10B".to_owned(), 1, 1), ("+T".to_owned(), 0, 3)]
+        );
+        assert_eq!(report.indefinite_empty, 3);
+        assert!(!report.is_compatible());
+    }
+
+    #[test]
+    fn test_check_compatibility_histogram() {
+        let rs = ReadStructure::from_str("4M20T").unwrap();
+        let report = rs.check_compatibility_histogram([(24, 90), (30, 5), (14, 5), (2, 0)]);
+        assert_eq!(report.reads, 100);
+        assert_eq!(report.fraction_shorter_than_fixed(), 0.05);
+        assert_eq!(report.segments[1].truncated, 5);
+        assert_eq!(report.segments[0].missing, 0);
+        assert_eq!(report.min_template_length, Some(10));
+        assert_eq!(report.max_template_length, Some(20));
+        assert_eq!(report.indefinite_empty, 0);
+
+        let report = rs.check_compatibility_histogram(Vec::new());
+        assert_eq!(report.reads, 0);
+        assert_eq!(report.fraction_shorter_than_fixed(), 0.0);
+        assert_eq!(report.min_template_length, None);
+        assert!(report.is_compatible());
+    }
+}
@@ -0,0 +1,170 @@
+//! Cycle Maps
+//!
+//! Type [`CycleMap`] records which segment of a [`ReadStructure`] each cycle of a read belongs
+//! to, for per-cycle QC (e.g. quality or error rate by cycle).  Build one with
+//! [`ReadStructure::cycle_map`] for fixed length read structures, or with
+//! [`ReadStructure::cycle_map_for_length`] for reads of a given length.  Use
+//! [`CycleMap::summarize`] to aggregate a per-cycle metric into a [`SegmentSummary`] per
+//! segment.
+//!
+//! Cycles are zero-based.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::ReadStructure;
+//!
+//! let rs = ReadStructure::from_str("2M3B+T").unwrap();
+//! let map = rs.cycle_map_for_length(8);
+//! assert_eq!(map.segment_index(3), Some(1));
+//!
+//! let mean_quality = [30, 32, 20, 22, 24, 36, 36, 30];
+//! let summaries = map.summarize(&mean_quality);
+//! assert_eq!(summaries[1].mean, Some(22.0));
+//! assert_eq!(summaries[2].min, Some(30.0));
+//! ```
+
+use crate::read_structure::ReadStructure;
+
+/// The segment index of each cycle of a read.  See [the module level documentation](self) for
+/// more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleMap {
+    /// The index of the segment for each cycle, or `None` after the end of the read structure.
+    segment_indices: Vec<Option<usize>>,
+    /// The number of segments in the read structure.
+    number_of_segments: usize,
+}
+
+/// A per-cycle metric aggregated over the cycles of a single segment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentSummary {
+    /// The index of the segment in the read structure.
+    pub segment_index: usize,
+    /// The number of cycles of the segment with a value.
+    pub cycles: usize,
+    /// The sum of the values.
+    pub sum: f64,
+    /// The mean of the values, or `None` if there are none.
+    pub mean: Option<f64>,
+    /// The minimum value, or `None` if there are none.
+    pub min: Option<f64>,
+    /// The maximum value, or `None` if there are none.
+    pub max: Option<f64>,
+}
+
+impl CycleMap {
+    /// Builds the map for reads of `read_length` cycles.
+    pub(crate) fn new(read_structure: &ReadStructure, read_length: usize) -> Self {
+        let segment_indices = (0..read_length)
+            .map(|cycle| read_structure.segment_at(cycle).map(|(i, _)| i))
+            .collect();
+        CycleMap { segment_indices, number_of_segments: read_structure.number_of_segments() }
+    }
+
+    /// Returns the number of cycles.
+    pub fn len(&self) -> usize {
+        self.segment_indices.len()
+    }
+
+    /// Returns true if there are no cycles.
+    pub fn is_empty(&self) -> bool {
+        self.segment_indices.is_empty()
+    }
+
+    /// Returns the index of the segment for the given cycle, or `None` if the cycle is after the
+    /// end of the read or of the read structure.
+    pub fn segment_index(&self, cycle: usize) -> Option<usize> {
+        self.segment_indices.get(cycle).copied().flatten()
+    }
+
+    /// Returns the index of the segment for each cycle.
+    pub fn segment_indices(&self) -> &[Option<usize>] {
+        &self.segment_indices
+    }
+
+    /// Returns the cycles of the segment with the given index.
+    pub fn cycles(&self, segment_index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.segment_indices
+            .iter()
+            .enumerate()
+            .filter(move |(_, i)| **i == Some(segment_index))
+            .map(|(cycle, _)| cycle)
+    }
+
+    /// Aggregates per-cycle values (e.g. mean quality by cycle) into one summary per segment, in
+    /// read structure order.  Values for cycles after the end of the map, or after the end of
+    /// the read structure, are ignored.
+    pub fn summarize<T: Copy + Into<f64>>(&self, values: &[T]) -> Vec<SegmentSummary> {
+        let mut summaries: Vec<SegmentSummary> = (0..self.number_of_segments)
+            .map(|segment_index| SegmentSummary {
+                segment_index,
+                cycles: 0,
+                sum: 0.0,
+                mean: None,
+                min: None,
+                max: None,
+            })
+            .collect();
+        for (index, value) in self.segment_indices.iter().zip(values) {
+            if let Some(summary) = index.map(|i| &mut summaries[i]) {
+                let value: f64 = (*value).into();
+                summary.cycles += 1;
+                summary.sum += value;
+                summary.min = Some(summary.min.map_or(value, |m| m.min(value)));
+                summary.max = Some(summary.max.map_or(value, |m| m.max(value)));
+            }
+        }
+        for summary in &mut summaries {
+            summary.mean = (summary.cycles > 0).then_some(summary.sum / summary.cycles as f64);
+        }
+        summaries
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::read_structure::ReadStructure;
+
+    #[test]
+    fn test_cycle_map() {
+        let rs = ReadStructure::from_str("2M3B4T").unwrap();
+        let map = rs.cycle_map().unwrap();
+        assert_eq!(map.len(), 9);
+        let indices: Vec<Option<usize>> = map.segment_indices().to_vec();
+        assert_eq!(indices, [0, 0, 1, 1, 1, 2, 2, 2, 2].map(Some));
+        assert_eq!(map.cycles(1).collect::<Vec<_>>(), vec![2, 3, 4]);
+        assert_eq!(map.segment_index(9), None);
+        assert!(ReadStructure::from_str("2M+T").unwrap().cycle_map().is_none());
+
+        // Reads longer or shorter than the read structure
+        let map = rs.cycle_map_for_length(11);
+        assert_eq!(map.segment_index(8), Some(2));
+        assert_eq!(map.segment_index(9), None);
+        let map = rs.cycle_map_for_length(3);
+        assert_eq!(map.segment_indices(), [Some(0), Some(0), Some(1)]);
+        assert_eq!(
+            ReadStructure::from_str("2M+T").unwrap().cycle_map_for_length(6).cycles(1).count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_summarize() {
+        let rs = ReadStructure::from_str("2M3B4T").unwrap();
+        let summaries = rs.cycle_map_for_length(4).summarize(&[1.0, 3.0, 0.5, 1.5, 9.0]);
+        assert_eq!(summaries.len(), 3);
+        assert_eq!(summaries[0].cycles, 2);
+        assert_eq!(summaries[0].sum, 4.0);
+        assert_eq!(summaries[0].mean, Some(2.0));
+        assert_eq!((summaries[1].min, summaries[1].max), (Some(0.5), Some(1.5)));
+        assert_eq!(summaries[2].cycles, 0);
+        assert_eq!(summaries[2].mean, None);
+
+        let summaries = rs.cycle_map().unwrap().summarize(&[10u32; 20]);
+        assert_eq!(summaries[2].sum, 40.0);
+    }
+}
@@ -0,0 +1,641 @@
+//! Sample Demultiplexing
+//!
+//! Assigns reads to samples by comparing the bases of their sample barcode (`B`) segments with
+//! the expected barcodes in a [`SampleSheet`], following fgbio's `DemuxFastqs`:
+//!
+//! - the sample barcode segments of all reads are concatenated, in order, into the observed
+//!   barcode;
+//! - a no-call (`N`) in an expected barcode matches any base, while a no-call in the observed
+//!   barcode is a mismatch;
+//! - reads with more than [`DemuxOptions::max_no_calls`] no-calls in the observed barcode are
+//!   unmatched; and
+//! - a read is assigned to the sample with the fewest mismatches if it has at most
+//!   [`DemuxOptions::max_mismatches`] mismatches, and the next best sample has at least
+//!   [`DemuxOptions::min_mismatch_delta`] more.  Otherwise the read is unmatched.
+//!
+//! [`DemuxMetrics`] tallies the assignments into per-sample and unmatched metrics.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::MultiReadStructure;
+//! use read_structure::demux::{Demultiplexer, DemuxMetrics, DemuxOptions, SampleSheet};
+//!
+//! let sheet = "Sample_ID,index,index2\ns1,AAAAAAAA,CCCCCCCC\ns2,GGGGGGGG,TTTTTTTT\n";
+//! let sheet = SampleSheet::from_reader(sheet.as_bytes()).unwrap();
+//! let mrs = MultiReadStructure::from_str("+T 8B 8B +T").unwrap();
+//! let demux = Demultiplexer::new(sheet, mrs, DemuxOptions::default()).unwrap();
+//!
+//! let reads: [&[u8]; 4] = [b"ACGTACGT", b"AAAAAAAT", b"CCCCCCCC", b"TTTTGGGG"];
+//! let assignment = demux.assign(&reads).unwrap();
+//! assert_eq!(assignment.sample, Some(0));
+//! assert_eq!(assignment.mismatches, 1);
+//!
+//! let mut metrics = DemuxMetrics::new(demux.sample_sheet());
+//! metrics.add(&assignment);
+//! assert_eq!(metrics.metrics()[0].one_mismatch_matches, 1);
+//! ```
+
+use std::collections::HashSet;
+use std::io::BufRead;
+
+use crate::extraction::Extraction;
+use crate::multi_read_structure::MultiReadStructure;
+use crate::ReadStructureError;
+
+/// The sample ID reported for unmatched reads in [`DemuxMetrics`].
+pub const UNMATCHED_SAMPLE_ID: &str = "unmatched";
+
+/// The names (compared case-insensitively, ignoring `_`) of sample sheet columns holding the
+/// sample ID.
+const SAMPLE_ID_COLUMNS: [&str; 3] = ["sampleid", "sample", "samplename"];
+
+/// The names (compared case-insensitively, ignoring `_`) of sample sheet columns holding the
+/// sample barcode, or parts of it, in the order they are concatenated.
+const BARCODE_COLUMNS: [&str; 4] = ["barcode", "samplebarcode", "index", "index2"];
+
+/// A sample and its expected sample barcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// The ID of the sample.
+    pub sample_id: String,
+    /// The expected bases of all sample barcode segments, concatenated and upper-cased.
+    pub barcode: Vec<u8>,
+    /// The length of each part of the barcode (e.g. the i7 and i5 indices), in order.
+    part_lengths: Vec<usize>,
+}
+
+impl Sample {
+    /// Builds a new [`Sample`].  Any `-` or `+` separating the parts of a multi-part barcode
+    /// (e.g. `ACGT-TTGA`) are removed, but the parts are kept (see [`Sample::parts`]).
+    pub fn new(sample_id: &str, barcode: &[u8]) -> Self {
+        let parts = barcode.split(|b| matches!(b, b'-' | b'+')).filter(|p| !p.is_empty());
+        let part_lengths = parts.clone().map(<[u8]>::len).collect();
+        let barcode = parts.flatten().map(u8::to_ascii_uppercase).collect();
+        Sample { sample_id: sample_id.to_owned(), barcode, part_lengths }
+    }
+
+    /// Returns the parts of the barcode, as separated by `-` or `+` or given in separate sample
+    /// sheet columns.
+    pub fn parts(&self) -> impl Iterator<Item = &[u8]> {
+        self.part_lengths.iter().scan(0, |start, &length| {
+            *start += length;
+            Some(&self.barcode[*start - length..*start])
+        })
+    }
+}
+
+/// The samples to demultiplex reads into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SampleSheet {
+    /// The samples, in the order given.
+    samples: Vec<Sample>,
+}
+
+impl SampleSheet {
+    /// Builds a new [`SampleSheet`].
+    ///
+    /// # Errors
+    ///
+    /// - If no samples are given.
+    /// - If any sample ID or barcode is given more than once.
+    /// - If any sample ID is [`UNMATCHED_SAMPLE_ID`] or contains a path separator, since sample
+    ///   IDs are used in output file names.
+    /// - If any barcode is empty, contains bases other than `ACGTN`, or differs in length from
+    ///   the others.
+    pub fn new(samples: Vec<Sample>) -> Result<Self, ReadStructureError> {
+        let invalid = |message: String| Err(ReadStructureError::SampleSheetInvalid(message));
+        if samples.is_empty() {
+            return invalid("no samples".to_owned());
+        }
+        let (mut ids, mut barcodes) = (HashSet::new(), HashSet::new());
+        for sample in &samples {
+            let barcode = String::from_utf8_lossy(&sample.barcode);
+            if sample.barcode.is_empty() || !sample.barcode.iter().all(|b| b"ACGTN".contains(b)) {
+                return invalid(format!(
+                    "invalid barcode for sample {}: {}",
+                    sample.sample_id, barcode
+                ));
+            }
+            if sample.barcode.len() != samples[0].barcode.len() {
+                return invalid(format!(
+                    "barcode for sample {} has length {}, expected {}",
+                    sample.sample_id,
+                    sample.barcode.len(),
+                    samples[0].barcode.len()
+                ));
+            }
+            if sample.sample_id == UNMATCHED_SAMPLE_ID || sample.sample_id.contains(['/', '\\']) {
+                return invalid(format!("invalid sample ID: {}", sample.sample_id));
+            }
+            if !ids.insert(&sample.sample_id) {
+                return invalid(format!("duplicate sample ID: {}", sample.sample_id));
+            }
+            if !barcodes.insert(&sample.barcode) {
+                return invalid(format!("duplicate barcode: {}", barcode));
+            }
+        }
+        Ok(SampleSheet { samples })
+    }
+
+    /// Reads a [`SampleSheet`] from a comma or tab delimited file with a header line.  See
+    /// [`SampleSheet::read_samples`] for the format.
+    ///
+    /// # Errors
+    ///
+    /// - If the samples could not be read (see [`SampleSheet::read_samples`]).
+    /// - If the samples are invalid (see [`SampleSheet::new`]).
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, ReadStructureError> {
+        SampleSheet::new(Self::read_samples(reader)?)
+    }
+
+    /// Reads the samples from a comma or tab delimited file with a header line, without checking
+    /// that they form a valid [`SampleSheet`] (e.g. to analyze barcodes of differing lengths).  The
+    /// sample ID is read from the `Sample_ID` (or `sample`) column and the barcode from the
+    /// `barcode` column, or the concatenation of the `index` and `index2` columns.  For Illumina
+    /// sample sheets with `[Section]` headers, only the `[Data]` (or `[BCLConvert_Data]`)
+    /// section is read, and a sample listed with the same barcode in several lanes is read once.
+    /// Blank lines and lines starting with `#` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// - If reading fails.
+    /// - If the header has no sample ID or barcode column, or a line has too few fields.
+    pub fn read_samples<R: BufRead>(reader: R) -> Result<Vec<Sample>, ReadStructureError> {
+        let invalid = |message: String| ReadStructureError::SampleSheetInvalid(message);
+        let mut in_data = true;
+        let mut columns: Option<(usize, Vec<usize>)> = None;
+        let mut samples = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.starts_with('[') {
+                let section = line.split(']').next().unwrap_or_default().to_lowercase();
+                in_data = section == "[data" || section == "[bclconvert_data";
+                columns = None;
+                continue;
+            }
+            if !in_data || line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let delimiter = if line.contains('\t') { '\t' } else { ',' };
+            let fields: Vec<&str> = line.split(delimiter).map(str::trim).collect();
+            match &columns {
+                None => {
+                    let names: Vec<String> =
+                        fields.iter().map(|f| f.to_lowercase().replace('_', "")).collect();
+                    let find = |candidates: &[&str]| {
+                        candidates
+                            .iter()
+                            .filter_map(|c| names.iter().position(|n| n == c))
+                            .collect::<Vec<_>>()
+                    };
+                    let id = find(&SAMPLE_ID_COLUMNS).first().copied();
+                    let barcode = match find(&BARCODE_COLUMNS[..2]).first() {
+                        Some(&column) => vec![column],
+                        None => find(&BARCODE_COLUMNS[2..]),
+                    };
+                    match (id, barcode.is_empty()) {
+                        (Some(id), false) => columns = Some((id, barcode)),
+                        _ => {
+                            return Err(invalid(format!(
+                                "no sample ID and barcode columns in: {}",
+                                line
+                            )))
+                        }
+                    }
+                }
+                Some((id, barcode)) => {
+                    let field = |i: usize| {
+                        fields
+                            .get(i)
+                            .copied()
+                            .ok_or_else(|| invalid(format!("too few fields: {}", line)))
+                    };
+                    let parts = barcode.iter().map(|&i| field(i)).collect::<Result<Vec<_>, _>>()?;
+                    let sample = Sample::new(field(*id)?, parts.join("-").as_bytes());
+                    if !samples.contains(&sample) {
+                        samples.push(sample);
+                    }
+                }
+            }
+        }
+        Ok(samples)
+    }
+
+    /// Returns the samples, in the order given.
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns true if there are no samples, which [`SampleSheet::new`] does not allow.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the length of the sample barcodes.
+    pub fn barcode_length(&self) -> usize {
+        self.samples[0].barcode.len()
+    }
+}
+
+/// Thresholds for assigning reads to samples.  See [the module level documentation](self) for
+/// how they are applied.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DemuxOptions {
+    /// The maximum number of mismatches between the observed and expected barcodes.
+    pub max_mismatches: usize,
+    /// The minimum difference in mismatches between the best and next best samples.
+    pub min_mismatch_delta: usize,
+    /// The maximum number of no-calls in the observed barcode.
+    pub max_no_calls: usize,
+}
+
+impl Default for DemuxOptions {
+    /// Returns the defaults of fgbio's `DemuxFastqs`: one mismatch, a delta of two, and two
+    /// no-calls.
+    fn default() -> Self {
+        DemuxOptions { max_mismatches: 1, min_mismatch_delta: 2, max_no_calls: 2 }
+    }
+}
+
+/// The sample a read was assigned to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    /// The index of the sample in the sample sheet, or `None` if the read is unmatched.
+    pub sample: Option<usize>,
+    /// The number of mismatches to the closest sample barcode.
+    pub mismatches: usize,
+    /// The number of no-calls in the observed barcode.
+    pub no_calls: usize,
+}
+
+/// Returns the number of mismatches between an observed and an expected barcode.  A no-call in
+/// the expected barcode matches any base; a no-call in the observed barcode is a mismatch.
+/// Bases are compared case-insensitively, and any extra bases in the longer barcode are
+/// mismatches.
+pub fn count_mismatches(observed: &[u8], expected: &[u8]) -> usize {
+    let mismatches = observed
+        .iter()
+        .zip(expected)
+        .filter(|(o, e)| !e.eq_ignore_ascii_case(&b'N') && !o.eq_ignore_ascii_case(e))
+        .count();
+    mismatches + observed.len().abs_diff(expected.len())
+}
+
+/// Assigns reads to the samples in a [`SampleSheet`] by their sample barcode segments.
+#[derive(Debug, Clone)]
+pub struct Demultiplexer {
+    /// The samples to assign reads to.
+    sample_sheet: SampleSheet,
+    /// The read structures locating the sample barcode segments.
+    read_structures: MultiReadStructure,
+    /// The thresholds for assigning reads.
+    options: DemuxOptions,
+}
+
+impl Demultiplexer {
+    /// Builds a new [`Demultiplexer`].
+    ///
+    /// # Errors
+    ///
+    /// - If the read structures have no sample barcode segments, or any of variable length.
+    /// - If the total length of the sample barcode segments differs from the length of the
+    ///   barcodes in the sample sheet.
+    pub fn new(
+        sample_sheet: SampleSheet,
+        read_structures: MultiReadStructure,
+        options: DemuxOptions,
+    ) -> Result<Self, ReadStructureError> {
+        let lengths: Option<Vec<usize>> =
+            read_structures.sample_barcodes().map(|(_, segment)| segment.length).collect();
+        let length = lengths.filter(|l| !l.is_empty()).map(|l| l.iter().sum::<usize>());
+        if length != Some(sample_sheet.barcode_length()) {
+            return Err(ReadStructureError::SampleSheetInvalid(format!(
+                "barcodes of length {} do not match the fixed length sample barcode segments of {}",
+                sample_sheet.barcode_length(),
+                read_structures
+            )));
+        }
+        Ok(Demultiplexer { sample_sheet, read_structures, options })
+    }
+
+    /// Returns the samples reads are assigned to.
+    pub fn sample_sheet(&self) -> &SampleSheet {
+        &self.sample_sheet
+    }
+
+    /// Returns the read structures locating the sample barcode segments.
+    pub fn read_structures(&self) -> &MultiReadStructure {
+        &self.read_structures
+    }
+
+    /// Returns the thresholds for assigning reads.
+    pub fn options(&self) -> &DemuxOptions {
+        &self.options
+    }
+
+    /// Returns the concatenated bases of the sample barcode segments of the given reads, each
+    /// in the orientation of its read (see [`crate::orientation`]).
+    ///
+    /// # Errors
+    ///
+    /// - If the number of reads differs from the number of read structures.
+    /// - If any read is too short for its read structure.
+    pub fn sample_barcode(&self, reads: &[&[u8]]) -> Result<Vec<u8>, ReadStructureError> {
+        Ok(extraction_barcode(&self.read_structures.extract(reads)?))
+    }
+
+    /// Assigns the read with the given observed sample barcode to a sample.
+    pub fn assign_barcode(&self, barcode: &[u8]) -> Assignment {
+        let no_calls = barcode.iter().filter(|b| b.eq_ignore_ascii_case(&b'N')).count();
+        let (mut best, mut best_mismatches, mut next_mismatches) = (0, usize::MAX, usize::MAX);
+        for (index, sample) in self.sample_sheet.samples.iter().enumerate() {
+            let mismatches = count_mismatches(barcode, &sample.barcode);
+            if mismatches < best_mismatches {
+                next_mismatches = best_mismatches;
+                best_mismatches = mismatches;
+                best = index;
+            } else if mismatches < next_mismatches {
+                next_mismatches = mismatches;
+            }
+        }
+        let matched = no_calls <= self.options.max_no_calls
+            && best_mismatches <= self.options.max_mismatches
+            && next_mismatches - best_mismatches >= self.options.min_mismatch_delta;
+        Assignment { sample: matched.then_some(best), mismatches: best_mismatches, no_calls }
+    }
+
+    /// Assigns the given reads (one per read structure) to a sample by their sample barcode
+    /// segments.
+    ///
+    /// # Errors
+    ///
+    /// - If the number of reads differs from the number of read structures.
+    /// - If any read is too short for its read structure.
+    pub fn assign(&self, reads: &[&[u8]]) -> Result<Assignment, ReadStructureError> {
+        Ok(self.assign_barcode(&self.sample_barcode(reads)?))
+    }
+
+    /// Assigns an extraction made with this demultiplexer's read structures to a sample by its
+    /// sample barcode segments.
+    pub fn assign_extraction(&self, extraction: &Extraction<'_>) -> Assignment {
+        self.assign_barcode(&extraction_barcode(extraction))
+    }
+}
+
+/// Returns the concatenated bases of the sample barcode segments of the extraction, each in the
+/// orientation of its read.
+fn extraction_barcode(extraction: &Extraction<'_>) -> Vec<u8> {
+    extraction.sample_barcodes().flat_map(|segment| segment.oriented_bases().to_vec()).collect()
+}
+
+/// Demultiplexing metrics for one sample, or for unmatched reads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleMetrics {
+    /// The ID of the sample, or [`UNMATCHED_SAMPLE_ID`].
+    pub sample_id: String,
+    /// The expected barcode, or all no-calls for unmatched reads.
+    pub barcode: String,
+    /// The number of templates (reads or read pairs) assigned.
+    pub templates: u64,
+    /// The number of templates assigned with no mismatches.
+    pub perfect_matches: u64,
+    /// The number of templates assigned with one mismatch.
+    pub one_mismatch_matches: u64,
+    /// The fraction of all templates assigned.
+    pub fraction_of_templates: f64,
+    /// The ratio of templates assigned to the templates of the sample with the most.
+    pub ratio_to_best: f64,
+    /// The ratio of templates assigned to the mean templates of all samples.
+    pub ratio_to_mean: f64,
+}
+
+/// Tallies [`Assignment`]s into per-sample and unmatched [`SampleMetrics`].
+#[derive(Debug, Clone)]
+pub struct DemuxMetrics {
+    /// The metrics for each sample, followed by unmatched reads.  Only the counts are kept up
+    /// to date; the ratios are computed by [`DemuxMetrics::metrics`].
+    metrics: Vec<SampleMetrics>,
+}
+
+impl DemuxMetrics {
+    /// Builds new, empty [`DemuxMetrics`] for the samples in the sample sheet.
+    pub fn new(sample_sheet: &SampleSheet) -> Self {
+        let metric = |sample_id: &str, barcode: String| SampleMetrics {
+            sample_id: sample_id.to_owned(),
+            barcode,
+            templates: 0,
+            perfect_matches: 0,
+            one_mismatch_matches: 0,
+            fraction_of_templates: 0.0,
+            ratio_to_best: 0.0,
+            ratio_to_mean: 0.0,
+        };
+        let mut metrics: Vec<SampleMetrics> = sample_sheet
+            .samples()
+            .iter()
+            .map(|s| metric(&s.sample_id, String::from_utf8_lossy(&s.barcode).into_owned()))
+            .collect();
+        metrics.push(metric(UNMATCHED_SAMPLE_ID, "N".repeat(sample_sheet.barcode_length())));
+        DemuxMetrics { metrics }
+    }
+
+    /// Adds an assignment to the metrics.
+    pub fn add(&mut self, assignment: &Assignment) {
+        let unmatched = self.metrics.len() - 1;
+        let metric = &mut self.metrics[assignment.sample.unwrap_or(unmatched).min(unmatched)];
+        metric.templates += 1;
+        if assignment.sample.is_some() {
+            match assignment.mismatches {
+                0 => metric.perfect_matches += 1,
+                1 => metric.one_mismatch_matches += 1,
+                _ => (),
+            }
+        }
+    }
+
+    /// Returns the metrics for each sample, in sample sheet order, followed by the metrics for
+    /// unmatched reads.
+    pub fn metrics(&self) -> Vec<SampleMetrics> {
+        let samples = &self.metrics[..self.metrics.len() - 1];
+        let total: u64 = self.metrics.iter().map(|m| m.templates).sum();
+        let best = samples.iter().map(|m| m.templates).max().unwrap_or(0);
+        let mean = samples.iter().map(|m| m.templates).sum::<u64>() as f64 / samples.len() as f64;
+        let ratio = |n: u64, d: f64| if d > 0.0 { n as f64 / d } else { 0.0 };
+        self.metrics
+            .iter()
+            .map(|m| SampleMetrics {
+                fraction_of_templates: ratio(m.templates, total as f64),
+                ratio_to_best: ratio(m.templates, best as f64),
+                ratio_to_mean: ratio(m.templates, mean),
+                ..m.clone()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::str::FromStr;
+
+    use crate::demux::{
+        count_mismatches, Assignment, Demultiplexer, DemuxMetrics, DemuxOptions, Sample,
+        SampleSheet, UNMATCHED_SAMPLE_ID,
+    };
+    use crate::multi_read_structure::MultiReadStructure;
+
+    /// Returns a sample sheet with samples `s1`, `s2`, ... with the given barcodes.
+    pub(crate) fn sheet(barcodes: &[&str]) -> SampleSheet {
+        let samples = barcodes
+            .iter()
+            .enumerate()
+            .map(|(i, b)| Sample::new(&format!("s{}", i + 1), b.as_bytes()))
+            .collect();
+        SampleSheet::new(samples).unwrap()
+    }
+
+    fn demux(barcodes: &[&str], read_structures: &str) -> Demultiplexer {
+        let mrs = MultiReadStructure::from_str(read_structures).unwrap();
+        Demultiplexer::new(sheet(barcodes), mrs, DemuxOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_count_mismatches() {
+        assert_eq!(count_mismatches(b"ACGT", b"ACGT"), 0);
+        assert_eq!(count_mismatches(b"acgt", b"ACGA"), 1);
+        assert_eq!(count_mismatches(b"ACGT", b"ACNN"), 0);
+        assert_eq!(count_mismatches(b"ACNN", b"ACGT"), 2);
+        assert_eq!(count_mismatches(b"ACG", b"ACGT"), 1);
+    }
+
+    #[test]
+    fn test_sample_sheet_from_reader() {
+        let csv = "Sample_ID,Sample_Name,index,index2\ns1,a,ACGT,TTTT\ns2,b,GGGG,CCCC\n";
+        let sheet = SampleSheet::from_reader(csv.as_bytes()).unwrap();
+        assert_eq!(sheet.samples()[1], Sample::new("s2", b"GGGG-CCCC"));
+        let parts: Vec<&[u8]> = sheet.samples()[1].parts().collect();
+        assert_eq!(parts, [b"GGGG", b"CCCC"]);
+        assert_eq!(sheet.barcode_length(), 8);
+
+        let tsv = "# comment\nsample\tbarcode\ns1\tacgt-tttt\n\ns2\tGGGG+CCCC\n";
+        assert_eq!(SampleSheet::from_reader(tsv.as_bytes()).unwrap(), sheet);
+
+        let illumina = "[Header]\nFileFormatVersion,2\n\n[BCLConvert_Settings]\n\
+            OverrideCycles,Y151;I4;I4;Y151\n\n[BCLConvert_Data]\nLane,Sample_ID,Index,Index2\n\
+            1,s1,ACGT,TTTT\n1,s2,GGGG,CCCC\n\n[Cloud_Data]\n";
+        assert_eq!(SampleSheet::from_reader(illumina.as_bytes()).unwrap(), sheet);
+
+        // Samples in several lanes, and other data sections, as written by BCL Convert v2
+        let illumina = "[Header]\nFileFormatVersion,2\n\n[BCLConvert_Data]\n\
+            Lane,Sample_ID,Index,Index2\n1,s1,ACGT,TTTT\n1,s2,GGGG,CCCC\n2,s1,ACGT,TTTT\n\
+            2,s2,GGGG,CCCC\n\n[Cloud_Data]\nSample_ID,ProjectName,LibraryName\n\
+            s1,p1,s1_ACGT_TTTT\ns2,p1,s2_GGGG_CCCC\n";
+        assert_eq!(SampleSheet::from_reader(illumina.as_bytes()).unwrap(), sheet);
+    }
+
+    #[test]
+    fn test_sample_sheet_invalid() {
+        let read = |s: &str| SampleSheet::from_reader(s.as_bytes()).unwrap_err().to_string();
+        assert_eq!(read(""), "Invalid sample sheet: no samples");
+        assert!(read("name,index\ns1,ACGT\n").contains("no sample ID and barcode columns"));
+        assert!(read("sample,barcode\ns1\n").contains("too few fields"));
+        assert!(read("sample,barcode\ns1,ACGT\ns1,GGGG\n").contains("duplicate sample ID: s1"));
+        assert!(read("sample,barcode\ns1,ACGT\ns2,ACGT\n").contains("duplicate barcode: ACGT"));
+        assert!(read("sample,barcode\ns1,ACGT\ns2,GGG\n").contains("has length 3, expected 4"));
+        assert!(read("sample,barcode\ns1,ACGX\n").contains("invalid barcode for sample s1"));
+        assert!(read("sample,barcode\nunmatched,ACGT\n").contains("invalid sample ID: unmatched"));
+        assert!(read("sample,barcode\n../s1,ACGT\n").contains("invalid sample ID: ../s1"));
+        assert!(read("sample,barcode\ns1\\a,ACGT\n").contains("invalid sample ID: s1\\a"));
+    }
+
+    #[test]
+    fn test_demultiplexer_new() {
+        let mrs = |s: &str| MultiReadStructure::from_str(s).unwrap();
+        let new =
+            |s: &str| Demultiplexer::new(sheet(&["ACGTACGT"]), mrs(s), DemuxOptions::default());
+        assert!(new("+T 4B 4B").is_ok());
+        assert!(new("4B4S+T").is_err());
+        assert!(new("+T").is_err());
+        assert!(new("4B+B").is_err());
+    }
+
+    #[test]
+    fn test_assign() {
+        let demux = demux(&["AAAAAAAA", "CCCCCCCC", "AAAAAATT"], "4B+T 4B");
+        let assign = |r1: &[u8], r2: &[u8]| demux.assign(&[r1, r2]).unwrap();
+        // Perfect and one-mismatch matches
+        assert_eq!(
+            assign(b"CCCCGG", b"CCCC"),
+            Assignment { sample: Some(1), mismatches: 0, no_calls: 0 }
+        );
+        assert_eq!(assign(b"CCCAGG", b"CCCC").sample, Some(1));
+        // Too many mismatches
+        assert_eq!(assign(b"CCAAGG", b"CCCC").sample, None);
+        // Too close to the next best sample: one mismatch from s1 and s3
+        let assignment = assign(b"AAAAGG", b"AATA");
+        assert_eq!(assignment, Assignment { sample: None, mismatches: 1, no_calls: 0 });
+        // No-calls are mismatches
+        assert_eq!(assign(b"CCCNGG", b"CCCC").sample, Some(1));
+        assert_eq!(assign(b"CCNNGG", b"CCCC").sample, None);
+        // Looser mismatch thresholds, but a stricter limit on no-calls
+        let options = DemuxOptions { max_mismatches: 2, min_mismatch_delta: 1, max_no_calls: 1 };
+        let demux = Demultiplexer::new(
+            demux.sample_sheet().clone(),
+            demux.read_structures().clone(),
+            options,
+        )
+        .unwrap();
+        assert_eq!(demux.assign_barcode(b"CCNCCCAC").sample, Some(1));
+        assert_eq!(demux.assign_barcode(b"CCNCCNCC").sample, None);
+        assert!(demux.assign(&[b"CCC", b"CCCC"]).is_err());
+    }
+
+    #[test]
+    fn test_assign_extraction() {
+        let demux = demux(&["AAAAAAAA", "CCCCCCCC"], "4B+T 4B");
+        let extraction = demux.read_structures().extract(&[b"CCCCGG", b"CCCA"]).unwrap();
+        assert_eq!(
+            demux.assign_extraction(&extraction),
+            Assignment { sample: Some(1), mismatches: 1, no_calls: 0 }
+        );
+    }
+
+    #[test]
+    fn test_assign_expected_no_calls() {
+        let demux = demux(&["ACGTNNNN", "TTTTNNNN"], "+T 8B");
+        assert_eq!(demux.assign_barcode(b"ACGTGGGG").mismatches, 0);
+        assert_eq!(demux.assign_barcode(b"ACGAGGGG").sample, Some(0));
+    }
+
+    #[test]
+    fn test_metrics() {
+        let demux = demux(&["AAAA", "CCCC", "GGGG"], "4B+T");
+        let mut metrics = DemuxMetrics::new(demux.sample_sheet());
+        for barcode in ["AAAA", "AAAA", "AAAT", "AATT", "CCCC", "AAAC", "CCCC", "CCCC"] {
+            metrics.add(&demux.assign_barcode(barcode.as_bytes()));
+        }
+        let metrics = metrics.metrics();
+        assert_eq!(metrics.len(), 4);
+        assert_eq!(metrics[0].sample_id, "s1");
+        assert_eq!(metrics[0].barcode, "AAAA");
+        assert_eq!(metrics[0].templates, 4);
+        assert_eq!(metrics[0].perfect_matches, 2);
+        assert_eq!(metrics[0].one_mismatch_matches, 2);
+        assert!((metrics[0].fraction_of_templates - 4.0 / 8.0).abs() < 1e-9);
+        assert!((metrics[0].ratio_to_best - 1.0).abs() < 1e-9);
+        assert!((metrics[0].ratio_to_mean - 4.0 / (7.0 / 3.0)).abs() < 1e-9);
+        assert_eq!(metrics[1].templates, 3);
+        assert_eq!(metrics[2].templates, 0);
+        assert_eq!(metrics[3].sample_id, UNMATCHED_SAMPLE_ID);
+        assert_eq!(metrics[3].barcode, "NNNN");
+        assert_eq!(metrics[3].templates, 1);
+        assert_eq!(metrics[3].perfect_matches, 0);
+    }
+}
@@ -0,0 +1,696 @@
+//! Read Structure Dialects
+//!
+//! Type [`Dialect`] names the other notations commonly used to describe read layouts, and
+//! [`parse`] and [`format`] translate between them and [`MultiReadStructure`]s:
+//!
+//! - `fgbio`: read structures, one per read separated by spaces (e.g. `16C12M +T`);
+//! - `bcl-convert`: the `OverrideCycles` sample sheet setting, with `Y` (template), `I` (index),
+//!   `U` (UMI) and `N` (skip) cycles, one read per `;` (e.g. `U8Y143;I8;I8;Y151`);
+//! - `bcl2fastq`: the `--use-bases-mask` option, with `Y`, `I` and `N` cycles, one read per `,`,
+//!   where `*` means all remaining cycles (e.g. `Y*,I8,I8,Y*`);
+//! - `kallisto`: the `-x` technology string `bc:umi:seq`, each a list of `file,start,stop`
+//!   ranges where a stop of zero means the end of the read (e.g. `0,0,16:0,16,28:1,0,0`); and
+//! - `starsolo`: STARsolo's `--soloCBposition` and `--soloUMIposition` options for the barcode
+//!   read, given as `startAnchor_start_endAnchor_end` with zero-based inclusive positions
+//!   (e.g. `--soloType CB_UMI_Complex --soloCBposition 0_0_0_15 --soloUMIposition 0_16_0_27`).
+//!   STARsolo describes only the barcode read, so it is parsed as the barcode read followed by a
+//!   single `+T` template read; when running STAR, list the template read(s) first in
+//!   `--readFilesIn` and the barcode read last.
+//!
+//! Constructs the target dialect cannot represent (e.g. cellular barcodes in `bcl-convert`, or
+//! variable length segments where explicit cycle counts are required) are reported as
+//! [`ReadStructureError::DialectUnsupported`] errors.  Variable length segments can be given a
+//! fixed length with [`resolve_read_lengths`] before formatting.
+//!
+//! # Example
+//!
+//! ```rust
+//! use read_structure::dialect::{convert, Dialect};
+//!
+//! let override_cycles = convert("8M143T 8B 8B +T", Dialect::Fgbio, Dialect::BclConvert);
+//! assert!(override_cycles.is_err()); // the last read has no cycle count
+//!
+//! let override_cycles = convert("8M143T 8B 8B 151T", Dialect::Fgbio, Dialect::BclConvert).unwrap();
+//! assert_eq!(override_cycles, "U8Y143;I8;I8;Y151");
+//! let kallisto = convert("16C12M +T", Dialect::Fgbio, Dialect::Kallisto).unwrap();
+//! assert_eq!(kallisto, "0,0,16:0,16,28:1,0,0");
+//! ```
+
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, IntoStaticStr};
+
+use crate::multi_read_structure::MultiReadStructure;
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// A notation for describing read layouts.  See [the module level documentation](self) for more.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Display, EnumIter, IntoStaticStr, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    /// fgbio read structures.
+    #[strum(serialize = "fgbio")]
+    Fgbio,
+    /// bcl-convert `OverrideCycles`.
+    #[strum(serialize = "bcl-convert")]
+    BclConvert,
+    /// bcl2fastq `--use-bases-mask`.
+    #[strum(serialize = "bcl2fastq")]
+    Bcl2fastq,
+    /// kallisto `-x` technology strings.
+    #[strum(serialize = "kallisto")]
+    Kallisto,
+    /// STARsolo barcode position options.
+    #[strum(serialize = "starsolo")]
+    StarSolo,
+}
+
+impl Dialect {
+    /// Returns the name of the dialect.
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = ReadStructureError;
+
+    /// Resolves a [`Dialect`] by name, ignoring case and treating `_` the same as `-`.
+    ///
+    /// # Errors
+    ///
+    /// - If no dialect has the given name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase().replace('_', "-");
+        Dialect::iter()
+            .find(|d| d.name() == name)
+            .ok_or_else(|| ReadStructureError::DialectUnknown(s.to_owned()))
+    }
+}
+
+/// Parses read structures written in the given dialect.
+///
+/// # Errors
+///
+/// - If the value is not valid in the dialect.
+pub fn parse(value: &str, dialect: Dialect) -> Result<MultiReadStructure, ReadStructureError> {
+    match dialect {
+        Dialect::Fgbio => MultiReadStructure::from_str(value),
+        Dialect::BclConvert => parse_cycles(value, dialect),
+        Dialect::Bcl2fastq => parse_cycles(value, dialect),
+        Dialect::Kallisto => parse_kallisto(value),
+        Dialect::StarSolo => parse_starsolo(value),
+    }
+}
+
+/// Formats read structures in the given dialect.
+///
+/// # Errors
+///
+/// - If the read structures contain constructs the dialect cannot represent.
+pub fn format(
+    read_structures: &MultiReadStructure,
+    dialect: Dialect,
+) -> Result<String, ReadStructureError> {
+    match dialect {
+        // Segment labels are not part of the fgbio syntax
+        Dialect::Fgbio => Ok(read_structures
+            .iter()
+            .map(|rs| rs.iter().map(ToString::to_string).collect::<String>())
+            .collect::<Vec<_>>()
+            .join(" ")),
+        Dialect::BclConvert => format_cycles(read_structures, dialect),
+        Dialect::Bcl2fastq => format_cycles(read_structures, dialect),
+        Dialect::Kallisto => format_kallisto(read_structures),
+        Dialect::StarSolo => format_starsolo(read_structures),
+    }
+}
+
+/// Converts read structures from one dialect to another.
+///
+/// # Errors
+///
+/// - If the value is not valid in the source dialect.
+/// - If the read structures contain constructs the target dialect cannot represent.
+pub fn convert(value: &str, from: Dialect, to: Dialect) -> Result<String, ReadStructureError> {
+    format(&parse(value, from)?, to)
+}
+
+/// Returns the read structures with any variable length segment given the length that fills
+/// the read, for reads of the given lengths (in cycles).
+///
+/// # Errors
+///
+/// - If the number of read lengths differs from the number of reads.
+/// - If a read structure does not fit its read length.
+pub fn resolve_read_lengths(
+    read_structures: &MultiReadStructure,
+    read_lengths: &[usize],
+) -> Result<MultiReadStructure, ReadStructureError> {
+    if read_lengths.len() != read_structures.number_of_reads() {
+        return Err(ReadStructureError::MismatchingNumberOfReads {
+            expected: read_structures.number_of_reads(),
+            actual: read_lengths.len(),
+        });
+    }
+    let resolved = read_structures
+        .iter()
+        .zip(read_lengths)
+        .map(|(rs, &length)| {
+            // Fixed length read structures must match the reads exactly
+            if rs.fixed_length().map_or(false, |fixed| fixed != length) {
+                return Err(ReadStructureError::ReadLengthMismatch {
+                    read_structure: rs.to_string(),
+                    length,
+                });
+            }
+            rs.resolve(length)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    MultiReadStructure::new(resolved)
+}
+
+/// Returns an error for a construct the dialect cannot represent.
+fn unsupported(dialect: Dialect, construct: String) -> ReadStructureError {
+    ReadStructureError::DialectUnsupported { dialect: dialect.to_string(), construct }
+}
+
+/// Returns an error for a value that is not valid in the dialect.
+fn invalid(dialect: Dialect, value: &str, message: &str) -> ReadStructureError {
+    ReadStructureError::DialectInvalid {
+        dialect: dialect.to_string(),
+        value: value.to_owned(),
+        message: message.to_owned(),
+    }
+}
+
+/// Returns the name of a segment type for error messages.
+fn kind_description(kind: SegmentType) -> String {
+    kind.name().replace('_', " ")
+}
+
+/// Builds a read structure from segment types and lengths, merging adjacent fixed length
+/// segments of the same type.
+fn build(segments: &[(SegmentType, Option<usize>)]) -> Result<ReadStructure, ReadStructureError> {
+    let mut merged: Vec<ReadSegment> = Vec::new();
+    for &(kind, length) in segments {
+        match merged.last_mut() {
+            Some(last) if last.kind == kind && last.length.is_some() && length.is_some() => {
+                last.length = last.length.zip(length).map(|(a, b)| a + b);
+            }
+            _ => merged.push(ReadSegment { offset: 0, length, kind }),
+        }
+    }
+    ReadStructure::new(merged)
+}
+
+/// Returns the cycle letter for a segment type in `OverrideCycles` or `--use-bases-mask`.
+fn cycle_letter(kind: SegmentType, dialect: Dialect) -> Option<char> {
+    match (kind, dialect) {
+        (SegmentType::Template, _) => Some('Y'),
+        (SegmentType::SampleBarcode, _) => Some('I'),
+        (SegmentType::Skip, _) => Some('N'),
+        (SegmentType::MolecularBarcode, Dialect::BclConvert) => Some('U'),
+        _ => None,
+    }
+}
+
+/// Formats `OverrideCycles` or `--use-bases-mask`.
+fn format_cycles(mrs: &MultiReadStructure, dialect: Dialect) -> Result<String, ReadStructureError> {
+    let mut reads = Vec::with_capacity(mrs.number_of_reads());
+    for (index, rs) in mrs.iter().enumerate() {
+        let mut read = String::new();
+        for segment in rs.iter() {
+            let letter = cycle_letter(segment.kind, dialect).ok_or_else(|| {
+                unsupported(
+                    dialect,
+                    format!(
+                        "{} segment {} in read {}",
+                        kind_description(segment.kind),
+                        segment,
+                        index + 1
+                    ),
+                )
+            })?;
+            read.push(letter);
+            match (segment.length, dialect) {
+                (Some(length), _) => read.push_str(&length.to_string()),
+                (None, Dialect::Bcl2fastq) => read.push('*'),
+                (None, _) => {
+                    return Err(unsupported(
+                        dialect,
+                        format!(
+                            "variable length segment {} in read {} (give the read lengths)",
+                            segment,
+                            index + 1
+                        ),
+                    ))
+                }
+            }
+        }
+        reads.push(read);
+    }
+    let separator = if dialect == Dialect::BclConvert { ";" } else { "," };
+    Ok(reads.join(separator))
+}
+
+/// Parses `OverrideCycles` or `--use-bases-mask`.  A letter without a count is a single cycle.
+fn parse_cycles(value: &str, dialect: Dialect) -> Result<MultiReadStructure, ReadStructureError> {
+    let separator = if dialect == Dialect::BclConvert { ';' } else { ',' };
+    let mut read_structures = Vec::new();
+    for read in value.trim().split(separator) {
+        let read = read.trim();
+        let mut segments = Vec::new();
+        let mut chars = read.chars().peekable();
+        while let Some(letter) = chars.next() {
+            let kind = match letter.to_ascii_uppercase() {
+                'Y' => SegmentType::Template,
+                'I' => SegmentType::SampleBarcode,
+                'N' => SegmentType::Skip,
+                'U' if dialect == Dialect::BclConvert => SegmentType::MolecularBarcode,
+                _ => {
+                    return Err(invalid(dialect, value, &format!("unknown cycle type: {}", letter)))
+                }
+            };
+            let mut count = String::new();
+            while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                count.push(digit);
+            }
+            let length = if chars.next_if_eq(&'*').is_some() {
+                if dialect == Dialect::BclConvert || !count.is_empty() {
+                    return Err(invalid(dialect, value, "unexpected '*'"));
+                }
+                None
+            } else if count.is_empty() {
+                Some(1)
+            } else {
+                match count.parse::<usize>() {
+                    Ok(0) | Err(_) => {
+                        return Err(invalid(dialect, value, &format!("invalid count: {}", count)))
+                    }
+                    Ok(n) => Some(n),
+                }
+            };
+            segments.push((kind, length));
+        }
+        if segments.is_empty() {
+            return Err(invalid(dialect, value, "empty read"));
+        }
+        read_structures.push(build(&segments)?);
+    }
+    MultiReadStructure::new(read_structures)
+}
+
+/// Formats a kallisto technology string.
+fn format_kallisto(mrs: &MultiReadStructure) -> Result<String, ReadStructureError> {
+    let mut parts: [Vec<String>; 3] = [Vec::new(), Vec::new(), Vec::new()];
+    for (index, rs) in mrs.iter().enumerate() {
+        for segment in rs.iter() {
+            let part = match segment.kind {
+                SegmentType::CellularBarcode => 0,
+                SegmentType::MolecularBarcode => 1,
+                SegmentType::Template => 2,
+                SegmentType::Skip => continue,
+                kind => {
+                    return Err(unsupported(
+                        Dialect::Kallisto,
+                        format!(
+                            "{} segment {} in read {}",
+                            kind_description(kind),
+                            segment,
+                            index + 1
+                        ),
+                    ))
+                }
+            };
+            let stop = segment.length.map_or(0, |length| segment.offset + length);
+            parts[part].push(format!("{},{},{}", index, segment.offset, stop));
+        }
+    }
+    if parts[2].is_empty() {
+        return Err(unsupported(Dialect::Kallisto, "read structures without template".to_owned()));
+    }
+    let parts: Vec<String> =
+        parts.iter().map(|p| if p.is_empty() { "-1".to_owned() } else { p.join(",") }).collect();
+    Ok(parts.join(":"))
+}
+
+/// Parses a kallisto technology string.
+fn parse_kallisto(value: &str) -> Result<MultiReadStructure, ReadStructureError> {
+    let dialect = Dialect::Kallisto;
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() != 3 {
+        return Err(invalid(dialect, value, "expected bc:umi:seq"));
+    }
+    let kinds =
+        [SegmentType::CellularBarcode, SegmentType::MolecularBarcode, SegmentType::Template];
+    // (file, start, stop, kind) for every range
+    let mut ranges: Vec<(usize, usize, usize, SegmentType)> = Vec::new();
+    for (part, kind) in parts.iter().zip(kinds) {
+        if part.trim() == "-1" {
+            continue;
+        }
+        let numbers = part
+            .split(',')
+            .map(|n| n.trim().parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid(dialect, value, &format!("invalid range: {}", part)))?;
+        if numbers.is_empty() || numbers.len() % 3 != 0 {
+            return Err(invalid(dialect, value, &format!("expected file,start,stop: {}", part)));
+        }
+        for range in numbers.chunks(3) {
+            if range[2] != 0 && range[2] <= range[1] {
+                return Err(invalid(dialect, value, &format!("empty range: {:?}", range)));
+            }
+            ranges.push((range[0], range[1], range[2], kind));
+        }
+    }
+    let files = ranges.iter().map(|r| r.0 + 1).max().unwrap_or(0);
+    let mut read_structures = Vec::with_capacity(files);
+    for file in 0..files {
+        let mut file_ranges: Vec<_> = ranges.iter().filter(|r| r.0 == file).collect();
+        file_ranges.sort_by_key(|r| r.1);
+        let mut segments = Vec::new();
+        let mut position = 0;
+        let mut to_end = false;
+        for &&(_, start, stop, kind) in &file_ranges {
+            if to_end || start < position {
+                return Err(invalid(
+                    dialect,
+                    value,
+                    &format!("overlapping ranges in file {}", file),
+                ));
+            }
+            if start > position {
+                segments.push((SegmentType::Skip, Some(start - position)));
+            }
+            if stop == 0 {
+                segments.push((kind, None));
+                to_end = true;
+            } else {
+                segments.push((kind, Some(stop - start)));
+                position = stop;
+            }
+        }
+        if segments.is_empty() {
+            segments.push((SegmentType::Skip, None));
+        }
+        read_structures.push(build(&segments)?);
+    }
+    MultiReadStructure::new(read_structures)
+}
+
+/// Formats STARsolo barcode position options.
+fn format_starsolo(mrs: &MultiReadStructure) -> Result<String, ReadStructureError> {
+    let dialect = Dialect::StarSolo;
+    let is_barcode = |s: &ReadSegment| {
+        matches!(s.kind, SegmentType::CellularBarcode | SegmentType::MolecularBarcode)
+    };
+    let barcode_reads: Vec<usize> =
+        (0..mrs.number_of_reads()).filter(|&i| mrs[i].iter().any(is_barcode)).collect();
+    let barcode_read = match barcode_reads.as_slice() {
+        [read] => *read,
+        [] => {
+            return Err(unsupported(dialect, "read structures without a barcode read".to_owned()))
+        }
+        _ => {
+            return Err(unsupported(
+                dialect,
+                "cell barcodes or UMIs in more than one read".to_owned(),
+            ))
+        }
+    };
+    for (index, rs) in mrs.iter().enumerate() {
+        for segment in rs.iter() {
+            let supported = if index == barcode_read {
+                is_barcode(segment) || segment.kind == SegmentType::Skip
+            } else {
+                segment.kind == SegmentType::Template && rs.number_of_segments() == 1
+            };
+            if !supported {
+                return Err(unsupported(
+                    dialect,
+                    format!(
+                        "{} segment {} in {} read {}",
+                        kind_description(segment.kind),
+                        segment,
+                        if index == barcode_read { "barcode" } else { "template" },
+                        index + 1
+                    ),
+                ));
+            }
+        }
+    }
+
+    let position = |s: &ReadSegment| {
+        s.length
+            .map(|length| format!("0_{}_0_{}", s.offset, s.offset + length - 1))
+            .ok_or_else(|| unsupported(dialect, format!("variable length barcode segment {}", s)))
+    };
+    let cell_barcodes =
+        mrs[barcode_read].cellular_barcodes().map(position).collect::<Result<Vec<_>, _>>()?;
+    if cell_barcodes.is_empty() {
+        return Err(unsupported(dialect, "a barcode read without a cellular barcode".to_owned()));
+    }
+    let umis =
+        mrs[barcode_read].molecular_barcodes().map(position).collect::<Result<Vec<_>, _>>()?;
+    if umis.len() > 1 {
+        return Err(unsupported(dialect, "more than one molecular barcode segment".to_owned()));
+    }
+    let mut options =
+        format!("--soloType CB_UMI_Complex --soloCBposition {}", cell_barcodes.join(" "));
+    if let Some(umi) = umis.first() {
+        options.push_str(&format!(" --soloUMIposition {}", umi));
+    }
+    Ok(options)
+}
+
+/// Parses STARsolo barcode position options, either `--soloCBposition`/`--soloUMIposition` or
+/// `--soloCBstart`/`--soloCBlen`/`--soloUMIstart`/`--soloUMIlen` (with STAR's defaults for any
+/// not given).
+fn parse_starsolo(value: &str) -> Result<MultiReadStructure, ReadStructureError> {
+    let dialect = Dialect::StarSolo;
+    let mut ranges: Vec<(usize, usize, SegmentType)> = Vec::new();
+    // STAR's defaults for --soloCBstart, --soloCBlen, --soloUMIstart and --soloUMIlen
+    let mut simple = [1, 16, 17, 10];
+    let mut option: Option<&str> = None;
+    for token in value.split_whitespace() {
+        if token.starts_with("--") {
+            option = Some(token);
+            continue;
+        }
+        let number = || {
+            token
+                .parse::<usize>()
+                .map_err(|_| invalid(dialect, value, &format!("invalid number: {}", token)))
+        };
+        match option {
+            Some("--soloType") => (),
+            Some("--soloCBposition") => {
+                ranges.push(parse_position(value, token, SegmentType::CellularBarcode)?)
+            }
+            Some("--soloUMIposition") => {
+                ranges.push(parse_position(value, token, SegmentType::MolecularBarcode)?)
+            }
+            Some("--soloCBstart") => simple[0] = number()?,
+            Some("--soloCBlen") => simple[1] = number()?,
+            Some("--soloUMIstart") => simple[2] = number()?,
+            Some("--soloUMIlen") => simple[3] = number()?,
+            Some(other) => {
+                return Err(invalid(dialect, value, &format!("unsupported option: {}", other)))
+            }
+            None => return Err(invalid(dialect, value, &format!("expected an option: {}", token))),
+        }
+    }
+    if ranges.is_empty() {
+        if simple[0] == 0 || simple[2] == 0 || simple[1] == 0 || simple[3] == 0 {
+            return Err(invalid(dialect, value, "starts and lengths must be positive"));
+        }
+        ranges.push((simple[0] - 1, simple[0] - 1 + simple[1], SegmentType::CellularBarcode));
+        ranges.push((simple[2] - 1, simple[2] - 1 + simple[3], SegmentType::MolecularBarcode));
+    }
+    ranges.sort_by_key(|r| r.0);
+    let mut segments = Vec::new();
+    let mut position = 0;
+    for (start, end, kind) in ranges {
+        if start < position {
+            return Err(invalid(dialect, value, "overlapping barcode positions"));
+        }
+        if start > position {
+            segments.push((SegmentType::Skip, Some(start - position)));
+        }
+        segments.push((kind, Some(end - start)));
+        position = end;
+    }
+    let template = build(&[(SegmentType::Template, None)])?;
+    MultiReadStructure::new(vec![build(&segments)?, template])
+}
+
+/// Parses a STARsolo `startAnchor_start_endAnchor_end` position anchored at the read start,
+/// returning the zero-based start and exclusive end.
+fn parse_position(
+    value: &str,
+    position: &str,
+    kind: SegmentType,
+) -> Result<(usize, usize, SegmentType), ReadStructureError> {
+    let numbers =
+        position.split('_').map(str::parse::<usize>).collect::<Result<Vec<_>, _>>().map_err(
+            |_| invalid(Dialect::StarSolo, value, &format!("invalid position: {}", position)),
+        )?;
+    match numbers.as_slice() {
+        [0, start, 0, end] if end >= start => Ok((*start, end + 1, kind)),
+        [_, _, _, _] if numbers[0] != 0 || numbers[2] != 0 => Err(unsupported(
+            Dialect::StarSolo,
+            format!("positions anchored to the read end or adapter: {}", position),
+        )),
+        _ => Err(invalid(Dialect::StarSolo, value, &format!("invalid position: {}", position))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use strum::IntoEnumIterator;
+
+    use crate::dialect::{convert, format, parse, resolve_read_lengths, Dialect};
+    use crate::multi_read_structure::MultiReadStructure;
+    use crate::ReadStructureError;
+
+    fn mrs(s: &str) -> MultiReadStructure {
+        MultiReadStructure::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_dialect_from_str() {
+        for dialect in Dialect::iter() {
+            assert_eq!(Dialect::from_str(dialect.name()).unwrap(), dialect);
+        }
+        assert_eq!(Dialect::from_str("BCL_CONVERT").unwrap(), Dialect::BclConvert);
+        assert!(Dialect::from_str("cellranger").is_err());
+    }
+
+    #[test]
+    fn test_bcl_convert() {
+        let override_cycles = format(&mrs("8M143T 8B 8B 151T"), Dialect::BclConvert).unwrap();
+        assert_eq!(override_cycles, "U8Y143;I8;I8;Y151");
+        assert_eq!(parse(&override_cycles, Dialect::BclConvert).unwrap(), mrs("8M143T 8B 8B 151T"));
+        assert_eq!(
+            parse("N1Y150;I8N2;Y151", Dialect::BclConvert).unwrap(),
+            mrs("1S150T 8B2S 151T")
+        );
+        assert_eq!(parse("NY3;I8", Dialect::BclConvert).unwrap(), mrs("1S3T 8B"));
+
+        let err = format(&mrs("16C12M 91T"), Dialect::BclConvert).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bcl-convert cannot represent cellular barcode segment 16C in read 1"
+        );
+        let err = format(&mrs("8B +T"), Dialect::BclConvert).unwrap_err();
+        assert!(err.to_string().contains("variable length segment +T in read 2"), "{}", err);
+        assert!(parse("Y*;I8", Dialect::BclConvert).is_err());
+        assert!(parse("Y150;X8", Dialect::BclConvert).is_err());
+        assert!(parse("Y0", Dialect::BclConvert).is_err());
+    }
+
+    #[test]
+    fn test_bcl2fastq() {
+        assert_eq!(format(&mrs("+T 8B 8B +T"), Dialect::Bcl2fastq).unwrap(), "Y*,I8,I8,Y*");
+        assert_eq!(parse("y*,i8n,i8,nY*", Dialect::Bcl2fastq).unwrap(), mrs("+T 8B1S 8B 1S+T"));
+        assert_eq!(parse("YYY,I8", Dialect::Bcl2fastq).unwrap(), mrs("3T 8B"));
+        assert!(matches!(
+            format(&mrs("8M+T"), Dialect::Bcl2fastq),
+            Err(ReadStructureError::DialectUnsupported { .. })
+        ));
+        assert!(parse("Y*10", Dialect::Bcl2fastq).is_err());
+    }
+
+    #[test]
+    fn test_kallisto() {
+        assert_eq!(format(&mrs("16C12M +T"), Dialect::Kallisto).unwrap(), "0,0,16:0,16,28:1,0,0");
+        assert_eq!(
+            format(&mrs("8C4S8C8M +T"), Dialect::Kallisto).unwrap(),
+            "0,0,8,0,12,20:0,20,28:1,0,0"
+        );
+        assert_eq!(format(&mrs("+T"), Dialect::Kallisto).unwrap(), "-1:-1:0,0,0");
+        assert_eq!(parse("0,0,16:0,16,28:1,0,0", Dialect::Kallisto).unwrap(), mrs("16C12M +T"));
+        assert_eq!(parse("1,0,16:1,16,26:2,0,0", Dialect::Kallisto).unwrap(), mrs("+S 16C10M +T"));
+        assert_eq!(parse("0,4,12:-1:0,20,0", Dialect::Kallisto).unwrap(), mrs("4S8C8S+T"));
+
+        let err = format(&mrs("8B +T"), Dialect::Kallisto).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "kallisto cannot represent sample barcode segment 8B in read 1"
+        );
+        assert!(format(&mrs("16C12M"), Dialect::Kallisto).is_err());
+        assert!(parse("0,0,16:0,16", Dialect::Kallisto).is_err());
+        assert!(parse("0,0,16:0,10,28:1,0,0", Dialect::Kallisto).is_err());
+        assert!(parse("0,0,16", Dialect::Kallisto).is_err());
+    }
+
+    #[test]
+    fn test_starsolo() {
+        let options = format(&mrs("16C12M +T"), Dialect::StarSolo).unwrap();
+        assert_eq!(
+            options,
+            "--soloType CB_UMI_Complex --soloCBposition 0_0_0_15 --soloUMIposition 0_16_0_27"
+        );
+        assert_eq!(parse(&options, Dialect::StarSolo).unwrap(), mrs("16C12M +T"));
+        assert_eq!(
+            format(&mrs("+T 9C12S9C8M"), Dialect::StarSolo).unwrap(),
+            "--soloType CB_UMI_Complex --soloCBposition 0_0_0_8 0_21_0_29 --soloUMIposition 0_30_0_37"
+        );
+        assert_eq!(parse("", Dialect::StarSolo).unwrap(), mrs("16C10M +T"));
+        assert_eq!(
+            parse(
+                "--soloCBstart 1 --soloCBlen 16 --soloUMIstart 17 --soloUMIlen 12",
+                Dialect::StarSolo
+            )
+            .unwrap(),
+            mrs("16C12M +T")
+        );
+
+        let err = format(&mrs("16C +T 12M"), Dialect::StarSolo).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "starsolo cannot represent cell barcodes or UMIs in more than one read"
+        );
+        assert!(format(&mrs("16C12M 8B +T"), Dialect::StarSolo).is_err());
+        assert!(format(&mrs("16C12M 10S+T"), Dialect::StarSolo).is_err());
+        assert!(format(&mrs("16C+M +T"), Dialect::StarSolo).is_err());
+        let err = parse("--soloCBposition 2_0_2_15", Dialect::StarSolo).unwrap_err();
+        assert!(matches!(err, ReadStructureError::DialectUnsupported { .. }));
+        assert!(parse("--soloCBposition 0_0_0_15 0_10_0_20", Dialect::StarSolo).is_err());
+        assert!(parse("--soloFeatures Gene", Dialect::StarSolo).is_err());
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(
+            convert("U8Y143;I8;Y151", Dialect::BclConvert, Dialect::Fgbio).unwrap(),
+            "8M143T 8B 151T"
+        );
+        assert_eq!(
+            convert("0,0,16:0,16,28:1,0,0", Dialect::Kallisto, Dialect::StarSolo).unwrap(),
+            "--soloType CB_UMI_Complex --soloCBposition 0_0_0_15 --soloUMIposition 0_16_0_27"
+        );
+        assert!(convert("16C12M +T", Dialect::Fgbio, Dialect::BclConvert).is_err());
+        assert_eq!(
+            convert("8B{i7} 8B{i5} +T", Dialect::Fgbio, Dialect::Fgbio).unwrap(),
+            "8B 8B +T"
+        );
+    }
+
+    #[test]
+    fn test_resolve_read_lengths() {
+        let resolved = resolve_read_lengths(&mrs("8M+T 8B +T"), &[151, 8, 151]).unwrap();
+        assert_eq!(resolved, mrs("8M143T 8B 151T"));
+        assert!(resolve_read_lengths(&mrs("8M+T 8B"), &[151]).is_err());
+        let err = resolve_read_lengths(&mrs("8M+T 8B"), &[151, 10]).unwrap_err();
+        assert_eq!(err.to_string(), "Read structure 8B does not fit reads of length 10");
+        assert!(resolve_read_lengths(&mrs("8M+T"), &[8]).is_err());
+    }
+}
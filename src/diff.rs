@@ -0,0 +1,255 @@
+//! Read Structure Diffs
+//!
+//! Function [`diff`] compares two [`ReadStructure`]s, e.g. when the chemistry of a sample sheet
+//! changes between runs.  The layouts are aligned cycle by cycle, and the result reports:
+//!
+//! - the ranges of cycles whose segment type changed, with the old and new types (or none, where
+//!   one read structure ends before the other);
+//! - the segments whose length changed, where the `n`th segment of each type in one read
+//!   structure is compared with the `n`th segment of the same type in the other; and
+//! - whether the indefinite length (`+`) segment changed its type or starting cycle.
+//!
+//! The [`std::fmt::Display`] form of [`ReadStructureDiff`] is suitable for logs, with cycles
+//! given as one-based inclusive ranges.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::ReadStructure;
+//! use read_structure::diff::diff;
+//!
+//! let old = ReadStructure::from_str("8B+T").unwrap();
+//! let new = ReadStructure::from_str("8B6M+T").unwrap();
+//! let diff = diff(&old, &new);
+//! assert_eq!(
+//!     diff.to_string(),
+//!     "8B+T -> 8B6M+T\n\
+//!      cycles 9-14: T -> M\n\
+//!      M1: none -> 6M\n\
+//!      indefinite segment: +T at cycle 9 -> +T at cycle 15"
+//! );
+//! ```
+
+use strum::IntoEnumIterator;
+
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+
+/// A range of cycles whose segment type changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleChange {
+    /// The zero-based first cycle of the range.
+    pub start: usize,
+    /// The zero-based cycle after the range, or `None` if the range extends to the end of the
+    /// read.
+    pub end: Option<usize>,
+    /// The segment type of the cycles in the old read structure, or `None` if it ends before.
+    pub old: Option<SegmentType>,
+    /// The segment type of the cycles in the new read structure, or `None` if it ends before.
+    pub new: Option<SegmentType>,
+}
+
+/// A segment whose length changed, or that was added or removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentChange {
+    /// The segment type.
+    pub kind: SegmentType,
+    /// The zero-based index of the segment among the segments of its type.
+    pub index: usize,
+    /// The segment in the old read structure, if any.
+    pub old: Option<ReadSegment>,
+    /// The segment in the new read structure, if any.
+    pub new: Option<ReadSegment>,
+}
+
+/// The differences between two read structures.  See [the module level documentation](self)
+/// for more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadStructureDiff {
+    /// The old read structure.
+    pub old: ReadStructure,
+    /// The new read structure.
+    pub new: ReadStructure,
+    /// The ranges of cycles whose segment type changed, in cycle order.
+    pub cycle_changes: Vec<CycleChange>,
+    /// The segments whose length changed, or that were added or removed, by segment type.
+    pub segment_changes: Vec<SegmentChange>,
+}
+
+impl ReadStructureDiff {
+    /// Returns the indefinite length segment of the old read structure, if any.
+    pub fn old_indefinite(&self) -> Option<&ReadSegment> {
+        self.old.last().filter(|s| !s.has_length())
+    }
+
+    /// Returns the indefinite length segment of the new read structure, if any.
+    pub fn new_indefinite(&self) -> Option<&ReadSegment> {
+        self.new.last().filter(|s| !s.has_length())
+    }
+
+    /// Returns true if the indefinite length segment was added or removed, or changed its type
+    /// or starting cycle.
+    pub fn indefinite_changed(&self) -> bool {
+        self.old_indefinite() != self.new_indefinite()
+    }
+
+    /// Returns true if the read structures describe the same layout.
+    pub fn is_empty(&self) -> bool {
+        self.cycle_changes.is_empty() && self.segment_changes.is_empty()
+    }
+}
+
+impl std::fmt::Display for ReadStructureDiff {
+    /// Formats the diff as a header line with the two read structures, followed by one line per
+    /// change.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = |k: Option<SegmentType>| k.map_or("none".to_owned(), |k| k.to_string());
+        let segment = |s: Option<ReadSegment>| s.map_or("none".to_owned(), |s| s.to_string());
+        let indefinite = |s: Option<&ReadSegment>| {
+            s.map_or("none".to_owned(), |s| format!("{} at cycle {}", s, s.offset() + 1))
+        };
+
+        write!(f, "{} -> {}", self.old, self.new)?;
+        if self.is_empty() {
+            return write!(f, ": no changes");
+        }
+        for change in &self.cycle_changes {
+            match change.end {
+                Some(end) if end == change.start + 1 => write!(f, "\ncycle {}", end)?,
+                Some(end) => write!(f, "\ncycles {}-{}", change.start + 1, end)?,
+                None => write!(f, "\ncycles {}+", change.start + 1)?,
+            }
+            write!(f, ": {} -> {}", kind(change.old), kind(change.new))?;
+        }
+        for change in &self.segment_changes {
+            write!(
+                f,
+                "\n{}{}: {} -> {}",
+                change.kind,
+                change.index + 1,
+                segment(change.old),
+                segment(change.new)
+            )?;
+        }
+        if self.indefinite_changed() {
+            write!(
+                f,
+                "\nindefinite segment: {} -> {}",
+                indefinite(self.old_indefinite()),
+                indefinite(self.new_indefinite())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares two read structures.  See [the module level documentation](self) for more.
+pub fn diff(old: &ReadStructure, new: &ReadStructure) -> ReadStructureDiff {
+    // Beyond the fixed length segments of both, each read structure has a single type (or none)
+    let horizon = old.length_of_fixed_segments().max(new.length_of_fixed_segments());
+    let mut cycle_changes: Vec<CycleChange> = Vec::new();
+    for cycle in 0..=horizon {
+        let kind_at = |rs: &ReadStructure| rs.segment_at(cycle).map(|(_, s)| s.kind);
+        let (old_kind, new_kind) = (kind_at(old), kind_at(new));
+        let end = if cycle == horizon { None } else { Some(cycle + 1) };
+        match cycle_changes.last_mut() {
+            _ if old_kind == new_kind => (),
+            Some(last)
+                if last.end == Some(cycle) && (last.old, last.new) == (old_kind, new_kind) =>
+            {
+                last.end = end;
+            }
+            _ => {
+                cycle_changes.push(CycleChange { start: cycle, end, old: old_kind, new: new_kind })
+            }
+        }
+    }
+
+    let mut segment_changes = Vec::new();
+    for kind in SegmentType::iter() {
+        let old_segments: Vec<&ReadSegment> = old.segments_by_type(kind).collect();
+        let new_segments: Vec<&ReadSegment> = new.segments_by_type(kind).collect();
+        for index in 0..old_segments.len().max(new_segments.len()) {
+            let old_segment = old_segments.get(index).map(|s| **s);
+            let new_segment = new_segments.get(index).map(|s| **s);
+            if old_segment.map(|s| s.length) != new_segment.map(|s| s.length) {
+                segment_changes.push(SegmentChange {
+                    kind,
+                    index,
+                    old: old_segment,
+                    new: new_segment,
+                });
+            }
+        }
+    }
+
+    ReadStructureDiff { old: old.clone(), new: new.clone(), cycle_changes, segment_changes }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::diff::{diff, CycleChange};
+    use crate::read_structure::ReadStructure;
+    use crate::segment_type::SegmentType;
+
+    fn rs(s: &str) -> ReadStructure {
+        ReadStructure::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let diff = diff(&rs("8M+T"), &rs("8M+T"));
+        assert!(diff.is_empty());
+        assert!(!diff.indefinite_changed());
+        assert_eq!(diff.to_string(), "8M+T -> 8M+T: no changes");
+    }
+
+    #[test]
+    fn test_diff_cycle_changes() {
+        let diff = diff(&rs("8B+T"), &rs("10B+T"));
+        assert_eq!(
+            diff.cycle_changes,
+            vec![CycleChange {
+                start: 8,
+                end: Some(10),
+                old: Some(SegmentType::Template),
+                new: Some(SegmentType::SampleBarcode),
+            }]
+        );
+        assert_eq!(diff.segment_changes.len(), 1);
+        assert_eq!(diff.segment_changes[0].kind, SegmentType::SampleBarcode);
+        assert!(diff.indefinite_changed());
+
+        // Truncated reads, and a changed type for the rest of the read
+        let diff = self::diff(&rs("4M146T"), &rs("5M+S"));
+        assert_eq!(
+            diff.to_string(),
+            "4M146T -> 5M+S\n\
+             cycle 5: T -> M\n\
+             cycles 6-150: T -> S\n\
+             cycles 151+: none -> S\n\
+             T1: 146T -> none\n\
+             M1: 4M -> 5M\n\
+             S1: none -> +S\n\
+             indefinite segment: none -> +S at cycle 6"
+        );
+    }
+
+    #[test]
+    fn test_diff_same_layout_different_lengths() {
+        // Same types at every cycle, but the segments were split differently
+        let diff = diff(&rs("8B8B+T"), &rs("6B10B+T"));
+        assert!(diff.cycle_changes.is_empty());
+        let changes: Vec<(usize, Option<usize>, Option<usize>)> = diff
+            .segment_changes
+            .iter()
+            .map(|c| (c.index, c.old.and_then(|s| s.length), c.new.and_then(|s| s.length)))
+            .collect();
+        assert_eq!(changes, vec![(0, Some(8), Some(6)), (1, Some(8), Some(10))]);
+        assert!(!diff.indefinite_changed());
+    }
+}
@@ -0,0 +1,149 @@
+//! Extractions
+//!
+//! Type [`Extraction`] holds the bases (and optionally qualities) extracted from one or more
+//! reads for each segment of a [`crate::read_structure::ReadStructure`] or
+//! [`crate::multi_read_structure::MultiReadStructure`], as returned by
+//! [`crate::read_structure::ReadStructure::extract`] and
+//! [`crate::multi_read_structure::MultiReadStructure::extract`].
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use crate::orientation::{reverse_complement, Orientation};
+use crate::read_segment::ReadSegment;
+use crate::segment_type::SegmentType;
+
+/// The bases and optional qualities extracted for a single [`ReadSegment`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedSegment<'a> {
+    /// The index of the read the segment was extracted from.
+    pub read_index: usize,
+    /// The segment the bases were extracted for.
+    pub segment: ReadSegment,
+    /// The bases of the segment.
+    pub bases: &'a [u8],
+    /// The qualities of the segment, if qualities were given.
+    pub quals: Option<&'a [u8]>,
+    /// The orientation of the read the segment was extracted from.
+    pub orientation: Orientation,
+    /// The label of the segment in its read structure, if any.
+    pub label: Option<Arc<str>>,
+}
+
+impl<'a> ExtractedSegment<'a> {
+    /// Returns the kind of the segment the bases were extracted for.
+    pub fn kind(&self) -> SegmentType {
+        self.segment.kind
+    }
+
+    /// Returns the bases of the segment in the orientation of its read: reverse complemented
+    /// if the read is [`Orientation::ReverseComplement`], otherwise as sequenced.
+    pub fn oriented_bases(&self) -> Cow<'a, [u8]> {
+        match self.orientation {
+            Orientation::Forward => Cow::Borrowed(self.bases),
+            Orientation::ReverseComplement => Cow::Owned(reverse_complement(self.bases)),
+        }
+    }
+
+    /// Returns the qualities of the segment in the orientation of its read: reversed if the read
+    /// is [`Orientation::ReverseComplement`], otherwise as sequenced.
+    pub fn oriented_quals(&self) -> Option<Cow<'a, [u8]>> {
+        self.quals.map(|quals| match self.orientation {
+            Orientation::Forward => Cow::Borrowed(quals),
+            Orientation::ReverseComplement => Cow::Owned(quals.iter().rev().copied().collect()),
+        })
+    }
+}
+
+/// The segments extracted from one or more reads, in the order they appear in the read
+/// structure(s).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extraction<'a> {
+    /// The extracted segments.
+    segments: Vec<ExtractedSegment<'a>>,
+}
+
+impl<'a> Extraction<'a> {
+    /// Builds a new [`Extraction`] from extracted segments.
+    pub fn new(segments: Vec<ExtractedSegment<'a>>) -> Self {
+        Extraction { segments }
+    }
+
+    /// Returns the number of reads the segments were extracted from.
+    pub fn number_of_reads(&self) -> usize {
+        self.segments.iter().map(|s| s.read_index + 1).max().unwrap_or(0)
+    }
+
+    /// Appends the segments of another extraction, treating its reads as additional reads
+    /// following the reads of this extraction.
+    pub fn append(&mut self, other: Extraction<'a>) {
+        let offset = self.number_of_reads();
+        self.segments.extend(other.segments.into_iter().map(|mut s| {
+            s.read_index += offset;
+            s
+        }));
+    }
+
+    /// Returns the extracted segments.
+    pub fn segments(&self) -> &[ExtractedSegment<'a>] {
+        &self.segments
+    }
+
+    /// Returns the extracted segments, mutably.
+    pub(crate) fn segments_mut(&mut self) -> &mut [ExtractedSegment<'a>] {
+        &mut self.segments
+    }
+
+    /// Returns an iterator over the extracted segments.
+    pub fn iter(&self) -> impl Iterator<Item = &ExtractedSegment<'a>> {
+        self.segments.iter()
+    }
+
+    /// Returns the first extracted segment with the given label.
+    pub fn segment_by_label(&self, label: &str) -> Option<&ExtractedSegment<'a>> {
+        self.segments.iter().find(|s| s.label.as_deref() == Some(label))
+    }
+
+    /// Returns the extracted segments of the given kind.
+    pub fn segments_by_type(
+        &self,
+        kind: SegmentType,
+    ) -> impl Iterator<Item = &ExtractedSegment<'a>> {
+        self.segments.iter().filter(move |s| s.kind() == kind)
+    }
+
+    /// Returns the extracted template segments.
+    pub fn templates(&self) -> impl Iterator<Item = &ExtractedSegment<'a>> {
+        self.segments_by_type(SegmentType::Template)
+    }
+
+    /// Returns the extracted sample barcode segments.
+    pub fn sample_barcodes(&self) -> impl Iterator<Item = &ExtractedSegment<'a>> {
+        self.segments_by_type(SegmentType::SampleBarcode)
+    }
+
+    /// Returns the extracted molecular barcode segments.
+    pub fn molecular_barcodes(&self) -> impl Iterator<Item = &ExtractedSegment<'a>> {
+        self.segments_by_type(SegmentType::MolecularBarcode)
+    }
+
+    /// Returns the extracted skip segments.
+    pub fn skips(&self) -> impl Iterator<Item = &ExtractedSegment<'a>> {
+        self.segments_by_type(SegmentType::Skip)
+    }
+
+    /// Returns the extracted cellular barcode segments.
+    pub fn cellular_barcodes(&self) -> impl Iterator<Item = &ExtractedSegment<'a>> {
+        self.segments_by_type(SegmentType::CellularBarcode)
+    }
+}
+
+impl<'a> IntoIterator for Extraction<'a> {
+    type Item = ExtractedSegment<'a>;
+
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.segments.into_iter()
+    }
+}
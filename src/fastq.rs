@@ -0,0 +1,232 @@
+//! FASTQ
+//!
+//! Minimal reading and writing of FASTQ records, enough to split reads into their segments with
+//! a [`crate::read_structure::ReadStructure`] or
+//! [`crate::multi_read_structure::MultiReadStructure`].  Records must have the sequence and
+//! qualities on single lines, as produced by all modern instruments.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::ReadStructure;
+//! use read_structure::fastq::{write_record, FastqReader};
+//!
+//! let fastq = b"@q1 1:N:0:ACGT\nAAAACCGGTT\n+\nIIIIIIIIII\n";
+//! let rs = ReadStructure::from_str("4M+T").unwrap();
+//! let mut out = Vec::new();
+//! for record in FastqReader::new(&fastq[..]) {
+//!     let record = record.unwrap();
+//!     let extraction = rs.extract_with_quals(&record.bases, &record.quals).unwrap();
+//!     let template = extraction.templates().next().unwrap();
+//!     write_record(&mut out, record.id(), template.bases, template.quals.unwrap()).unwrap();
+//! }
+//! assert_eq!(out, b"@q1\nCCGGTT\n+\nIIIIII\n");
+//! ```
+
+use std::io::{BufRead, Write};
+
+use crate::ReadStructureError;
+
+/// A single FASTQ record.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FastqRecord {
+    /// The header line, without the leading `@`.
+    pub name: Vec<u8>,
+    /// The bases of the read.
+    pub bases: Vec<u8>,
+    /// The qualities of the read.
+    pub quals: Vec<u8>,
+}
+
+impl FastqRecord {
+    /// Returns the read identifier, i.e. the header up to the first whitespace.
+    pub fn id(&self) -> &[u8] {
+        let end = self.name.iter().position(u8::is_ascii_whitespace).unwrap_or(self.name.len());
+        &self.name[..end]
+    }
+
+    /// Returns the comment following the read identifier, if any.
+    pub fn comment(&self) -> Option<&[u8]> {
+        let start = self.name.iter().position(u8::is_ascii_whitespace)?;
+        Some(&self.name[start + 1..])
+    }
+
+    /// Returns the read identifier without any trailing `/1` or `/2` style read number.
+    pub fn base_id(&self) -> &[u8] {
+        match self.id() {
+            [rest @ .., b'/', n] if n.is_ascii_digit() => rest,
+            id => id,
+        }
+    }
+
+    /// Returns true if both records have the same read identifier, ignoring any trailing `/1`
+    /// or `/2` style read numbers.
+    pub fn same_id(&self, other: &FastqRecord) -> bool {
+        self.base_id() == other.base_id()
+    }
+}
+
+/// Reads [`FastqRecord`]s from a buffered reader.
+#[derive(Debug)]
+pub struct FastqReader<R> {
+    /// The underlying reader.
+    reader: R,
+    /// The number of lines read so far, for error messages.
+    line_number: usize,
+    /// A buffer for the separator line.
+    separator: Vec<u8>,
+}
+
+impl<R: BufRead> FastqReader<R> {
+    /// Builds a new [`FastqReader`].
+    pub fn new(reader: R) -> Self {
+        FastqReader { reader, line_number: 0, separator: Vec::new() }
+    }
+
+    /// Reads the next record into `record`, reusing its buffers.  Returns `false` if there are no
+    /// more records.
+    ///
+    /// # Errors
+    ///
+    /// - If reading fails.
+    /// - If the record is truncated or malformed.
+    pub fn read_record(&mut self, record: &mut FastqRecord) -> Result<bool, ReadStructureError> {
+        if !self.read_line(&mut record.name)? {
+            return Ok(false);
+        }
+        match record.name.first() {
+            Some(b'@') => {
+                record.name.remove(0);
+            }
+            _ => return Err(self.invalid("header does not start with '@'")),
+        }
+        if !self.read_line(&mut record.bases)? {
+            return Err(self.invalid("truncated record, missing bases"));
+        }
+        let mut separator = std::mem::take(&mut self.separator);
+        let has_separator = self.read_line(&mut separator)?;
+        let starts_with_plus = separator.first() == Some(&b'+');
+        self.separator = separator;
+        if !has_separator || !starts_with_plus {
+            return Err(self.invalid("missing '+' separator line"));
+        }
+        if !self.read_line(&mut record.quals)? {
+            return Err(self.invalid("truncated record, missing qualities"));
+        }
+        if record.bases.len() != record.quals.len() {
+            return Err(self.invalid(&format!(
+                "{} bases but {} qualities",
+                record.bases.len(),
+                record.quals.len()
+            )));
+        }
+        Ok(true)
+    }
+
+    /// Reads the next line into `buf` without the line ending.  Returns `false` at end of input.
+    fn read_line(&mut self, buf: &mut Vec<u8>) -> Result<bool, ReadStructureError> {
+        buf.clear();
+        if self.reader.read_until(b'\n', buf)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        if buf.last() == Some(&b'\n') {
+            buf.pop();
+            if buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns an error describing a malformed record at the current line.
+    fn invalid(&self, message: &str) -> ReadStructureError {
+        ReadStructureError::FastqInvalid { line: self.line_number, message: message.to_owned() }
+    }
+}
+
+impl<R: BufRead> Iterator for FastqReader<R> {
+    type Item = Result<FastqRecord, ReadStructureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = FastqRecord::default();
+        match self.read_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Writes a single FASTQ record with the given header (without the leading `@`), bases and
+/// qualities.
+///
+/// # Errors
+///
+/// - If writing fails.
+pub fn write_record<W: Write>(
+    writer: &mut W,
+    name: &[u8],
+    bases: &[u8],
+    quals: &[u8],
+) -> std::io::Result<()> {
+    writer.write_all(b"@")?;
+    writer.write_all(name)?;
+    writer.write_all(b"\n")?;
+    writer.write_all(bases)?;
+    writer.write_all(b"\n+\n")?;
+    writer.write_all(quals)?;
+    writer.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fastq::{write_record, FastqReader, FastqRecord};
+
+    fn read_all(fastq: &[u8]) -> Result<Vec<FastqRecord>, crate::ReadStructureError> {
+        FastqReader::new(fastq).collect()
+    }
+
+    #[test]
+    fn test_read_records() {
+        let records =
+            read_all(b"@q1 1:N:0:ACGT\nACGT\n+\nIIII\n@q2\r\nGG\r\n+q2\r\n##\r\n").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].id(), b"q1");
+        assert_eq!(records[0].comment(), Some(&b"1:N:0:ACGT"[..]));
+        assert_eq!(records[0].bases, b"ACGT");
+        assert_eq!(records[1].name, b"q2");
+        assert_eq!(records[1].comment(), None);
+        assert_eq!(records[1].quals, b"##");
+        assert!(read_all(b"").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_invalid_records() {
+        assert!(read_all(b"q1\nACGT\n+\nIIII\n").is_err());
+        assert!(read_all(b"@q1\nACGT\n").is_err());
+        assert!(read_all(b"@q1\nACGT\n-\nIIII\n").is_err());
+        let err = read_all(b"@q1\nACGT\n+\nIIII\n@q2\nACGT\n+\nIII\n").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid FASTQ record at line 8: 4 bases but 3 qualities");
+    }
+
+    #[test]
+    fn test_write_record_round_trip() {
+        let mut out = Vec::new();
+        write_record(&mut out, b"q1 comment", b"ACGT", b"IIII").unwrap();
+        assert_eq!(out, b"@q1 comment\nACGT\n+\nIIII\n");
+        let records = read_all(&out).unwrap();
+        assert_eq!(records[0].name, b"q1 comment");
+    }
+
+    #[test]
+    fn test_same_id() {
+        let record = |name: &[u8]| FastqRecord { name: name.to_vec(), ..FastqRecord::default() };
+        assert!(record(b"q1/1").same_id(&record(b"q1/2")));
+        assert!(record(b"q1 1:N:0").same_id(&record(b"q1 2:N:0")));
+        assert!(!record(b"q1").same_id(&record(b"q2")));
+        assert_eq!(record(b"q1/1 comment").base_id(), b"q1");
+        assert_eq!(record(b"q1/a").base_id(), b"q1/a");
+    }
+}
@@ -0,0 +1,213 @@
+//! Illumina FASTQ Header Comments
+//!
+//! Type [`IlluminaComment`] represents the comment that Illumina software writes after the
+//! read name in FASTQ headers, of the form `<read>:<is filtered>:<control number>:<index>`
+//! (e.g. `1:N:0:ACGTACGT+TTGGCCAA`).  When index reads were not written to their own FASTQs
+//! the sample barcodes only exist in the index field, so they can be treated as additional
+//! pseudo-reads: [`IlluminaComment::index_read_structures`] describes them and
+//! [`crate::MultiReadStructure::extract_with_comment`] extracts them after the sequenced reads,
+//! so that sample barcodes are handled the same way whether they came from I1/I2 FASTQs or from
+//! the header.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::{illumina::IlluminaComment, MultiReadStructure};
+//!
+//! let comment = IlluminaComment::from_read_name("@q1 1:N:0:ACGTACGT+TTGGCCAA").unwrap();
+//! let mrs = MultiReadStructure::from_str("8M+T +T").unwrap();
+//! let extraction = mrs.extract_with_comment(&[b"AAAAAAAACCCC", b"GGGG"], &comment).unwrap();
+//!
+//! let barcodes: Vec<&[u8]> = extraction.sample_barcodes().map(|s| s.bases).collect();
+//! assert_eq!(barcodes, vec![&b"ACGTACGT"[..], b"TTGGCCAA"]);
+//!
+//! let mrs = mrs.with_reads(comment.index_read_structures());
+//! assert_eq!(mrs.to_string(), "8M+T +T 8B 8B");
+//! ```
+
+use std::str::FromStr;
+
+use crate::extraction::{ExtractedSegment, Extraction};
+use crate::orientation::Orientation;
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// The delimiter between the index reads in the index field.
+pub const INDEX_DELIMITER: char = '+';
+
+/// An Illumina FASTQ header comment.  See [the module level documentation](self) for more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IlluminaComment {
+    /// The read number (e.g. 1 for R1, 2 for R2).
+    pub read_number: u8,
+    /// True if the read was filtered (i.e. failed the chastity filter, `Y`), false otherwise.
+    pub is_filtered: bool,
+    /// The control number, 0 when none of the control bits are on.
+    pub control_number: u16,
+    /// The index field: the index reads separated by [`INDEX_DELIMITER`], or the sample number
+    /// if no index reads were sequenced.
+    pub index: String,
+}
+
+impl IlluminaComment {
+    /// Parses the comment from a full FASTQ header or read name, i.e. the text following the
+    /// first whitespace.
+    ///
+    /// # Errors
+    ///
+    /// - If the read name has no comment.
+    /// - If the comment is not a valid Illumina comment.
+    pub fn from_read_name(name: &str) -> Result<Self, ReadStructureError> {
+        match name.split_once(char::is_whitespace) {
+            Some((_, comment)) => Self::from_str(comment),
+            None => Err(ReadStructureError::IlluminaCommentInvalid(name.to_owned())),
+        }
+    }
+
+    /// Returns the bases of each index read in the index field, or no index reads if the index
+    /// field is empty or holds a sample number.
+    pub fn index_reads(&self) -> Vec<&[u8]> {
+        if self.index.is_empty() || self.index.chars().all(|c| c.is_ascii_digit()) {
+            vec![]
+        } else {
+            self.index.split(INDEX_DELIMITER).map(str::as_bytes).collect()
+        }
+    }
+
+    /// Returns a read structure for each index read, consisting of a single sample barcode
+    /// segment spanning the index read.
+    pub fn index_read_structures(&self) -> Vec<ReadStructure> {
+        self.index_reads()
+            .into_iter()
+            .filter(|bases| !bases.is_empty())
+            .map(|bases| {
+                let segment = ReadSegment {
+                    offset: 0,
+                    length: Some(bases.len()),
+                    kind: SegmentType::SampleBarcode,
+                };
+                // Unwrap is safe since the segment is non-empty and has a fixed length
+                ReadStructure::new(vec![segment]).unwrap()
+            })
+            .collect()
+    }
+
+    /// Extracts the index reads as sample barcode segments, one pseudo-read per index read.
+    /// Use [`Extraction::append`] to combine this with an extraction of the sequenced reads.
+    /// The indices are given [`Orientation::Forward`], since they are taken as written in the
+    /// header rather than as read from the instrument.
+    pub fn extract_index(&self) -> Extraction<'_> {
+        let segments = self
+            .index_read_structures()
+            .into_iter()
+            .zip(self.index_reads().into_iter().filter(|bases| !bases.is_empty()))
+            .enumerate()
+            .map(|(read_index, (rs, bases))| ExtractedSegment {
+                read_index,
+                segment: rs[0],
+                bases,
+                quals: None,
+                orientation: Orientation::Forward,
+                label: None,
+            })
+            .collect();
+        Extraction::new(segments)
+    }
+}
+
+impl FromStr for IlluminaComment {
+    type Err = ReadStructureError;
+
+    /// Parses an Illumina FASTQ header comment, e.g. `1:N:0:ACGTACGT+TTGGCCAA`.
+    ///
+    /// # Errors
+    ///
+    /// - If the comment does not have four `:` delimited fields.
+    /// - If the read number or control number are not integers.
+    /// - If the filter flag is not `Y` or `N`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ReadStructureError::IlluminaCommentInvalid(s.to_owned());
+        let fields: Vec<&str> = s.trim().splitn(4, ':').collect();
+        if fields.len() != 4 || fields[3].contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        let read_number = fields[0].parse::<u8>().map_err(|_| invalid())?;
+        let is_filtered = match fields[1] {
+            "Y" => true,
+            "N" => false,
+            _ => return Err(invalid()),
+        };
+        let control_number = fields[2].parse::<u16>().map_err(|_| invalid())?;
+        Ok(IlluminaComment {
+            read_number,
+            is_filtered,
+            control_number,
+            index: fields[3].to_owned(),
+        })
+    }
+}
+
+impl std::fmt::Display for IlluminaComment {
+    /// Formats the comment as it appears in a FASTQ header.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let filtered = if self.is_filtered { 'Y' } else { 'N' };
+        write!(f, "{}:{}:{}:{}", self.read_number, filtered, self.control_number, self.index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::illumina::IlluminaComment;
+    use crate::multi_read_structure::MultiReadStructure;
+
+    #[test]
+    fn test_parse_comment() {
+        let comment = IlluminaComment::from_str("2:Y:18:ACGTACGT+TTGGCCAA").unwrap();
+        assert_eq!(comment.read_number, 2);
+        assert!(comment.is_filtered);
+        assert_eq!(comment.control_number, 18);
+        assert_eq!(comment.index_reads(), vec![&b"ACGTACGT"[..], b"TTGGCCAA"]);
+        assert_eq!(comment.to_string(), "2:Y:18:ACGTACGT+TTGGCCAA");
+    }
+
+    #[test]
+    fn test_parse_comment_without_index() {
+        let comment = IlluminaComment::from_read_name("@q1 1:N:0:3").unwrap();
+        assert!(!comment.is_filtered);
+        assert!(comment.index_reads().is_empty());
+        assert!(comment.index_read_structures().is_empty());
+        let comment = IlluminaComment::from_str("1:N:0:").unwrap();
+        assert!(comment.index_reads().is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_comment() {
+        assert!(IlluminaComment::from_str("1:N:0").is_err());
+        assert!(IlluminaComment::from_str("X:N:0:ACGT").is_err());
+        assert!(IlluminaComment::from_str("1:Q:0:ACGT").is_err());
+        assert!(IlluminaComment::from_str("1:N:-1:ACGT").is_err());
+        assert!(IlluminaComment::from_read_name("@q1").is_err());
+    }
+
+    #[test]
+    fn test_index_as_pseudo_reads() {
+        let comment = IlluminaComment::from_str("1:N:0:ACGTACGT+TTGGCC").unwrap();
+        let mrs = MultiReadStructure::from_str("+T +T").unwrap();
+        let mrs = mrs.with_reads(comment.index_read_structures());
+        assert_eq!(mrs.to_string(), "+T +T 8B 6B");
+
+        let extraction = MultiReadStructure::from_str("+T +T")
+            .unwrap()
+            .extract_with_comment(&[b"AAAA", b"CCCC"], &comment)
+            .unwrap();
+        let barcodes: Vec<(usize, &[u8])> =
+            extraction.sample_barcodes().map(|s| (s.read_index, s.bases)).collect();
+        assert_eq!(barcodes, vec![(2, &b"ACGTACGT"[..]), (3, b"TTGGCC")]);
+        assert!(extraction.iter().all(|s| s.oriented_bases() == s.bases));
+    }
+}
@@ -0,0 +1,959 @@
+//! Read Structure Inference
+//!
+//! Proposes [`ReadStructure`]s for reads of unknown structure from a sample of their sequences.
+//! The sampled reads are summarized per cycle ([`ReadProfile`]) and each cycle is marked using,
+//! in order of precedence:
+//!
+//! 1. matches against lists of expected barcodes (e.g. a cell barcode whitelist) at a fixed
+//!    offset, giving cellular or sample barcode segments;
+//! 2. exact matches against known adapter and linker sequences at a fixed offset, giving skips;
+//! 3. runs of poly-T (e.g. the oligo-dT of 3' single-cell assays), giving skips;
+//! 4. low entropy (near-constant) cycles, giving skips; and
+//! 5. high entropy (variable) cycles, classified as sample barcodes, molecular barcodes or
+//!    template by the diversity of their sequences and their position in the read.
+//!
+//! Variable regions are often ambiguous, so each is given several possible segment types with
+//! a confidence for each, and the most confident combinations are proposed along with the read
+//! structures of known [`Chemistry`]s consistent with the profile.  Every [`Candidate`] carries
+//! the evidence for each of its segments, including why each segment starts where it does.
+//!
+//! # Example
+//!
+//! ```rust
+//! use read_structure::infer::{infer, InferenceOptions, ReadProfile};
+//!
+//! // An index read from a pool of four samples
+//! let barcodes = [b"ACGTACGT", b"TTGCAAGC", b"GACTTCAG", b"CATGGTCA"];
+//! let reads: Vec<&[u8]> = (0..400).map(|i| &barcodes[i % 4][..]).collect();
+//! let profile = ReadProfile::from_reads(reads, 10_000);
+//! let candidates = infer(&profile, &InferenceOptions::default()).unwrap();
+//! assert_eq!(candidates[0].read_structure.to_string(), "8B");
+//! assert_eq!(
+//!     candidates[0].segments[0].evidence,
+//!     vec!["cycles 1-8: mean entropy 1.62 bits, 1% distinct sequences, the 40 most common cover 100% of reads"]
+//! );
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::io::BufRead;
+
+use strum::IntoEnumIterator;
+
+use crate::chemistry::Chemistry;
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// The default maximum number of reads sampled.
+pub const DEFAULT_MAX_READS: usize = 10_000;
+
+/// The default maximum number of candidates proposed.
+pub const DEFAULT_MAX_CANDIDATES: usize = 5;
+
+/// The minimum fraction of reads matching a barcode list or known sequence at an offset for the
+/// match to be used.
+const MIN_MATCH_FRACTION: f64 = 0.5;
+
+/// The minimum fraction of the most common base for a cycle to be considered constant.
+const MIN_CONSTANT_FRACTION: f64 = 0.8;
+
+/// The minimum fraction of `T`s for a cycle to be part of a poly-T run.
+const MIN_POLY_T_FRACTION: f64 = 0.7;
+
+/// The minimum number of cycles in a poly-T run.
+const MIN_POLY_T_LENGTH: usize = 8;
+
+/// The fraction of reads that the most common sequences of a sample barcode are expected to
+/// cover.
+const MIN_SAMPLE_BARCODE_COVERAGE: f64 = 0.8;
+
+/// The maximum number of distinct sample barcodes expected in a pool.
+const MAX_SAMPLE_BARCODES: usize = 96;
+
+/// The maximum length of a barcode segment.
+const MAX_BARCODE_LENGTH: usize = 24;
+
+/// The confidence scaling applied to candidates from known chemistries, so that they rank below
+/// equally consistent structures inferred directly from the reads.
+const CHEMISTRY_CONFIDENCE_SCALE: f64 = 0.9;
+
+/// The bases counted per cycle, with any other base counted as `N`.
+const BASES: [u8; 4] = [b'A', b'C', b'G', b'T'];
+
+/// The base counts for a single cycle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CycleStats {
+    /// The counts of `A`, `C`, `G`, `T` and `N` (or any other base).
+    counts: [u64; 5],
+}
+
+impl CycleStats {
+    /// Adds a base to the counts.
+    fn add(&mut self, base: u8) {
+        let index = BASES.iter().position(|&b| b == base.to_ascii_uppercase()).unwrap_or(4);
+        self.counts[index] += 1;
+    }
+
+    /// Returns the total number of bases counted, including `N`s.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the number of the given base, with `N` counting all non-`ACGT` bases.
+    pub fn count(&self, base: u8) -> u64 {
+        let index = BASES.iter().position(|&b| b == base.to_ascii_uppercase()).unwrap_or(4);
+        self.counts[index]
+    }
+
+    /// Returns the fraction of called (`ACGT`) bases that are the given base.
+    pub fn fraction(&self, base: u8) -> f64 {
+        let called: u64 = self.counts[..4].iter().sum();
+        if called == 0 {
+            0.0
+        } else {
+            self.count(base) as f64 / called as f64
+        }
+    }
+
+    /// Returns the most common called base and its fraction of called bases.
+    pub fn dominant(&self) -> (u8, f64) {
+        let (index, _) =
+            self.counts[..4].iter().enumerate().max_by_key(|(_, &c)| c).unwrap_or((0, &0));
+        (BASES[index], self.fraction(BASES[index]))
+    }
+
+    /// Returns the Shannon entropy, in bits, of the called bases: from zero for a constant cycle
+    /// to two for perfectly balanced bases.
+    pub fn entropy(&self) -> f64 {
+        BASES
+            .iter()
+            .map(|&b| self.fraction(b))
+            .filter(|&p| p > 0.0)
+            .map(|p| -p * p.log2())
+            .sum::<f64>()
+            .max(0.0)
+    }
+}
+
+/// A summary of a sample of reads: the reads themselves, per-cycle base counts and read lengths.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadProfile {
+    /// The sampled reads, upper-cased.
+    reads: Vec<Vec<u8>>,
+    /// The base counts for each cycle, up to the longest read.
+    cycles: Vec<CycleStats>,
+    /// The length of the shortest read.
+    min_length: usize,
+    /// The length of the longest read.
+    max_length: usize,
+}
+
+impl ReadProfile {
+    /// Builds a [`ReadProfile`] from (at most) the first `max_reads` reads.
+    pub fn from_reads<I, B>(reads: I, max_reads: usize) -> Self
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let reads: Vec<Vec<u8>> =
+            reads.into_iter().take(max_reads).map(|r| r.as_ref().to_ascii_uppercase()).collect();
+        let max_length = reads.iter().map(Vec::len).max().unwrap_or(0);
+        let min_length = reads.iter().map(Vec::len).min().unwrap_or(0);
+        let mut cycles = vec![CycleStats::default(); max_length];
+        for read in &reads {
+            for (stats, &base) in cycles.iter_mut().zip(read) {
+                stats.add(base);
+            }
+        }
+        ReadProfile { reads, cycles, min_length, max_length }
+    }
+
+    /// Returns the number of sampled reads.
+    pub fn number_of_reads(&self) -> usize {
+        self.reads.len()
+    }
+
+    /// Returns the base counts for each cycle.
+    pub fn cycles(&self) -> &[CycleStats] {
+        &self.cycles
+    }
+
+    /// Returns the length of the shortest read.
+    pub fn min_length(&self) -> usize {
+        self.min_length
+    }
+
+    /// Returns the length of the longest read.
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// Returns true if all reads have the same length.
+    pub fn has_fixed_length(&self) -> bool {
+        self.min_length == self.max_length
+    }
+
+    /// Returns the bases of every read between the given cycles (zero-based, exclusive end).
+    fn windows(&self, start: usize, end: usize) -> impl Iterator<Item = &[u8]> {
+        self.reads.iter().filter(move |r| r.len() >= end).map(move |r| &r[start..end])
+    }
+
+    /// Returns the mean entropy of the given cycles.
+    fn mean_entropy(&self, start: usize, end: usize) -> f64 {
+        mean(self.cycles[start..end].iter().map(CycleStats::entropy))
+    }
+}
+
+/// A known adapter or linker sequence, expected at a fixed offset in the reads if present.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownSequence {
+    /// The name of the sequence, reported in evidence.
+    pub name: String,
+    /// The sequence.
+    pub sequence: Vec<u8>,
+}
+
+impl KnownSequence {
+    /// Builds a new [`KnownSequence`].
+    pub fn new(name: &str, sequence: &[u8]) -> Self {
+        KnownSequence { name: name.to_owned(), sequence: sequence.to_ascii_uppercase() }
+    }
+
+    /// Returns the commonly encountered adapter and linker sequences.
+    pub fn defaults() -> Vec<KnownSequence> {
+        vec![
+            KnownSequence::new("10x template switch oligo", b"TTTCTTATATGGG"),
+            KnownSequence::new("Nextera mosaic end", b"AGATGTGTATAAGAGACAG"),
+            KnownSequence::new("TruSeq adapter", b"AGATCGGAAGAGC"),
+        ]
+    }
+}
+
+/// A list of expected barcodes of a single length, such as a cell barcode whitelist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BarcodeList {
+    /// The name of the list, reported in evidence.
+    name: String,
+    /// The kind of segment the barcodes are found in.
+    kind: SegmentType,
+    /// The length of every barcode.
+    length: usize,
+    /// The barcodes.
+    barcodes: HashSet<Vec<u8>>,
+}
+
+impl BarcodeList {
+    /// Builds a new [`BarcodeList`].
+    ///
+    /// # Errors
+    ///
+    /// - If there are no barcodes.
+    /// - If the barcodes differ in length.
+    pub fn new<I, B>(name: &str, kind: SegmentType, barcodes: I) -> Result<Self, ReadStructureError>
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let barcodes: HashSet<Vec<u8>> =
+            barcodes.into_iter().map(|b| b.as_ref().to_ascii_uppercase()).collect();
+        let length = barcodes.iter().next().map(Vec::len).unwrap_or(0);
+        if length == 0 {
+            return Err(ReadStructureError::BarcodeListInvalid(format!("{}: no barcodes", name)));
+        }
+        if barcodes.iter().any(|b| b.len() != length) {
+            return Err(ReadStructureError::BarcodeListInvalid(format!(
+                "{}: barcodes differ in length",
+                name
+            )));
+        }
+        Ok(BarcodeList { name: name.to_owned(), kind, length, barcodes })
+    }
+
+    /// Reads a [`BarcodeList`] with one barcode per line, taking the first whitespace-delimited
+    /// field of each line and ignoring empty lines and lines starting with `#`.
+    ///
+    /// # Errors
+    ///
+    /// - If reading fails.
+    /// - If there are no barcodes, or the barcodes differ in length.
+    pub fn from_reader<R: BufRead>(
+        name: &str,
+        kind: SegmentType,
+        reader: R,
+    ) -> Result<Self, ReadStructureError> {
+        let mut barcodes = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            match line.split_whitespace().next() {
+                Some(barcode) if !barcode.starts_with('#') => barcodes.push(barcode.to_owned()),
+                _ => (),
+            }
+        }
+        Self::new(name, kind, barcodes)
+    }
+
+    /// Returns the name of the list.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the kind of segment the barcodes are found in.
+    pub fn kind(&self) -> SegmentType {
+        self.kind
+    }
+
+    /// Returns the length of every barcode.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Returns the number of barcodes.
+    pub fn len(&self) -> usize {
+        self.barcodes.len()
+    }
+
+    /// Returns true if there are no barcodes.
+    pub fn is_empty(&self) -> bool {
+        self.barcodes.is_empty()
+    }
+
+    /// Returns true if the list contains the barcode.
+    pub fn contains(&self, barcode: &[u8]) -> bool {
+        self.barcodes.contains(barcode)
+    }
+}
+
+/// Options for inferring read structures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InferenceOptions {
+    /// The known adapter and linker sequences to search for.
+    pub known_sequences: Vec<KnownSequence>,
+    /// The lists of expected barcodes to search for.
+    pub barcode_lists: Vec<BarcodeList>,
+    /// Whether to propose the read structures of known chemistries.
+    pub chemistries: bool,
+    /// The maximum number of candidates to propose.
+    pub max_candidates: usize,
+}
+
+impl Default for InferenceOptions {
+    fn default() -> Self {
+        InferenceOptions {
+            known_sequences: KnownSequence::defaults(),
+            barcode_lists: Vec::new(),
+            chemistries: true,
+            max_candidates: DEFAULT_MAX_CANDIDATES,
+        }
+    }
+}
+
+/// The evidence for a single segment of a [`Candidate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentEvidence {
+    /// The segment.
+    pub segment: ReadSegment,
+    /// The confidence, from zero to one, in the segment's type and boundaries.
+    pub confidence: f64,
+    /// Human-readable descriptions of the evidence for the segment, starting with why it starts
+    /// where it does.
+    pub evidence: Vec<String>,
+}
+
+/// A proposed read structure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    /// The read structure.
+    pub read_structure: ReadStructure,
+    /// The confidence, from zero to one, in the read structure.
+    pub confidence: f64,
+    /// The evidence for each segment, in order.
+    pub segments: Vec<SegmentEvidence>,
+}
+
+/// What a cycle was found to contain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mark {
+    /// Variable bases.
+    Variable,
+    /// Near-constant bases.
+    Constant,
+    /// Part of a poly-T run.
+    PolyT,
+    /// Part of a match to the known sequence with the given index.
+    Known(usize, f64),
+    /// Part of a match to the barcode list with the given index.
+    Barcodes(usize, f64),
+}
+
+/// A run of cycles with the same mark.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Region {
+    /// The zero-based first cycle.
+    start: usize,
+    /// The zero-based exclusive last cycle.
+    end: usize,
+    /// What the cycles contain.
+    mark: Mark,
+}
+
+/// A possible segment type for a region.
+#[derive(Debug, Clone, PartialEq)]
+struct Choice {
+    /// The segment type.
+    kind: SegmentType,
+    /// The confidence in the segment type.
+    confidence: f64,
+    /// The evidence for the region.
+    evidence: String,
+}
+
+/// Proposes read structures for the sampled reads, most confident first.
+///
+/// # Errors
+///
+/// - If there are no reads.
+pub fn infer(
+    profile: &ReadProfile,
+    options: &InferenceOptions,
+) -> Result<Vec<Candidate>, ReadStructureError> {
+    if profile.number_of_reads() == 0 {
+        return Err(ReadStructureError::InferenceNoReads);
+    }
+    let regions = regions(profile, options);
+    let choices: Vec<Vec<Choice>> = regions
+        .iter()
+        .enumerate()
+        .map(|(i, region)| choices(profile, options, region, i + 1 == regions.len()))
+        .collect();
+
+    // Beam search over the choices for each region, keeping the most confident combinations
+    let beam_width = options.max_candidates.max(1) * 4;
+    let mut beams: Vec<(Vec<usize>, f64)> = vec![(Vec::new(), 1.0)];
+    for region_choices in &choices {
+        let mut next: Vec<(Vec<usize>, f64)> = beams
+            .iter()
+            .flat_map(|(picked, confidence)| {
+                region_choices.iter().enumerate().map(move |(i, choice)| {
+                    let mut picked = picked.clone();
+                    picked.push(i);
+                    (picked, confidence * choice.confidence)
+                })
+            })
+            .collect();
+        next.sort_by(|a, b| b.1.total_cmp(&a.1));
+        next.truncate(beam_width);
+        beams = next;
+    }
+
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for (picked, confidence) in beams {
+        let segments: Vec<(Region, &Choice)> = regions
+            .iter()
+            .zip(&picked)
+            .zip(&choices)
+            .map(|((region, &i), region_choices)| (*region, &region_choices[i]))
+            .collect();
+        add_candidate(&mut candidates, build_candidate(profile, &segments, confidence)?);
+    }
+    if options.chemistries {
+        for candidate in chemistry_candidates(profile, &regions)? {
+            add_candidate(&mut candidates, candidate);
+        }
+    }
+    candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    candidates.truncate(options.max_candidates);
+    Ok(candidates)
+}
+
+/// Adds a candidate, keeping only the most confident of candidates with the same read structure.
+fn add_candidate(candidates: &mut Vec<Candidate>, candidate: Candidate) {
+    match candidates.iter_mut().find(|c| c.read_structure == candidate.read_structure) {
+        Some(existing) if existing.confidence >= candidate.confidence => (),
+        Some(existing) => *existing = candidate,
+        None => candidates.push(candidate),
+    }
+}
+
+/// Marks each cycle shared by all reads and groups them into regions.
+fn regions(profile: &ReadProfile, options: &InferenceOptions) -> Vec<Region> {
+    let n = profile.min_length();
+    let mut marks: Vec<Option<Mark>> = vec![None; n];
+
+    for (index, list) in options.barcode_lists.iter().enumerate() {
+        let best = best_offset(profile, &marks, list.length(), |window| list.contains(window));
+        if let Some((offset, fraction)) = best {
+            marks[offset..offset + list.length()].fill(Some(Mark::Barcodes(index, fraction)));
+        }
+    }
+
+    for (index, known) in options.known_sequences.iter().enumerate() {
+        let length = known.sequence.len();
+        let best = best_offset(profile, &marks, length, |window| window == known.sequence);
+        if let Some((offset, fraction)) = best {
+            marks[offset..offset + length].fill(Some(Mark::Known(index, fraction)));
+        }
+    }
+
+    let mut start = 0;
+    while start < n {
+        let is_poly_t = |i: usize| {
+            marks[i].is_none() && profile.cycles()[i].fraction(b'T') >= MIN_POLY_T_FRACTION
+        };
+        let end = (start..n).find(|&i| !is_poly_t(i)).unwrap_or(n);
+        if end - start >= MIN_POLY_T_LENGTH {
+            marks[start..end].fill(Some(Mark::PolyT));
+        }
+        start = end.max(start + 1);
+    }
+
+    let marks: Vec<Mark> = marks
+        .into_iter()
+        .zip(profile.cycles())
+        .map(|(mark, stats)| {
+            mark.unwrap_or(if stats.dominant().1 >= MIN_CONSTANT_FRACTION {
+                Mark::Constant
+            } else {
+                Mark::Variable
+            })
+        })
+        .collect();
+
+    let mut regions: Vec<Region> = Vec::new();
+    for (cycle, mark) in marks.into_iter().enumerate() {
+        match regions.last_mut() {
+            Some(region) if region.mark == mark => region.end = cycle + 1,
+            _ => regions.push(Region { start: cycle, end: cycle + 1, mark }),
+        }
+    }
+    regions
+}
+
+/// Returns the offset, among unmarked cycles, at which the most reads match, and the fraction of
+/// reads matching, if at least [`MIN_MATCH_FRACTION`] of reads match.
+fn best_offset<F>(
+    profile: &ReadProfile,
+    marks: &[Option<Mark>],
+    length: usize,
+    matches: F,
+) -> Option<(usize, f64)>
+where
+    F: Fn(&[u8]) -> bool,
+{
+    let n = marks.len();
+    if length == 0 || length > n {
+        return None;
+    }
+    (0..=n - length)
+        .filter(|&offset| marks[offset..offset + length].iter().all(Option::is_none))
+        .map(|offset| {
+            let hits = profile.windows(offset, offset + length).filter(|w| matches(w)).count();
+            (offset, hits as f64 / profile.number_of_reads() as f64)
+        })
+        .filter(|&(_, fraction)| fraction >= MIN_MATCH_FRACTION)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Returns the possible segment types for a region, most confident first.
+fn choices(
+    profile: &ReadProfile,
+    options: &InferenceOptions,
+    region: &Region,
+    is_last: bool,
+) -> Vec<Choice> {
+    let cycles = cycle_range(region.start, Some(region.end));
+    let choice = |kind, confidence: f64, evidence: String| Choice { kind, confidence, evidence };
+    match region.mark {
+        Mark::Barcodes(index, fraction) => {
+            let list = &options.barcode_lists[index];
+            vec![choice(
+                list.kind(),
+                fraction,
+                format!(
+                    "{}: {:.0}% of reads match barcode list '{}'",
+                    cycles,
+                    fraction * 100.0,
+                    list.name()
+                ),
+            )]
+        }
+        Mark::Known(index, fraction) => vec![choice(
+            SegmentType::Skip,
+            fraction,
+            format!(
+                "{}: {:.0}% of reads match {}",
+                cycles,
+                fraction * 100.0,
+                options.known_sequences[index].name
+            ),
+        )],
+        Mark::PolyT => {
+            let fraction =
+                mean(profile.cycles()[region.start..region.end].iter().map(|c| c.fraction(b'T')));
+            vec![choice(
+                SegmentType::Skip,
+                fraction,
+                format!("{}: poly-T run, {:.0}% T", cycles, fraction * 100.0),
+            )]
+        }
+        Mark::Constant => {
+            let fraction =
+                mean(profile.cycles()[region.start..region.end].iter().map(|c| c.dominant().1));
+            let consensus: String = profile.cycles()[region.start..region.end]
+                .iter()
+                .map(|c| c.dominant().0 as char)
+                .collect();
+            let evidence = format!(
+                "{}: constant sequence {} in {:.0}% of reads",
+                cycles,
+                consensus,
+                fraction * 100.0
+            );
+            if is_last && region.end - region.start > MAX_BARCODE_LENGTH {
+                // A long constant tail is more likely amplicon template than a linker
+                vec![
+                    choice(SegmentType::Template, 0.6, evidence.clone()),
+                    choice(SegmentType::Skip, 0.4, evidence),
+                ]
+            } else {
+                vec![choice(SegmentType::Skip, fraction, evidence)]
+            }
+        }
+        Mark::Variable => variable_choices(profile, region, is_last),
+    }
+}
+
+/// Returns the possible segment types for a region of variable cycles, most confident first.
+fn variable_choices(profile: &ReadProfile, region: &Region, is_last: bool) -> Vec<Choice> {
+    let length = region.end - region.start;
+    let entropy = profile.mean_entropy(region.start, region.end);
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for window in profile.windows(region.start, region.end) {
+        *counts.entry(window).or_default() += 1;
+    }
+    let reads = profile.number_of_reads();
+    let distinct = counts.len() as f64 / reads as f64;
+    let top = (reads / 10).clamp(1, MAX_SAMPLE_BARCODES);
+    let mut counts: Vec<usize> = counts.into_values().collect();
+    counts.sort_unstable_by(|a, b| b.cmp(a));
+    let coverage = counts.iter().take(top).sum::<usize>() as f64 / reads as f64;
+    let evidence = format!(
+        "{}: mean entropy {:.2} bits, {:.0}% distinct sequences, the {} most common cover {:.0}% \
+         of reads",
+        cycle_range(region.start, Some(region.end)),
+        entropy,
+        distinct * 100.0,
+        top,
+        coverage * 100.0
+    );
+
+    let weights: Vec<(SegmentType, f64)> =
+        if length <= MAX_BARCODE_LENGTH && coverage >= MIN_SAMPLE_BARCODE_COVERAGE {
+            vec![
+                (SegmentType::SampleBarcode, coverage),
+                (SegmentType::MolecularBarcode, 1.0 - coverage),
+            ]
+        } else if is_last && length >= 2 * MAX_BARCODE_LENGTH / 3 {
+            vec![(SegmentType::Template, 0.9), (SegmentType::MolecularBarcode, 0.1)]
+        } else if is_last {
+            vec![
+                (SegmentType::Template, 0.5),
+                (SegmentType::MolecularBarcode, 0.3),
+                (SegmentType::CellularBarcode, 0.2),
+            ]
+        } else if length <= MAX_BARCODE_LENGTH * 2 / 3 {
+            vec![
+                (SegmentType::MolecularBarcode, 0.6),
+                (SegmentType::CellularBarcode, 0.3),
+                (SegmentType::Template, 0.1),
+            ]
+        } else {
+            vec![
+                (SegmentType::Template, 0.6),
+                (SegmentType::MolecularBarcode, 0.2),
+                (SegmentType::CellularBarcode, 0.2),
+            ]
+        };
+    weights
+        .into_iter()
+        .filter(|&(_, confidence)| confidence > 0.0)
+        .map(|(kind, confidence)| Choice { kind, confidence, evidence: evidence.clone() })
+        .collect()
+}
+
+/// Builds a candidate from the chosen segment type of each region, merging adjacent regions of
+/// the same type.
+fn build_candidate(
+    profile: &ReadProfile,
+    chosen: &[(Region, &Choice)],
+    confidence: f64,
+) -> Result<Candidate, ReadStructureError> {
+    // (start, end, kind, confidence, evidence) for each merged segment
+    let mut merged: Vec<(usize, usize, SegmentType, f64, Vec<String>)> = Vec::new();
+    for (region, choice) in chosen {
+        match merged.last_mut() {
+            Some(last) if last.2 == choice.kind => {
+                last.1 = region.end;
+                last.3 = last.3.min(choice.confidence);
+                last.4.push(choice.evidence.clone());
+            }
+            _ => {
+                let mut evidence = Vec::new();
+                if region.start > 0 {
+                    evidence.push(format!("starts at cycle {}", region.start + 1));
+                }
+                evidence.push(choice.evidence.clone());
+                merged.push((region.start, region.end, choice.kind, choice.confidence, evidence));
+            }
+        }
+    }
+
+    let variable_length = !profile.has_fixed_length();
+    let mut segments: Vec<SegmentEvidence> = merged
+        .into_iter()
+        .map(|(start, end, kind, confidence, evidence)| SegmentEvidence {
+            segment: ReadSegment { offset: start, length: Some(end - start), kind },
+            confidence,
+            evidence,
+        })
+        .collect();
+    let length_evidence = format!(
+        "reads vary in length from {} to {} bases",
+        profile.min_length(),
+        profile.max_length()
+    );
+    let ends_with_template =
+        segments.last().map_or(false, |last| last.segment.kind == SegmentType::Template);
+    if ends_with_template {
+        let last = segments.last_mut().expect("segments is not empty");
+        last.segment.length = None;
+        if variable_length {
+            last.evidence.push(length_evidence);
+        }
+    } else if variable_length || segments.is_empty() {
+        segments.push(SegmentEvidence {
+            segment: ReadSegment {
+                offset: profile.min_length(),
+                length: None,
+                kind: SegmentType::Template,
+            },
+            confidence: 1.0,
+            evidence: vec![length_evidence],
+        });
+    }
+    let read_structure = ReadStructure::new(segments.iter().map(|s| s.segment).collect())?;
+    Ok(Candidate { read_structure, confidence, segments })
+}
+
+/// Returns candidates for the read structures of known chemistries that fit the reads, with
+/// confidence reflecting how consistent each segment is with the marked cycles.
+fn chemistry_candidates(
+    profile: &ReadProfile,
+    regions: &[Region],
+) -> Result<Vec<Candidate>, ReadStructureError> {
+    let mut seen: Vec<ReadStructure> = Vec::new();
+    let mut candidates = Vec::new();
+    for chemistry in Chemistry::iter() {
+        let mrs = chemistry.read_structures();
+        for (read_name, rs) in chemistry.read_names().iter().zip(mrs.iter()) {
+            if seen.contains(rs) {
+                continue;
+            }
+            seen.push(rs.clone());
+            let fits = match rs.fixed_length() {
+                Some(length) => profile.has_fixed_length() && length == profile.min_length(),
+                None => rs.length_of_fixed_segments() < profile.min_length(),
+            };
+            if !fits {
+                continue;
+            }
+            let segments: Vec<SegmentEvidence> = rs
+                .iter()
+                .map(|segment| {
+                    let end = segment.length.map_or(profile.min_length(), |l| segment.offset + l);
+                    let (confidence, consistent) =
+                        consistency(regions, segment.kind, segment.offset, end);
+                    SegmentEvidence {
+                        segment: *segment,
+                        confidence,
+                        evidence: vec![format!(
+                            "{}: {} of {}, consistent with {} of {} cycles",
+                            cycle_range(segment.offset, segment.length.map(|_| end)),
+                            read_name,
+                            chemistry,
+                            consistent,
+                            end.saturating_sub(segment.offset)
+                        )],
+                    }
+                })
+                .collect();
+            let confidence =
+                CHEMISTRY_CONFIDENCE_SCALE * segments.iter().map(|s| s.confidence).product::<f64>();
+            candidates.push(Candidate { read_structure: rs.clone(), confidence, segments });
+        }
+    }
+    Ok(candidates)
+}
+
+/// Returns the confidence that the cycles in the range are a segment of the given type, and the
+/// number of cycles consistent with the type: skips should be constant, and other types should be
+/// variable (or match barcodes).  Each inconsistent cycle halves the confidence, and each variable
+/// cycle in a skip reduces it slightly, so that misplaced boundaries are strongly penalized.
+fn consistency(regions: &[Region], kind: SegmentType, start: usize, end: usize) -> (f64, usize) {
+    let score = |mark: Mark| match (kind, mark) {
+        (SegmentType::Skip, Mark::Constant | Mark::PolyT | Mark::Known(..)) => 1.0,
+        (SegmentType::Skip, _) => 0.8,
+        (_, Mark::Variable) => 1.0,
+        (kind, Mark::Barcodes(..)) if kind != SegmentType::Template => 1.0,
+        _ => 0.5,
+    };
+    let mut confidence = 1.0;
+    let mut consistent = 0;
+    for region in regions {
+        let overlap = region.end.min(end).saturating_sub(region.start.max(start));
+        let score: f64 = score(region.mark);
+        confidence *= score.powi(overlap as i32);
+        if score >= 1.0 {
+            consistent += overlap;
+        }
+    }
+    (confidence, consistent)
+}
+
+/// Formats a zero-based range of cycles as one-based inclusive cycles.
+fn cycle_range(start: usize, end: Option<usize>) -> String {
+    match end {
+        Some(end) if end == start + 1 => format!("cycle {}", end),
+        Some(end) => format!("cycles {}-{}", start + 1, end),
+        None => format!("cycles {}-", start + 1),
+    }
+}
+
+/// Returns the mean of the values, or zero if there are none.
+fn mean<I: Iterator<Item = f64>>(values: I) -> f64 {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::infer::{infer, BarcodeList, CycleStats, InferenceOptions, ReadProfile};
+    use crate::SegmentType;
+
+    /// A simple deterministic pseudo-random base generator.
+    struct Bases(u64);
+
+    impl Bases {
+        fn next(&mut self) -> u8 {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            b"ACGT"[(self.0 >> 62) as usize]
+        }
+
+        fn take(&mut self, n: usize) -> Vec<u8> {
+            (0..n).map(|_| self.next()).collect()
+        }
+    }
+
+    fn structures(profile: &ReadProfile, options: &InferenceOptions) -> Vec<String> {
+        infer(profile, options).unwrap().iter().map(|c| c.read_structure.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cycle_stats() {
+        let mut stats = CycleStats::default();
+        for base in b"AACGTN" {
+            stats.add(*base);
+        }
+        assert_eq!(stats.total(), 6);
+        assert_eq!(stats.count(b'N'), 1);
+        assert_eq!(stats.dominant(), (b'A', 0.4));
+        assert!(stats.entropy() > 1.9 && stats.entropy() < 2.0);
+        let mut constant = CycleStats::default();
+        constant.add(b'G');
+        assert_eq!(constant.entropy(), 0.0);
+    }
+
+    #[test]
+    fn test_infer_umi_linker_template() {
+        let mut bases = Bases(1);
+        let reads: Vec<Vec<u8>> = (0..1000)
+            .map(|_| [bases.take(6), b"ATGGTA".to_vec(), bases.take(60)].concat())
+            .collect();
+        let profile = ReadProfile::from_reads(reads, 10_000);
+        let candidates = infer(&profile, &InferenceOptions::default()).unwrap();
+        assert_eq!(candidates[0].read_structure.to_string(), "6M6S+T");
+        let evidence = &candidates[0].segments[1].evidence;
+        assert_eq!(evidence[0], "starts at cycle 7");
+        assert_eq!(evidence[1], "cycles 7-12: constant sequence ATGGTA in 100% of reads");
+        assert!(candidates.windows(2).all(|w| w[0].confidence >= w[1].confidence));
+    }
+
+    #[test]
+    fn test_infer_single_cell_with_whitelist_and_poly_t() {
+        let mut bases = Bases(2);
+        let whitelist: Vec<Vec<u8>> = (0..50).map(|_| bases.take(16)).collect();
+        let reads: Vec<Vec<u8>> = (0..1000)
+            .map(|i| {
+                let len = 80 + i % 10;
+                let barcode = whitelist[i % whitelist.len()].clone();
+                [barcode, bases.take(12), vec![b'T'; 30], bases.take(len - 58)].concat()
+            })
+            .collect();
+        let profile = ReadProfile::from_reads(reads, 10_000);
+        assert!(!profile.has_fixed_length());
+        let mut options = InferenceOptions::default();
+        options
+            .barcode_lists
+            .push(BarcodeList::new("whitelist", SegmentType::CellularBarcode, &whitelist).unwrap());
+        let candidates = infer(&profile, &options).unwrap();
+        assert_eq!(candidates[0].read_structure.to_string(), "16C12M30S+T");
+        assert_eq!(
+            candidates[0].segments[0].evidence,
+            vec!["cycles 1-16: 100% of reads match barcode list 'whitelist'"]
+        );
+        assert_eq!(candidates[0].segments[2].evidence[1], "cycles 29-58: poly-T run, 100% T");
+    }
+
+    #[test]
+    fn test_infer_sample_barcode_and_chemistry() {
+        let mut bases = Bases(3);
+        let barcodes: Vec<Vec<u8>> = (0..8).map(|_| bases.take(8)).collect();
+        let reads: Vec<Vec<u8>> = (0..1000).map(|i| barcodes[i % 8].clone()).collect();
+        let profile = ReadProfile::from_reads(reads, 10_000);
+        assert_eq!(structures(&profile, &InferenceOptions::default())[0], "8B");
+
+        // A 10x 3' v3 R1 with no whitelist is ambiguous, but the chemistry is proposed
+        let reads: Vec<Vec<u8>> = (0..1000).map(|_| bases.take(28)).collect();
+        let profile = ReadProfile::from_reads(reads, 10_000);
+        let found = structures(&profile, &InferenceOptions::default());
+        assert!(found.contains(&"16C12M".to_owned()), "{:?}", found);
+        let options = InferenceOptions { chemistries: false, ..InferenceOptions::default() };
+        assert!(!structures(&profile, &options).contains(&"16C12M".to_owned()));
+    }
+
+    #[test]
+    fn test_infer_errors() {
+        let reads: Vec<Vec<u8>> = Vec::new();
+        let profile = ReadProfile::from_reads(reads, 10);
+        assert!(infer(&profile, &InferenceOptions::default()).is_err());
+        assert!(
+            BarcodeList::new("empty", SegmentType::CellularBarcode, Vec::<&[u8]>::new()).is_err()
+        );
+        assert!(BarcodeList::new("mixed", SegmentType::CellularBarcode, ["ACGT", "ACG"]).is_err());
+        let list = BarcodeList::from_reader(
+            "list",
+            SegmentType::SampleBarcode,
+            &b"# header\nacgt\n\nTTTT x\n"[..],
+        )
+        .unwrap();
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(b"ACGT"));
+    }
+}
@@ -46,13 +46,41 @@
 #![allow(unused, clippy::must_use_candidate)]
 #![allow(dead_code)]
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod cell_barcode;
+pub mod chemistry;
+pub mod collision;
+pub mod color_balance;
+pub mod compatibility;
+pub mod cycle_map;
+pub mod demux;
+pub mod dialect;
+pub mod diff;
+mod extraction;
+pub mod fastq;
+pub mod illumina;
+pub mod infer;
+mod multi_read_structure;
+pub mod orientation;
+pub mod read_name;
 mod read_segment;
 mod read_structure;
+pub mod sam;
 mod segment_type;
+#[cfg(feature = "seqspec")]
+pub mod seqspec;
+#[cfg(feature = "serde")]
+mod serde_impls;
+pub mod umi;
 
 pub use crate::read_structure::*;
+pub use extraction::*;
+pub use multi_read_structure::*;
 pub use read_segment::*;
 pub use segment_type::*;
+#[cfg(feature = "serde")]
+pub use serde_impls::structured;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -101,6 +129,105 @@ pub enum ReadStructureError {
 
     #[error("Invalid SegmentType: {0}")]
     ReadSegmentTypeStringInvalid(String),
+
+    #[error("Multi-read structure contains zero reads")]
+    MultiReadStructureContainsZeroReads,
+
+    #[error("Mismatching number of reads and read structures: {actual}, {expected}")]
+    MismatchingNumberOfReads { expected: usize, actual: usize },
+
+    #[error("Invalid Illumina FASTQ header comment: {0}")]
+    IlluminaCommentInvalid(String),
+
+    #[error("SAM header has no read group with ID: {0}")]
+    SamReadGroupNotFound(String),
+
+    #[error("Extraction is missing segment: {0}")]
+    ExtractionMissingSegment(String),
+
+    #[error("Arrow error: {0}")]
+    Arrow(String),
+
+    #[error("Unknown chemistry: {0}")]
+    ChemistryUnknown(String),
+
+    #[error("Read name is missing appended fields: {0}")]
+    ReadNameMissingFields(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid seqspec: {0}")]
+    SeqspecInvalid(String),
+
+    #[error("seqspec read not found: {0}")]
+    SeqspecMissingRead(String),
+
+    #[error("seqspec region not found: {0}")]
+    SeqspecMissingRegion(String),
+
+    #[error("seqspec region {region_id} has unsupported region type: {region_type}")]
+    SeqspecUnsupportedRegionType { region_id: String, region_type: String },
+
+    #[error("Invalid FASTQ record at line {line}: {message}")]
+    FastqInvalid { line: usize, message: String },
+
+    #[error("FASTQ read names do not match: {first}, {other}")]
+    FastqReadNamesMismatch { first: String, other: String },
+
+    #[error("No reads to infer a read structure from")]
+    InferenceNoReads,
+
+    #[error("Invalid barcode list: {0}")]
+    BarcodeListInvalid(String),
+
+    #[error("Unknown read structure dialect: {0}")]
+    DialectUnknown(String),
+
+    #[error("{dialect} cannot represent {construct}")]
+    DialectUnsupported { dialect: String, construct: String },
+
+    #[error("Invalid {dialect} value '{value}': {message}")]
+    DialectInvalid { dialect: String, value: String, message: String },
+
+    #[error("Read structure {read_structure} does not fit reads of length {length}")]
+    ReadLengthMismatch { read_structure: String, length: usize },
+
+    #[error("Invalid sample sheet: {0}")]
+    SampleSheetInvalid(String),
+
+    #[error("Invalid whitelist: {0}")]
+    WhitelistInvalid(String),
+
+    #[error("Extraction is missing base qualities")]
+    ExtractionMissingQuals,
+
+    #[error("Unknown UMI grouping strategy: {0}")]
+    UmiStrategyUnknown(String),
+
+    #[error("Invalid UMI: {0}")]
+    UmiInvalid(String),
+
+    #[error("Read structure has no fixed length sample barcode segments: {0}")]
+    SampleBarcodeSegmentsInvalid(String),
+
+    #[error("Unknown instrument workflow: {0}")]
+    InstrumentWorkflowUnknown(String),
+
+    #[error("Unknown color chemistry: {0}")]
+    ColorChemistryUnknown(String),
+
+    #[error("Invalid range {range} for read structure {read_structure}")]
+    ReadStructureRangeInvalid { read_structure: String, range: String },
+
+    #[error("Invalid segment label: {}[{}]{}", .0.prefix, .0.error, .0.suffix)]
+    ReadSegmentLabelInvalid(ErrorMessageParts),
+
+    #[error("Read structure has more than one segment labelled: {0}")]
+    ReadStructureDuplicateLabel(String),
+
+    #[error("ReadSegment str contained a label, which only read structures hold: {0}")]
+    ReadSegmentLabelled(String),
 }
 
 /// Helper struct for isolating the erroneous portion of a string.
@@ -122,4 +249,34 @@ impl ErrorMessageParts {
         };
         Self { prefix, error, suffix }
     }
+
+    /// Returns the portion of the string before the erroneous portion.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Returns the erroneous portion of the string.
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+
+    /// Returns the portion of the string after the erroneous portion.
+    pub fn suffix(&self) -> &str {
+        &self.suffix
+    }
+}
+
+impl ReadStructureError {
+    /// Returns the parts of the string isolating the erroneous portion, for errors that
+    /// identify one.
+    pub fn error_message_parts(&self) -> Option<&ErrorMessageParts> {
+        match self {
+            ReadStructureError::ReadStructureMissingLengthInformation(parts)
+            | ReadStructureError::ReadStructureMissingOperator(parts)
+            | ReadStructureError::ReadStructureHadUnknownType(parts)
+            | ReadStructureError::ReadSegmentLengthZero(parts)
+            | ReadStructureError::ReadSegmentLabelInvalid(parts) => Some(parts),
+            _ => None,
+        }
+    }
 }
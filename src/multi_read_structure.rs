@@ -0,0 +1,347 @@
+//! Multi-Read Structures
+//!
+//! Type [`MultiReadStructure`] describes the structures of all the reads (e.g. R1, I1, I2, R2)
+//! produced for each cluster in a sequencing run, with one [`ReadStructure`] per read.  The
+//! string form is the per-read structures separated by whitespace (e.g. `76T 8B 8B 76T`).
+//!
+//! Each read also has an [`Orientation`] (see [`crate::orientation`]), which is not part of the
+//! string form and defaults to [`Orientation::Forward`].
+
+use std::ops::Index;
+use std::str::FromStr;
+
+use crate::extraction::Extraction;
+use crate::illumina::IlluminaComment;
+use crate::orientation::Orientation;
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// The read structures for one or more reads.  See [the module level documentation](self) for
+/// more.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiReadStructure {
+    /// The read structure for each read, in sequencing order.
+    read_structures: Vec<ReadStructure>,
+    /// The orientation of each read.
+    orientations: Vec<Orientation>,
+}
+
+impl MultiReadStructure {
+    /// Builds a new [`MultiReadStructure`] from one read structure per read.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if no read structures are given.
+    pub fn new(read_structures: Vec<ReadStructure>) -> Result<Self, ReadStructureError> {
+        if read_structures.is_empty() {
+            return Err(ReadStructureError::MultiReadStructureContainsZeroReads);
+        }
+        let orientations = vec![Orientation::Forward; read_structures.len()];
+        Ok(MultiReadStructure { read_structures, orientations })
+    }
+
+    /// Returns the number of reads.
+    pub fn number_of_reads(&self) -> usize {
+        self.read_structures.len()
+    }
+
+    /// Returns the read structure for each read.
+    pub fn read_structures(&self) -> &[ReadStructure] {
+        &self.read_structures
+    }
+
+    /// Returns the orientation of the read at the given index.
+    pub fn orientation(&self, read_index: usize) -> Orientation {
+        self.orientations[read_index]
+    }
+
+    /// Returns the orientation of each read.
+    pub fn orientations(&self) -> &[Orientation] {
+        &self.orientations
+    }
+
+    /// Returns a new [`MultiReadStructure`] with the given orientation for each read.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` if the number of orientations differs from the number of reads.
+    pub fn with_orientations(
+        &self,
+        orientations: Vec<Orientation>,
+    ) -> Result<Self, ReadStructureError> {
+        self.check_read_count(orientations.len())?;
+        Ok(MultiReadStructure { read_structures: self.read_structures.clone(), orientations })
+    }
+
+    /// Returns an iterator over the read structures.
+    pub fn iter(&self) -> impl Iterator<Item = &ReadStructure> {
+        self.read_structures.iter()
+    }
+
+    /// Returns the [`ReadSegment`]s of the given kind across all reads, each paired with the
+    /// index of the read that contains it.
+    pub fn segments_by_type(
+        &self,
+        kind: SegmentType,
+    ) -> impl Iterator<Item = (usize, &ReadSegment)> {
+        self.read_structures
+            .iter()
+            .enumerate()
+            .flat_map(move |(i, rs)| rs.segments_by_type(kind).map(move |s| (i, s)))
+    }
+
+    /// Returns the template [`ReadSegment`]s across all reads.
+    pub fn templates(&self) -> impl Iterator<Item = (usize, &ReadSegment)> {
+        self.segments_by_type(SegmentType::Template)
+    }
+
+    /// Returns the sample barcode [`ReadSegment`]s across all reads.
+    pub fn sample_barcodes(&self) -> impl Iterator<Item = (usize, &ReadSegment)> {
+        self.segments_by_type(SegmentType::SampleBarcode)
+    }
+
+    /// Returns the molecular barcode [`ReadSegment`]s across all reads.
+    pub fn molecular_barcodes(&self) -> impl Iterator<Item = (usize, &ReadSegment)> {
+        self.segments_by_type(SegmentType::MolecularBarcode)
+    }
+
+    /// Returns the skip [`ReadSegment`]s across all reads.
+    pub fn skips(&self) -> impl Iterator<Item = (usize, &ReadSegment)> {
+        self.segments_by_type(SegmentType::Skip)
+    }
+
+    /// Returns the cellular barcode [`ReadSegment`]s across all reads.
+    pub fn cellular_barcodes(&self) -> impl Iterator<Item = (usize, &ReadSegment)> {
+        self.segments_by_type(SegmentType::CellularBarcode)
+    }
+
+    /// Returns a new [`MultiReadStructure`] with the given read structures appended as
+    /// additional reads, in [`Orientation::Forward`].
+    #[must_use]
+    pub fn with_reads<I: IntoIterator<Item = ReadStructure>>(&self, read_structures: I) -> Self {
+        let mut read_structures_out = self.read_structures.clone();
+        read_structures_out.extend(read_structures);
+        let mut orientations = self.orientations.clone();
+        orientations.resize(read_structures_out.len(), Orientation::Forward);
+        MultiReadStructure { read_structures: read_structures_out, orientations }
+    }
+
+    /// Extracts the bases for every [`ReadSegment`] from the given reads, one per read
+    /// structure, in the same order as the read structures.  Each extracted segment carries the
+    /// orientation of its read.
+    ///
+    /// # Errors
+    ///
+    /// - If the number of reads does not match the number of read structures.
+    /// - If any segment does not fall wholely within its read.
+    pub fn extract<'a>(&self, reads: &[&'a [u8]]) -> Result<Extraction<'a>, ReadStructureError> {
+        self.check_read_count(reads.len())?;
+        let mut extraction = Extraction::new(vec![]);
+        for (rs, bases) in self.read_structures.iter().zip(reads) {
+            extraction.append(rs.extract(bases)?);
+        }
+        Ok(self.orient(extraction))
+    }
+
+    /// Extracts the bases for every [`ReadSegment`] from the given reads, as
+    /// [`MultiReadStructure::extract`] does, followed by the index reads in an Illumina FASTQ
+    /// header comment as additional sample barcode pseudo-reads (see
+    /// [`IlluminaComment::extract_index`]).  Sample barcodes are then found the same way
+    /// whether they came from index FASTQs or from the header.
+    ///
+    /// # Errors
+    ///
+    /// - If the number of reads does not match the number of read structures.
+    /// - If any segment does not fall wholely within its read.
+    pub fn extract_with_comment<'a>(
+        &self,
+        reads: &[&'a [u8]],
+        comment: &'a IlluminaComment,
+    ) -> Result<Extraction<'a>, ReadStructureError> {
+        let mut extraction = self.extract(reads)?;
+        extraction.append(comment.extract_index());
+        Ok(extraction)
+    }
+
+    /// Extracts the bases and qualities for every [`ReadSegment`] from the given reads, one
+    /// `(bases, quals)` pair per read structure, in the same order as the read structures.
+    ///
+    /// # Errors
+    ///
+    /// - If the number of reads does not match the number of read structures.
+    /// - If any segment does not fall wholely within its read.
+    /// - If the bases and quals lengths of any read are not equal.
+    pub fn extract_with_quals<'a>(
+        &self,
+        reads: &[(&'a [u8], &'a [u8])],
+    ) -> Result<Extraction<'a>, ReadStructureError> {
+        self.check_read_count(reads.len())?;
+        let mut extraction = Extraction::new(vec![]);
+        for (rs, (bases, quals)) in self.read_structures.iter().zip(reads) {
+            extraction.append(rs.extract_with_quals(bases, quals)?);
+        }
+        Ok(self.orient(extraction))
+    }
+
+    /// Sets the orientation of each extracted segment to that of its read.
+    fn orient<'a>(&self, mut extraction: Extraction<'a>) -> Extraction<'a> {
+        for segment in extraction.segments_mut() {
+            segment.orientation = self.orientations[segment.read_index];
+        }
+        extraction
+    }
+
+    /// Returns `Err` if the given number of reads differs from the number of read structures.
+    fn check_read_count(&self, actual: usize) -> Result<(), ReadStructureError> {
+        if actual == self.read_structures.len() {
+            Ok(())
+        } else {
+            Err(ReadStructureError::MismatchingNumberOfReads {
+                expected: self.read_structures.len(),
+                actual,
+            })
+        }
+    }
+
+    /// Returns the indices of the reads that contain at least one segment of the given kind.
+    pub fn reads_with_type(&self, kind: SegmentType) -> Vec<usize> {
+        self.read_structures
+            .iter()
+            .enumerate()
+            .filter(|(_, rs)| rs.segments_by_type(kind).next().is_some())
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+impl IntoIterator for MultiReadStructure {
+    type Item = ReadStructure;
+
+    type IntoIter = std::vec::IntoIter<Self::Item>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.read_structures.into_iter()
+    }
+}
+
+impl Index<usize> for MultiReadStructure {
+    type Output = ReadStructure;
+
+    /// Returns the [`ReadStructure`] for the read at the given index.
+    fn index(&self, idx: usize) -> &Self::Output {
+        &self.read_structures[idx]
+    }
+}
+
+impl std::fmt::Display for MultiReadStructure {
+    /// Formats the read structures separated by single spaces.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, rs) in self.read_structures.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", rs)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for MultiReadStructure {
+    type Err = ReadStructureError;
+
+    /// Returns a new multi-read structure from whitespace separated read structures, or `Err`
+    /// if parsing any read structure failed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let read_structures =
+            s.split_whitespace().map(ReadStructure::from_str).collect::<Result<Vec<_>, _>>()?;
+        Self::new(read_structures)
+    }
+}
+
+impl TryFrom<Vec<ReadStructure>> for MultiReadStructure {
+    type Error = ReadStructureError;
+
+    /// Builds a new multi-read structure from one read structure per read.
+    fn try_from(read_structures: Vec<ReadStructure>) -> Result<Self, Self::Error> {
+        Self::new(read_structures)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::multi_read_structure::MultiReadStructure;
+    use crate::orientation::Orientation;
+    use crate::read_structure::ReadStructure;
+    use crate::segment_type::SegmentType;
+
+    #[test]
+    fn test_multi_read_structure_from_str() {
+        let mrs = MultiReadStructure::from_str("  76T 8B\t8B76T ").unwrap();
+        assert_eq!(mrs.number_of_reads(), 3);
+        assert_eq!(mrs.to_string(), "76T 8B 8B76T");
+        assert_eq!(mrs[2].to_string(), "8B76T");
+        assert!(MultiReadStructure::from_str("").is_err());
+        assert!(MultiReadStructure::from_str("76T 8X").is_err());
+    }
+
+    #[test]
+    fn test_multi_read_structure_extract() {
+        let mrs = MultiReadStructure::from_str("4M+T 3B").unwrap();
+        let extraction = mrs.extract(&[b"AAAACCCC", b"GGG"]).unwrap();
+        let segments: Vec<(usize, &[u8])> =
+            extraction.iter().map(|s| (s.read_index, s.bases)).collect();
+        assert_eq!(segments, vec![(0, &b"AAAA"[..]), (0, b"CCCC"), (1, b"GGG")]);
+        assert_eq!(extraction.number_of_reads(), 2);
+        assert!(mrs.extract(&[b"AAAACCCC"]).is_err());
+        assert!(mrs.extract(&[b"AAAACCCC", b"GG"]).is_err());
+
+        let extraction = mrs.extract_with_quals(&[(b"AAAAC", b"12345"), (b"GGG", b"678")]).unwrap();
+        assert_eq!(extraction.sample_barcodes().next().unwrap().quals, Some(&b"678"[..]));
+    }
+
+    #[test]
+    fn test_multi_read_structure_with_reads() {
+        let mrs = MultiReadStructure::from_str("+T +T").unwrap();
+        let mrs = mrs.with_reads(vec![ReadStructure::from_str("8B").unwrap()]);
+        assert_eq!(mrs.to_string(), "+T +T 8B");
+    }
+
+    #[test]
+    fn test_multi_read_structure_segments_by_type() {
+        let mrs = MultiReadStructure::from_str("16C12M 10B 10B +T").unwrap();
+        let barcodes: Vec<(usize, String)> =
+            mrs.sample_barcodes().map(|(i, s)| (i, s.to_string())).collect();
+        assert_eq!(barcodes, vec![(1, "10B".to_owned()), (2, "10B".to_owned())]);
+        assert_eq!(mrs.cellular_barcodes().count(), 1);
+        assert_eq!(mrs.molecular_barcodes().next().unwrap().0, 0);
+        assert_eq!(mrs.reads_with_type(SegmentType::Template), vec![3]);
+        assert!(mrs.reads_with_type(SegmentType::Skip).is_empty());
+    }
+
+    #[test]
+    fn test_multi_read_structure_orientations() {
+        let mrs = MultiReadStructure::from_str("+T 4B").unwrap();
+        assert_eq!(mrs.orientations(), [Orientation::Forward, Orientation::Forward]);
+        assert!(mrs.with_orientations(vec![Orientation::ReverseComplement]).is_err());
+        let mrs = mrs
+            .with_orientations(vec![Orientation::Forward, Orientation::ReverseComplement])
+            .unwrap();
+        assert_eq!(mrs.orientation(1), Orientation::ReverseComplement);
+        assert_eq!(mrs.to_string(), "+T 4B");
+
+        let extraction = mrs.extract_with_quals(&[(b"ACGT", b"1234"), (b"AACN", b"5678")]).unwrap();
+        let template = extraction.templates().next().unwrap();
+        assert_eq!(template.oriented_bases().as_ref(), b"ACGT");
+        let barcode = extraction.sample_barcodes().next().unwrap();
+        assert_eq!(barcode.bases, b"AACN");
+        assert_eq!(barcode.oriented_bases().as_ref(), b"NGTT");
+        assert_eq!(barcode.oriented_quals().unwrap().as_ref(), b"8765");
+
+        let mrs = mrs.with_reads(vec![ReadStructure::from_str("8B").unwrap()]);
+        assert_eq!(mrs.orientation(2), Orientation::Forward);
+    }
+}
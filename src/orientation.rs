@@ -0,0 +1,220 @@
+//! Read Orientation
+//!
+//! Depending on the instrument, the i5 index read is sequenced either in the same orientation as
+//! the i5 index is listed in forward strand sample sheets (the "forward strand workflow") or as
+//! its reverse complement (the "reverse complement workflow").  Type [`Orientation`] records, per
+//! read of a [`MultiReadStructure`], whether its bases must be reverse complemented to match the
+//! sample sheet; [`crate::ExtractedSegment::oriented_bases`] and
+//! [`crate::ExtractedSegment::oriented_quals`] apply it to extracted segments, while the bases
+//! and qualities as sequenced remain available as they were.
+//!
+//! Type [`InstrumentWorkflow`] names common instruments, and sets the orientation of the i5
+//! read for sample sheets that list i5 indices in forward strand orientation.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::MultiReadStructure;
+//! use read_structure::orientation::{InstrumentWorkflow, Orientation};
+//!
+//! let mrs = MultiReadStructure::from_str("+T 8B 8B +T").unwrap();
+//! let mrs = InstrumentWorkflow::from_str("novaseq-x").unwrap().apply(&mrs);
+//! assert_eq!(mrs.orientation(2), Orientation::ReverseComplement);
+//!
+//! let extraction = mrs.extract(&[b"ACGT", b"AAAAAAAA", b"AAAACCGG", b"ACGT"]).unwrap();
+//! let i5 = extraction.sample_barcodes().nth(1).unwrap();
+//! assert_eq!(i5.bases, b"AAAACCGG");
+//! assert_eq!(i5.oriented_bases().as_ref(), b"CCGGTTTT");
+//! ```
+
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, IntoStaticStr};
+
+use crate::multi_read_structure::MultiReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// The orientation of a read relative to the sample sheet.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// The bases are used as sequenced.
+    #[default]
+    Forward,
+    /// The bases are reverse complemented, and the qualities reversed.
+    ReverseComplement,
+}
+
+/// Returns the reverse complement of the bases.  `A`, `C`, `G` and `T` (in either case) are
+/// complemented, and all other bases (e.g. `N`) are kept as they are.
+pub fn reverse_complement(bases: &[u8]) -> Vec<u8> {
+    bases
+        .iter()
+        .rev()
+        .map(|base| match base {
+            b'A' => b'T',
+            b'C' => b'G',
+            b'G' => b'C',
+            b'T' => b'A',
+            b'a' => b't',
+            b'c' => b'g',
+            b'g' => b'c',
+            b't' => b'a',
+            other => *other,
+        })
+        .collect()
+}
+
+/// A sequencing instrument (and reagent version), and so the orientation of its i5 index read.
+/// See [the module level documentation](self) for more.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Display, EnumIter, IntoStaticStr, PartialEq, Eq, Hash)]
+pub enum InstrumentWorkflow {
+    /// Illumina MiSeq: forward strand workflow.
+    #[strum(serialize = "miseq")]
+    MiSeq,
+    /// Illumina HiSeq 2000 and 2500: forward strand workflow.
+    #[strum(serialize = "hiseq-2500")]
+    HiSeq2500,
+    /// Illumina NovaSeq 6000 with v1.0 reagents: forward strand workflow.
+    #[strum(serialize = "novaseq-6000-v1.0")]
+    NovaSeq6000V1,
+    /// Illumina iSeq 100: reverse complement workflow.
+    #[strum(serialize = "iseq-100")]
+    ISeq100,
+    /// Illumina MiniSeq: reverse complement workflow.
+    #[strum(serialize = "miniseq")]
+    MiniSeq,
+    /// Illumina NextSeq 500 and 550: reverse complement workflow.
+    #[strum(serialize = "nextseq-500")]
+    NextSeq500,
+    /// Illumina NextSeq 1000 and 2000: reverse complement workflow.
+    #[strum(serialize = "nextseq-2000")]
+    NextSeq2000,
+    /// Illumina HiSeq 3000 and 4000: reverse complement workflow.
+    #[strum(serialize = "hiseq-4000")]
+    HiSeq4000,
+    /// Illumina HiSeq X: reverse complement workflow.
+    #[strum(serialize = "hiseq-x")]
+    HiSeqX,
+    /// Illumina NovaSeq 6000 with v1.5 reagents: reverse complement workflow.
+    #[strum(serialize = "novaseq-6000-v1.5")]
+    NovaSeq6000V15,
+    /// Illumina NovaSeq X and X Plus: reverse complement workflow.
+    #[strum(serialize = "novaseq-x")]
+    NovaSeqX,
+}
+
+impl InstrumentWorkflow {
+    /// Returns the name of the instrument workflow.
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+
+    /// Returns the orientation of the i5 index read relative to forward strand sample sheets.
+    pub fn i5_orientation(&self) -> Orientation {
+        match self {
+            InstrumentWorkflow::MiSeq
+            | InstrumentWorkflow::HiSeq2500
+            | InstrumentWorkflow::NovaSeq6000V1 => Orientation::Forward,
+            _ => Orientation::ReverseComplement,
+        }
+    }
+
+    /// Returns the read structures with the orientation of the i5 index read (see
+    /// [`i5_read`]) set for this instrument.  Read structures without an i5 index read are
+    /// returned unchanged.
+    #[must_use]
+    pub fn apply(&self, read_structures: &MultiReadStructure) -> MultiReadStructure {
+        let mut orientations = read_structures.orientations().to_vec();
+        if let Some(i5) = i5_read(read_structures) {
+            orientations[i5] = self.i5_orientation();
+        }
+        // Unwrap is safe since there is one orientation per read
+        read_structures.with_orientations(orientations).unwrap()
+    }
+}
+
+impl FromStr for InstrumentWorkflow {
+    type Err = ReadStructureError;
+
+    /// Resolves an [`InstrumentWorkflow`] by name, ignoring case and treating `_` the same as
+    /// `-`.
+    ///
+    /// # Errors
+    ///
+    /// - If no instrument workflow has the given name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase().replace('_', "-");
+        InstrumentWorkflow::iter()
+            .find(|w| w.name() == name)
+            .ok_or_else(|| ReadStructureError::InstrumentWorkflowUnknown(s.to_owned()))
+    }
+}
+
+/// Returns the index of the i5 index read: the read with a segment labelled `i5`, if any,
+/// otherwise the second read with a sample barcode segment, if any, as reads are given in
+/// sequencing order (R1, I1, I2, R2).
+pub fn i5_read(read_structures: &MultiReadStructure) -> Option<usize> {
+    read_structures
+        .iter()
+        .position(|rs| rs.segment_by_label("i5").is_some())
+        .or_else(|| read_structures.reads_with_type(SegmentType::SampleBarcode).get(1).copied())
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use strum::IntoEnumIterator;
+
+    use crate::multi_read_structure::MultiReadStructure;
+    use crate::orientation::{i5_read, reverse_complement, InstrumentWorkflow, Orientation};
+
+    #[test]
+    fn test_reverse_complement() {
+        assert_eq!(reverse_complement(b"AACGTN"), b"NACGTT");
+        assert_eq!(reverse_complement(b"acgG"), b"Ccgt");
+        assert!(reverse_complement(b"").is_empty());
+    }
+
+    #[test]
+    fn test_instrument_workflow_from_str() {
+        for workflow in InstrumentWorkflow::iter() {
+            assert_eq!(InstrumentWorkflow::from_str(workflow.name()).unwrap(), workflow);
+        }
+        assert_eq!(
+            InstrumentWorkflow::from_str("NovaSeq_X").unwrap(),
+            InstrumentWorkflow::NovaSeqX
+        );
+        assert!(InstrumentWorkflow::from_str("novaseq").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mrs = MultiReadStructure::from_str("+T 8B 8B +T").unwrap();
+        assert_eq!(i5_read(&mrs), Some(2));
+        let applied = InstrumentWorkflow::NextSeq2000.apply(&mrs);
+        assert_eq!(
+            applied.orientations(),
+            [
+                Orientation::Forward,
+                Orientation::Forward,
+                Orientation::ReverseComplement,
+                Orientation::Forward
+            ]
+        );
+        assert_eq!(applied.to_string(), mrs.to_string());
+        assert_eq!(InstrumentWorkflow::MiSeq.apply(&applied), mrs);
+
+        let single_index = MultiReadStructure::from_str("+T 8B +T").unwrap();
+        assert_eq!(i5_read(&single_index), None);
+        assert_eq!(InstrumentWorkflow::NovaSeqX.apply(&single_index), single_index);
+
+        // A labelled i5 segment takes precedence over the position of the read
+        let labelled = MultiReadStructure::from_str("+T 8B{i5} 8B{i7} +T").unwrap();
+        assert_eq!(i5_read(&labelled), Some(1));
+    }
+}
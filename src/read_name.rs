@@ -0,0 +1,260 @@
+//! Read Names
+//!
+//! Type [`ReadNameFormat`] describes how extracted segments (e.g. UMIs) are appended to read
+//! names, as done by bcl-convert (`@NAME:ACGTACGT` or `@NAME:UMI1+UMI2`) and umi_tools
+//! (`@NAME_CELL_UMI`), so that they survive tools that only preserve read names.  Each kind of
+//! segment appended is its own field, separated from the name (and other fields) by the field
+//! delimiter; multiple segments of the same kind are joined by a per-kind separator.
+//!
+//! Only the read identifier (the name up to the first whitespace) is rewritten; any comment is
+//! preserved, along with the whitespace separating it from the identifier.
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::{read_name::ReadNameFormat, ReadStructure, SegmentType};
+//!
+//! let rs = ReadStructure::from_str("4M2S4M+T").unwrap();
+//! let extraction = rs.extract(b"ACGTNNTTGGAAAAAA").unwrap();
+//! let format = ReadNameFormat::default();
+//! let name = format.format("@q1 1:N:0:ACGT", &extraction).unwrap();
+//! assert_eq!(name, "@q1:ACGT+TTGG 1:N:0:ACGT");
+//!
+//! let parsed = format.parse(&name).unwrap();
+//! assert_eq!(parsed.name, "@q1");
+//! assert_eq!(parsed.fields, vec![(SegmentType::MolecularBarcode, vec!["ACGT", "TTGG"])]);
+//! ```
+
+use crate::extraction::Extraction;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// Describes how extracted segments are appended to read names.  See
+/// [the module level documentation](self) for more.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadNameFormat {
+    /// The delimiter between the read name and each appended field.
+    field_delimiter: char,
+    /// The kinds of segments appended, in order, each with the separator used to join multiple
+    /// segments of that kind.
+    fields: Vec<(SegmentType, String)>,
+}
+
+/// A read name parsed by [`ReadNameFormat::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedReadName<'a> {
+    /// The read identifier with the appended fields removed.
+    pub name: &'a str,
+    /// The comment following the read identifier, if any.
+    pub comment: Option<&'a str>,
+    /// The segments recovered for each appended field, in the order given by the format.
+    pub fields: Vec<(SegmentType, Vec<&'a str>)>,
+}
+
+impl ReadNameFormat {
+    /// Builds a new [`ReadNameFormat`] with the given field delimiter that appends no fields.
+    pub fn new(field_delimiter: char) -> Self {
+        ReadNameFormat { field_delimiter, fields: Vec::new() }
+    }
+
+    /// Returns the format used by bcl-convert: UMIs appended after a `:`, with multiple UMIs
+    /// joined by `+`.
+    pub fn illumina() -> Self {
+        Self::new(':').with_field(SegmentType::MolecularBarcode, "+")
+    }
+
+    /// Returns the format used by umi_tools: the cell barcode and UMI appended, each after a
+    /// `_`, with multiple segments of the same kind concatenated.
+    pub fn umi_tools() -> Self {
+        Self::new('_')
+            .with_field(SegmentType::CellularBarcode, "")
+            .with_field(SegmentType::MolecularBarcode, "")
+    }
+
+    /// Adds a field for segments of the given kind, with multiple segments joined by
+    /// `separator`.
+    #[must_use]
+    pub fn with_field(mut self, kind: SegmentType, separator: &str) -> Self {
+        self.fields.push((kind, separator.to_owned()));
+        self
+    }
+
+    /// Returns the delimiter between the read name and each appended field.
+    pub fn field_delimiter(&self) -> char {
+        self.field_delimiter
+    }
+
+    /// Returns the kinds of segments appended, each with its separator.
+    pub fn fields(&self) -> &[(SegmentType, String)] {
+        &self.fields
+    }
+
+    /// Returns the read name with the extracted segments appended to the read identifier.  The
+    /// bases of each segment are written in the orientation of their read (see
+    /// [`crate::extraction::ExtractedSegment::oriented_bases`]).
+    ///
+    /// # Errors
+    ///
+    /// - If the extraction has no segments of a kind the format appends.
+    pub fn format(
+        &self,
+        name: &str,
+        extraction: &Extraction,
+    ) -> Result<String, ReadStructureError> {
+        let (identifier, comment) = split_comment(name);
+        let mut formatted = identifier.to_owned();
+        for (kind, separator) in &self.fields {
+            formatted.push(self.field_delimiter);
+            let mut segments = extraction.segments_by_type(*kind).peekable();
+            if segments.peek().is_none() {
+                return Err(ReadStructureError::ExtractionMissingSegment(
+                    kind.name().replace('_', " "),
+                ));
+            }
+            for (i, segment) in segments.enumerate() {
+                if i > 0 {
+                    formatted.push_str(separator);
+                }
+                formatted.push_str(&String::from_utf8_lossy(&segment.oriented_bases()));
+            }
+        }
+        if let Some(comment) = comment {
+            formatted.push_str(comment);
+        }
+        Ok(formatted)
+    }
+
+    /// Recovers the read identifier and appended segments from a read name written with
+    /// [`ReadNameFormat::format`].
+    ///
+    /// # Errors
+    ///
+    /// - If the read identifier has fewer fields than the format appends.
+    pub fn parse<'a>(&self, name: &'a str) -> Result<ParsedReadName<'a>, ReadStructureError> {
+        let (identifier, comment) = split_comment(name);
+        // Drop the whitespace separating the comment from the identifier
+        let comment = comment.map(|c| &c[c.chars().next().map_or(0, char::len_utf8)..]);
+        let mut parts = identifier.rsplitn(self.fields.len() + 1, self.field_delimiter);
+        let mut values: Vec<&str> = parts.by_ref().take(self.fields.len()).collect();
+        let name = match parts.next() {
+            Some(name) if values.len() == self.fields.len() => name,
+            _ => return Err(ReadStructureError::ReadNameMissingFields(identifier.to_owned())),
+        };
+        values.reverse();
+        let fields = self
+            .fields
+            .iter()
+            .zip(values)
+            .map(|((kind, separator), value)| {
+                let segments = if value.is_empty() {
+                    vec![]
+                } else if separator.is_empty() {
+                    vec![value]
+                } else {
+                    value.split(separator.as_str()).collect()
+                };
+                (*kind, segments)
+            })
+            .collect();
+        Ok(ParsedReadName { name, comment, fields })
+    }
+}
+
+impl Default for ReadNameFormat {
+    /// Returns the bcl-convert format.  See [`ReadNameFormat::illumina`].
+    fn default() -> Self {
+        Self::illumina()
+    }
+}
+
+/// Splits a read name at the first whitespace into the identifier and the comment, with the
+/// comment starting with the whitespace that separates it.
+fn split_comment(name: &str) -> (&str, Option<&str>) {
+    match name.find(char::is_whitespace) {
+        Some(start) => (&name[..start], Some(&name[start..])),
+        None => (name, None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::read_name::ReadNameFormat;
+    use crate::read_structure::ReadStructure;
+    use crate::segment_type::SegmentType;
+
+    #[test]
+    fn test_format_illumina() {
+        let rs = ReadStructure::from_str("8M+T").unwrap();
+        let extraction = rs.extract(b"ACGTACGTTTTT").unwrap();
+        let format = ReadNameFormat::illumina();
+        assert_eq!(format.format("@NAME", &extraction).unwrap(), "@NAME:ACGTACGT");
+        let name = format.format("NAME\tcomment", &extraction).unwrap();
+        assert_eq!(name, "NAME:ACGTACGT\tcomment");
+        assert_eq!(format.parse(&name).unwrap().comment, Some("comment"));
+
+        // Sequenced as AACCGGTTTT, stored reverse complemented
+        let rs = ReadStructure::from_str("4M+T").unwrap();
+        let extraction = rs.extract_reverse_strand(b"AAAACCGGTT").unwrap();
+        assert_eq!(format.format("NAME", &extraction).unwrap(), "NAME:AACC");
+    }
+
+    #[test]
+    fn test_format_umi_tools() {
+        let rs = ReadStructure::from_str("4C2M2C2M+T").unwrap();
+        let extraction = rs.extract(b"AAAACCGGTTNNNN").unwrap();
+        let format = ReadNameFormat::umi_tools();
+        let name = format.format("read1", &extraction).unwrap();
+        assert_eq!(name, "read1_AAAAGG_CCTT");
+        let parsed = format.parse(&name).unwrap();
+        assert_eq!(parsed.name, "read1");
+        assert_eq!(
+            parsed.fields,
+            vec![
+                (SegmentType::CellularBarcode, vec!["AAAAGG"]),
+                (SegmentType::MolecularBarcode, vec!["CCTT"])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_round_trip() {
+        let format = ReadNameFormat::new(':')
+            .with_field(SegmentType::SampleBarcode, "-")
+            .with_field(SegmentType::MolecularBarcode, "+");
+        let rs = ReadStructure::from_str("3B3M3B3M").unwrap();
+        let extraction = rs.extract(b"AAACCCGGGTTT").unwrap();
+        let name = format.format("M00:1:FC:1:1:10:20 1:N:0", &extraction).unwrap();
+        assert_eq!(name, "M00:1:FC:1:1:10:20:AAA-GGG:CCC+TTT 1:N:0");
+        let parsed = format.parse(&name).unwrap();
+        assert_eq!(parsed.name, "M00:1:FC:1:1:10:20");
+        assert_eq!(parsed.comment, Some("1:N:0"));
+        assert_eq!(
+            parsed.fields,
+            vec![
+                (SegmentType::SampleBarcode, vec!["AAA", "GGG"]),
+                (SegmentType::MolecularBarcode, vec!["CCC", "TTT"])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_missing_segments() {
+        let rs = ReadStructure::from_str("+T").unwrap();
+        let extraction = rs.extract(b"ACGT").unwrap();
+        let format = ReadNameFormat::default();
+        let err = format.format("q1", &extraction).unwrap_err();
+        assert_eq!(err.to_string(), "Extraction is missing segment: molecular barcode");
+        let parsed = format.parse("q1:").unwrap();
+        assert_eq!(parsed.fields, vec![(SegmentType::MolecularBarcode, vec![])]);
+    }
+
+    #[test]
+    fn test_parse_missing_fields() {
+        let format = ReadNameFormat::default();
+        assert!(format.parse("q1").is_err());
+        assert!(ReadNameFormat::umi_tools().parse("q1_ACGT").is_err());
+    }
+}
@@ -5,7 +5,7 @@
 //! length must be `Some(usize)`, or an indefinite length (can be any length, 1 or more)
 //! in which case length must be `None`.
 
-use std::{convert::TryFrom, io::Read};
+use std::{convert::TryFrom, io::Read, ops::Range};
 
 use crate::{segment_type::SegmentType, ReadStructure, ReadStructureError};
 
@@ -21,7 +21,6 @@ pub const ANY_LENGTH_STR: &str = "+";
 /// The read segment describing a given kind ([`SegmentType`]), optional length, and offset of the
 /// bases within a [`crate::read_structure::ReadStructure`].
 #[derive(Debug, Copy, Clone, PartialEq)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReadSegment {
     /// The offset in the read if the segment belongs to a read structure
     pub(crate) offset: usize,
@@ -32,6 +31,13 @@ pub struct ReadSegment {
 }
 
 impl ReadSegment {
+    /// Builds a new [`ReadSegment`] of the given kind, with a fixed length if `length` is
+    /// `Some`, otherwise an indefinite length.  The offset is zero until the segment is added to
+    /// a [`crate::read_structure::ReadStructure`].
+    pub fn new(length: Option<usize>, kind: SegmentType) -> Self {
+        ReadSegment { offset: 0, length, kind }
+    }
+
     /// Extract the bases corresponding to this [`ReadSegment`] from a slice.
     ///
     /// # Errors
@@ -68,11 +74,27 @@ impl ReadSegment {
         self.length
     }
 
+    /// Returns the zero-based offset of the read segment within its read structure.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
     /// Returns true if the read segment has a length defined (i.e. not `None`)
     pub fn has_length(&self) -> bool {
         self.length.is_some()
     }
 
+    /// Returns the positions of the segment in a read stored as its reverse complement (e.g. a
+    /// record mapped to the reverse strand), where the segment is counted from the end.
+    ///
+    /// # Errors
+    ///
+    /// - If the segment does not fall wholely within the read.
+    pub(crate) fn reverse_range<T>(&self, bases: &[T]) -> Result<Range<usize>, ReadStructureError> {
+        let end = self.calculate_end(bases)?;
+        Ok(bases.len() - end..bases.len() - self.offset)
+    }
+
     /// Returns the end position for the segment for the given read.
     ///
     /// # Errors
@@ -96,7 +118,7 @@ impl ReadSegment {
     /// Clone the read segment but with an updated end. If the new end is before
     /// the current offset, the read segment will have no length defined.
     /// Otherwise, the new length will be reduced based on the offset (`end - offset`).
-    fn clone_with_new_end(&self, end: usize) -> Self {
+    pub(crate) fn clone_with_new_end(&self, end: usize) -> Self {
         let option_new_length = if self.offset >= end { None } else { Some(end - self.offset) };
         if option_new_length == self.length {
             *self
@@ -115,11 +137,13 @@ impl std::str::FromStr for ReadSegment {
     ///
     /// # Errors
     ///
-    /// Returns `Err` if the string was too short, if the length could not be parsed, or if
-    /// the segment type could not be recognized.
+    /// Returns `Err` if the string was too short, if the length could not be parsed, if
+    /// the segment type could not be recognized, or if the segment has a label.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let rs = ReadStructure::from_str(s)?;
-        if rs.number_of_segments() == 1 {
+        if rs.label(0).is_some() {
+            Err(ReadStructureError::ReadSegmentLabelled(s.to_owned()))
+        } else if rs.number_of_segments() == 1 {
             // Unwrap is safe since we checked the length
             Ok(rs.first().copied().unwrap())
         } else {
@@ -167,6 +191,14 @@ mod test {
         seg_no_length.length().unwrap();
     }
 
+    #[test]
+    fn test_read_segment_new() {
+        let segment = ReadSegment::new(Some(8), SegmentType::SampleBarcode);
+        assert_eq!(segment, ReadSegment::from_str("8B").unwrap());
+        assert_eq!(segment.offset(), 0);
+        assert_eq!(ReadSegment::new(None, SegmentType::Template).to_string(), "+T");
+    }
+
     #[test]
     fn test_read_segment_to_string() {
         for tpe in SegmentType::iter() {
@@ -216,5 +248,10 @@ mod test {
             ReadSegment::from_str("10S").unwrap(),
             ReadSegment { offset: 0, length: Some(10), kind: SegmentType::Skip }
         );
+        let err = ReadSegment::from_str("8B{i7}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ReadSegment str contained a label, which only read structures hold: 8B{i7}"
+        );
     }
 }
@@ -4,7 +4,34 @@
 //! contains one or more read segments. A read segment describes a contiguous
 //! stretch of bases of the same type (e.g. template bases) of some length and
 //! some offset from the start of the read.
+//!
+//! Read structures are usually parsed from strings, but may also be built with a
+//! [`ReadStructureBuilder`]:
+//!
+//! ```rust
+//! use read_structure::ReadStructure;
+//!
+//! let rs = ReadStructure::builder().molecular_barcode(6).skip(2).rest_template().build().unwrap();
+//! assert_eq!(rs.to_string(), "6M2S+T");
+//! assert_eq!(rs[2].offset(), 8);
+//! ```
+//!
+//! Segments may be given labels, written in braces after the segment (e.g. `8B{i7}8B{i5}`), so
+//! that they can be found by name rather than by position:
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::ReadStructure;
+//!
+//! let rs = ReadStructure::from_str("8B{i7}8B{i5}+T").unwrap();
+//! assert_eq!(rs.segment_by_label("i5").map(|(i, s)| (i, s.offset())), Some((1, 8)));
+//! assert_eq!(rs.to_string(), "8B{i7}8B{i5}+T");
+//! ```
 
+use crate::compatibility::CompatibilityReport;
+use crate::cycle_map::CycleMap;
+use crate::extraction::{ExtractedSegment, Extraction};
+use crate::orientation::Orientation;
 use crate::read_segment;
 use crate::read_segment::ReadSegment;
 use crate::read_segment::ANY_LENGTH_BYTE;
@@ -12,18 +39,20 @@ use crate::segment_type::SegmentType;
 use crate::ErrorMessageParts;
 use crate::ReadStructureError;
 use std::convert::TryFrom;
-use std::ops::Index;
+use std::ops::{Bound, Index, RangeBounds};
 use std::string;
 use std::string::ToString;
+use std::sync::Arc;
 
 /// The read structure composed of one or more [`ReadSegment`]s.
 #[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReadStructure {
     /// The elements that make up the [`ReadStructure`].
     elements: Vec<ReadSegment>,
     /// The combined length of fixed length segments.
     length_of_fixed_segments: usize,
+    /// The label of each element, if any.
+    labels: Vec<Option<Arc<str>>>,
 }
 
 impl ReadStructure {
@@ -75,7 +104,48 @@ impl ReadStructure {
             segment.offset = off;
             off += segment.length.unwrap_or(0);
         }
-        Ok(ReadStructure { elements: segments, length_of_fixed_segments })
+        let labels = vec![None; segments.len()];
+        Ok(ReadStructure { elements: segments, length_of_fixed_segments, labels })
+    }
+
+    /// Builds a new [`ReadStructure`] from [`ReadSegment`]s paired with their labels.
+    fn new_labelled(
+        segments: Vec<(ReadSegment, Option<Arc<str>>)>,
+    ) -> Result<Self, ReadStructureError> {
+        let (segments, labels): (Vec<_>, Vec<_>) = segments.into_iter().unzip();
+        ReadStructure::new(segments)?.with_labels(labels)
+    }
+
+    /// Returns the read structure with the given labels, one per segment.
+    ///
+    /// # Errors
+    ///
+    /// - If any label is invalid.
+    /// - If two segments have the same label.
+    pub(crate) fn with_labels(
+        mut self,
+        labels: Vec<Option<Arc<str>>>,
+    ) -> Result<Self, ReadStructureError> {
+        for (i, label) in labels.iter().enumerate() {
+            if let Some(label) = label {
+                check_label(label)?;
+                if labels[..i].iter().flatten().any(|l| l == label) {
+                    return Err(ReadStructureError::ReadStructureDuplicateLabel(label.to_string()));
+                }
+            }
+        }
+        self.labels = labels;
+        Ok(self)
+    }
+
+    /// Returns the [`ReadSegment`]s paired with their labels.
+    fn labelled(&self) -> impl DoubleEndedIterator<Item = (ReadSegment, Option<Arc<str>>)> + '_ {
+        self.elements.iter().copied().zip(self.labels.iter().cloned())
+    }
+
+    /// Returns a new [`ReadStructureBuilder`] with no segments.
+    pub fn builder() -> ReadStructureBuilder {
+        ReadStructureBuilder::default()
     }
 
     /// Returns `true` if the [`ReadStructure`] has a fixed (i.e. non-variable) length,
@@ -93,6 +163,11 @@ impl ReadStructure {
         }
     }
 
+    /// Returns the combined length of the fixed length segments in this read structure.
+    pub fn length_of_fixed_segments(&self) -> usize {
+        self.length_of_fixed_segments
+    }
+
     /// Returns the number of segments in this read structure.
     pub fn number_of_segments(&self) -> usize {
         self.elements.len()
@@ -138,6 +213,46 @@ impl ReadStructure {
         self.segments_by_type(SegmentType::CellularBarcode)
     }
 
+    /// Returns the label of the segment at the given index, if it has one.
+    pub fn label(&self, index: usize) -> Option<&str> {
+        self.labels.get(index).and_then(|l| l.as_deref())
+    }
+
+    /// Returns the label of each segment, if it has one.
+    pub fn labels(&self) -> impl Iterator<Item = Option<&str>> {
+        self.labels.iter().map(|l| l.as_deref())
+    }
+
+    /// Returns an iterator over the read segments, each paired with its label, if any.
+    pub fn labelled_segments(&self) -> impl Iterator<Item = (&ReadSegment, Option<&str>)> {
+        self.elements.iter().zip(self.labels())
+    }
+
+    /// Returns the index of the segment with the given label, and the segment.
+    pub fn segment_by_label(&self, label: &str) -> Option<(usize, &ReadSegment)> {
+        self.labels
+            .iter()
+            .position(|l| l.as_deref() == Some(label))
+            .map(|index| (index, &self.elements[index]))
+    }
+
+    /// Returns the read structure with the segment at the given index labelled.
+    ///
+    /// # Errors
+    ///
+    /// - If the label is empty, or contains characters other than ASCII letters, digits, `_`,
+    ///   `.` and `-`.
+    /// - If another segment has the same label.
+    ///
+    /// # Panics
+    ///
+    /// - If the index is out of bounds.
+    pub fn with_label(&self, index: usize, label: &str) -> Result<Self, ReadStructureError> {
+        let mut labels = self.labels.clone();
+        labels[index] = Some(Arc::from(label));
+        self.clone().with_labels(labels)
+    }
+
     /// Returns the first [`ReadSegment`] in this read structure
     pub fn first(&self) -> Option<&ReadSegment> {
         self.elements.first()
@@ -147,6 +262,448 @@ impl ReadStructure {
     pub fn last(&self) -> Option<&ReadSegment> {
         self.elements.last()
     }
+
+    /// Returns the read structure covering the given range of cycles (zero-based, end
+    /// exclusive), with offsets recomputed.  Segments that span either end of the range are
+    /// truncated to it, and an indefinite length segment remains indefinite only if the range is
+    /// unbounded at the end.
+    ///
+    /// # Errors
+    ///
+    /// - If the range is empty.
+    /// - If the range ends after the end of a fixed length read structure.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Result<Self, ReadStructureError> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => Some(end + 1),
+            Bound::Excluded(&end) => Some(end),
+            Bound::Unbounded => None,
+        };
+        let read_end = self.fixed_length();
+        let out_of_bounds = |e: usize| read_end.map_or(false, |r| e > r);
+        if end.map_or(false, |e| e <= start || out_of_bounds(e))
+            || (end.is_none() && read_end.map_or(false, |r| start >= r))
+        {
+            return Err(ReadStructureError::ReadStructureRangeInvalid {
+                read_structure: self.to_string(),
+                range: format!("{}..{}", start, end.map(|e| e.to_string()).unwrap_or_default()),
+            });
+        }
+
+        let segments = self
+            .labelled()
+            .filter_map(|(segment, label)| {
+                let seg_start = segment.offset.max(start);
+                let seg_end = match (segment.length.map(|l| segment.offset + l), end) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+                match seg_end {
+                    Some(seg_end) if seg_end <= seg_start => None,
+                    length => {
+                        Some((ReadSegment::new(length.map(|e| e - seg_start), segment.kind), label))
+                    }
+                }
+            })
+            .collect();
+        ReadStructure::new_labelled(segments)
+    }
+
+    /// Splits the read structure at the given zero-based cycle, returning the read structures
+    /// before and from the cycle.  A segment spanning the cycle is split in two.
+    ///
+    /// # Errors
+    ///
+    /// - If either read structure would be empty.
+    pub fn split_at(&self, cycle: usize) -> Result<(Self, Self), ReadStructureError> {
+        Ok((self.slice(..cycle)?, self.slice(cycle..)?))
+    }
+
+    /// Returns the read structure for reads of exactly `length` bases, as when a run was
+    /// truncated or reads were trimmed.  Segments after the end of the read are removed, a
+    /// segment spanning the end is truncated, and an indefinite length segment is given the
+    /// remaining bases.
+    ///
+    /// # Errors
+    ///
+    /// - If `length` is zero.
+    /// - If `length` is longer than a fixed length read structure.
+    pub fn with_length(&self, length: usize) -> Result<Self, ReadStructureError> {
+        if length == 0 || self.fixed_length().map_or(false, |l| length > l) {
+            return Err(ReadStructureError::ReadLengthMismatch {
+                read_structure: self.to_string(),
+                length,
+            });
+        }
+        let segments = self
+            .labelled()
+            .filter(|(s, _)| s.offset < length)
+            .map(|(s, label)| match s.length {
+                Some(l) if s.offset + l <= length => (s, label),
+                _ => (s.clone_with_new_end(length), label),
+            })
+            .collect();
+        ReadStructure::new_labelled(segments)
+    }
+
+    /// Returns the fixed length read structure for reads of `read_length` bases: the indefinite
+    /// length segment, if any, is given the bases remaining after the fixed length segments
+    /// (e.g. `8B+T` resolves to `8B142T` for 150 base reads).  Fixed length read structures are
+    /// returned as they are, since any bases after their end are ignored.
+    ///
+    /// # Errors
+    ///
+    /// - If the read is too short for every segment to have at least one base.
+    pub fn resolve(&self, read_length: usize) -> Result<Self, ReadStructureError> {
+        if self.has_fixed_length() && read_length >= self.length_of_fixed_segments {
+            Ok(self.clone())
+        } else if !self.has_fixed_length() && read_length > self.length_of_fixed_segments {
+            self.with_length(read_length)
+        } else {
+            Err(ReadStructureError::ReadLengthMismatch {
+                read_structure: self.to_string(),
+                length: read_length,
+            })
+        }
+    }
+
+    /// Returns the read structure with the last segment given an indefinite length.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn with_variable_last_segment(&self) -> Self {
+        let mut segments = self.elements.clone();
+        // Unwrap is safe since read structures have at least one segment
+        segments.last_mut().unwrap().length = None;
+        // Unwraps are safe since only the last segment has an indefinite length, and the labels
+        // are unchanged
+        ReadStructure::new(segments).unwrap().with_labels(self.labels.clone()).unwrap()
+    }
+
+    /// Checks how this read structure fits reads of the given lengths.  See
+    /// [`crate::compatibility`] for more.
+    pub fn check_compatibility<I: IntoIterator<Item = usize>>(
+        &self,
+        read_lengths: I,
+    ) -> CompatibilityReport {
+        CompatibilityReport::new(self, read_lengths.into_iter().map(|length| (length, 1)))
+    }
+
+    /// Checks how this read structure fits reads with the given histogram of lengths, as read
+    /// lengths paired with the number of reads of each length.  See [`crate::compatibility`]
+    /// for more.
+    pub fn check_compatibility_histogram<I: IntoIterator<Item = (usize, u64)>>(
+        &self,
+        histogram: I,
+    ) -> CompatibilityReport {
+        CompatibilityReport::new(self, histogram)
+    }
+
+    /// Returns the read structure with its segments in reverse order, describing the read from
+    /// its 3' end (e.g. `4M8B10T` reversed is `10T8B4M`).  Read structures with an indefinite
+    /// length segment must first be given a read length with [`ReadStructure::resolve`].
+    ///
+    /// # Errors
+    ///
+    /// - If the read structure has an indefinite length segment, unless it is the only segment.
+    pub fn reversed(&self) -> Result<Self, ReadStructureError> {
+        ReadStructure::new_labelled(self.labelled().rev().collect())
+    }
+
+    /// Returns the read structure with the segments of another read structure appended, as when
+    /// reads were merged upstream.
+    ///
+    /// # Errors
+    ///
+    /// - If this read structure ends with an indefinite length segment.
+    /// - If a segment of each read structure has the same label.
+    pub fn concat(&self, other: &ReadStructure) -> Result<Self, ReadStructureError> {
+        ReadStructure::new_labelled(self.labelled().chain(other.labelled()).collect())
+    }
+
+    /// Returns the read structure without the segments of the given kind, as when those bases
+    /// have been trimmed from the reads.
+    ///
+    /// # Errors
+    ///
+    /// - If every segment has the given kind.
+    pub fn without_kind(&self, kind: SegmentType) -> Result<Self, ReadStructureError> {
+        ReadStructure::new_labelled(self.labelled().filter(|(s, _)| s.kind != kind).collect())
+    }
+
+    /// Returns the index of the segment containing the given zero-based cycle, and the segment,
+    /// or `None` if the cycle is after the end of a fixed length read structure.  Every cycle
+    /// from the start of an indefinite length segment belongs to it.
+    pub fn segment_at(&self, cycle: usize) -> Option<(usize, &ReadSegment)> {
+        self.elements
+            .iter()
+            .enumerate()
+            .find(|(_, s)| cycle >= s.offset && s.length.map_or(true, |l| cycle < s.offset + l))
+    }
+
+    /// Returns the segment index of each cycle, or `None` if the read structure has an
+    /// indefinite length segment.  See [`crate::cycle_map`] for more.
+    pub fn cycle_map(&self) -> Option<CycleMap> {
+        self.fixed_length().map(|length| CycleMap::new(self, length))
+    }
+
+    /// Returns the segment index of each cycle of reads of `read_length` cycles.  See
+    /// [`crate::cycle_map`] for more.
+    pub fn cycle_map_for_length(&self, read_length: usize) -> CycleMap {
+        CycleMap::new(self, read_length)
+    }
+
+    /// Extracts the bases for every [`ReadSegment`] in this read structure from a read.
+    ///
+    /// # Errors
+    ///
+    /// - If any segment does not fall wholely within the read.
+    pub fn extract<'a>(&self, bases: &'a [u8]) -> Result<Extraction<'a>, ReadStructureError> {
+        let segments = self
+            .elements
+            .iter()
+            .zip(&self.labels)
+            .map(|(segment, label)| {
+                let bases = segment.extract_bases(bases)?;
+                Ok(ExtractedSegment {
+                    read_index: 0,
+                    segment: *segment,
+                    bases,
+                    quals: None,
+                    orientation: Orientation::Forward,
+                    label: label.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, ReadStructureError>>()?;
+        Ok(Extraction::new(segments))
+    }
+
+    /// Extracts the bases and qualities for every [`ReadSegment`] in this read structure from
+    /// a read.
+    ///
+    /// # Errors
+    ///
+    /// - If any segment does not fall wholely within the read.
+    /// - If the bases and quals lengths are not equal.
+    pub fn extract_with_quals<'a>(
+        &self,
+        bases: &'a [u8],
+        quals: &'a [u8],
+    ) -> Result<Extraction<'a>, ReadStructureError> {
+        let segments = self
+            .elements
+            .iter()
+            .zip(&self.labels)
+            .map(|(segment, label)| {
+                let (bases, quals) = segment.extract_bases_and_quals(bases, quals)?;
+                Ok(ExtractedSegment {
+                    read_index: 0,
+                    segment: *segment,
+                    bases,
+                    quals: Some(quals),
+                    orientation: Orientation::Forward,
+                    label: label.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, ReadStructureError>>()?;
+        Ok(Extraction::new(segments))
+    }
+
+    /// Extracts the bases for every [`ReadSegment`] in this read structure from a read stored
+    /// as the reverse complement of the sequenced read (e.g. a record mapped to the reverse
+    /// strand), interpreting the read structure from the 3' end of the stored bases.  Each
+    /// extracted segment holds the stored bases, and has [`Orientation::ReverseComplement`] so
+    /// that [`ExtractedSegment::oriented_bases`] returns the bases as sequenced.
+    ///
+    /// # Errors
+    ///
+    /// - If any segment does not fall wholely within the read.
+    pub fn extract_reverse_strand<'a>(
+        &self,
+        bases: &'a [u8],
+    ) -> Result<Extraction<'a>, ReadStructureError> {
+        let segments = self
+            .elements
+            .iter()
+            .zip(&self.labels)
+            .map(|(segment, label)| {
+                let range = segment.reverse_range(bases)?;
+                Ok(ExtractedSegment {
+                    read_index: 0,
+                    segment: *segment,
+                    bases: &bases[range],
+                    quals: None,
+                    orientation: Orientation::ReverseComplement,
+                    label: label.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, ReadStructureError>>()?;
+        Ok(Extraction::new(segments))
+    }
+
+    /// Extracts the bases and qualities for every [`ReadSegment`] in this read structure from a
+    /// read stored as the reverse complement of the sequenced read.  See
+    /// [`ReadStructure::extract_reverse_strand`] for more; [`ExtractedSegment::oriented_quals`]
+    /// returns the qualities as sequenced.
+    ///
+    /// # Errors
+    ///
+    /// - If any segment does not fall wholely within the read.
+    /// - If the bases and quals lengths are not equal.
+    pub fn extract_reverse_strand_with_quals<'a>(
+        &self,
+        bases: &'a [u8],
+        quals: &'a [u8],
+    ) -> Result<Extraction<'a>, ReadStructureError> {
+        if bases.len() != quals.len() {
+            return Err(ReadStructureError::MismatchingBasesAndQualsLen {
+                bases_len: bases.len(),
+                quals_len: quals.len(),
+            });
+        }
+        let segments = self
+            .elements
+            .iter()
+            .zip(&self.labels)
+            .map(|(segment, label)| {
+                let range = segment.reverse_range(bases)?;
+                Ok(ExtractedSegment {
+                    read_index: 0,
+                    segment: *segment,
+                    bases: &bases[range.clone()],
+                    quals: Some(&quals[range]),
+                    orientation: Orientation::ReverseComplement,
+                    label: label.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>, ReadStructureError>>()?;
+        Ok(Extraction::new(segments))
+    }
+}
+
+/// Builds a [`ReadStructure`] one segment at a time, in read order.  Each segment is validated
+/// as it is added, and the first error is returned by [`ReadStructureBuilder::build`].
+#[derive(Debug, Default)]
+pub struct ReadStructureBuilder {
+    /// The segments added so far, with offsets assigned.
+    segments: Vec<ReadSegment>,
+    /// The label of each segment added so far, if any.
+    labels: Vec<Option<Arc<str>>>,
+    /// The first error encountered, if any.
+    error: Option<ReadStructureError>,
+}
+
+impl ReadStructureBuilder {
+    /// Adds a segment of the given kind, with a fixed length if `length` is `Some`, otherwise an
+    /// indefinite length.  Errors are deferred to [`ReadStructureBuilder::build`].
+    #[must_use]
+    pub fn segment(mut self, length: Option<usize>, kind: SegmentType) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        let segment = ReadSegment::new(length, kind);
+        if let Some(indefinite) = self.segments.last().filter(|s| !s.has_length()) {
+            self.error =
+                Some(ReadStructureError::ReadStructureNonTerminalIndefiniteLengthReadSegment(
+                    *indefinite,
+                ));
+        } else if length == Some(0) {
+            let prefix: String = self.segments.iter().map(ToString::to_string).collect();
+            let chars: Vec<char> = format!("{}{}", prefix, segment).chars().collect();
+            let start = prefix.chars().count();
+            self.error = Some(ReadStructureError::ReadSegmentLengthZero(ErrorMessageParts::new(
+                &chars,
+                start,
+                chars.len() - 1,
+            )));
+        } else {
+            let offset = self.segments.last().map_or(0, |s| s.offset + s.length.unwrap_or(0));
+            self.segments.push(ReadSegment { offset, ..segment });
+            self.labels.push(None);
+        }
+        self
+    }
+
+    /// Labels the most recently added segment.  Errors are deferred to
+    /// [`ReadStructureBuilder::build`].
+    #[must_use]
+    pub fn label(mut self, label: &str) -> Self {
+        if self.error.is_some() {
+            return self;
+        }
+        match self.labels.last_mut() {
+            Some(last) => *last = Some(Arc::from(label)),
+            None => self.error = Some(ReadStructureError::ReadStructureContainsZeroElements),
+        }
+        self
+    }
+
+    /// Adds a segment of the given kind that consumes the rest of the read.
+    #[must_use]
+    pub fn rest(self, kind: SegmentType) -> Self {
+        self.segment(None, kind)
+    }
+
+    /// Adds a template segment of the given length.
+    #[must_use]
+    pub fn template(self, length: usize) -> Self {
+        self.segment(Some(length), SegmentType::Template)
+    }
+
+    /// Adds a sample barcode segment of the given length.
+    #[must_use]
+    pub fn sample_barcode(self, length: usize) -> Self {
+        self.segment(Some(length), SegmentType::SampleBarcode)
+    }
+
+    /// Adds a molecular barcode segment of the given length.
+    #[must_use]
+    pub fn molecular_barcode(self, length: usize) -> Self {
+        self.segment(Some(length), SegmentType::MolecularBarcode)
+    }
+
+    /// Adds a molecular barcode (UMI) segment of the given length.  The same as
+    /// [`ReadStructureBuilder::molecular_barcode`].
+    #[must_use]
+    pub fn umi(self, length: usize) -> Self {
+        self.molecular_barcode(length)
+    }
+
+    /// Adds a skip segment of the given length.
+    #[must_use]
+    pub fn skip(self, length: usize) -> Self {
+        self.segment(Some(length), SegmentType::Skip)
+    }
+
+    /// Adds a cellular barcode segment of the given length.
+    #[must_use]
+    pub fn cellular_barcode(self, length: usize) -> Self {
+        self.segment(Some(length), SegmentType::CellularBarcode)
+    }
+
+    /// Adds a template segment that consumes the rest of the read.
+    #[must_use]
+    pub fn rest_template(self) -> Self {
+        self.rest(SegmentType::Template)
+    }
+
+    /// Builds the [`ReadStructure`].
+    ///
+    /// # Errors
+    ///
+    /// - If any segment has length zero.
+    /// - If any segment follows a segment of indefinite length.
+    /// - If no segments were added, or a label was added before any segment.
+    /// - If any label is invalid, or two segments have the same label.
+    pub fn build(self) -> Result<ReadStructure, ReadStructureError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => ReadStructure::new(self.segments)?.with_labels(self.labels),
+        }
+    }
 }
 
 impl IntoIterator for ReadStructure {
@@ -154,6 +711,8 @@ impl IntoIterator for ReadStructure {
 
     type IntoIter = std::vec::IntoIter<Self::Item>;
 
+    /// Returns the read segments, without their labels.  Use
+    /// [`ReadStructure::labelled_segments`] to keep them.
     fn into_iter(self) -> Self::IntoIter {
         self.elements.into_iter()
     }
@@ -169,10 +728,13 @@ impl Index<usize> for ReadStructure {
 }
 
 impl std::fmt::Display for ReadStructure {
-    /// Formats this read structure as a string.
+    /// Formats this read structure as a string, with each label in braces after its segment.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for e in &self.elements {
+        for (e, label) in self.elements.iter().zip(&self.labels) {
             write!(f, "{}", e)?;
+            if let Some(label) = label {
+                write!(f, "{{{}}}", label)?;
+            }
         }
         Ok(())
     }
@@ -185,8 +747,22 @@ impl std::str::FromStr for ReadStructure {
     fn from_str(rs: &str) -> Result<Self, Self::Err> {
         let mut offset = 0;
         let mut i = 0;
-        let mut segs: Vec<ReadSegment> = Vec::new();
-        let chars: Vec<char> = rs.to_uppercase().chars().filter(|c| !c.is_whitespace()).collect();
+        let mut segs: Vec<(ReadSegment, Option<Arc<str>>)> = Vec::new();
+        // Remove whitespace and upper case everything but labels, which are kept as they are
+        let mut in_label = false;
+        let chars: Vec<char> = rs
+            .chars()
+            .flat_map(|c| {
+                in_label = (in_label || c == '{') && c != '}';
+                if in_label {
+                    vec![c]
+                } else if c.is_whitespace() {
+                    vec![]
+                } else {
+                    c.to_uppercase().collect()
+                }
+            })
+            .collect();
         while i < chars.len() {
             // Stash the beginning position of our parsing so we can highlight what we're having trouble with
             let parse_i = i;
@@ -222,7 +798,26 @@ impl std::str::FromStr for ReadStructure {
                     )));
                 }
                 i += 1;
-                segs.push(ReadSegment { offset, length, kind });
+                // Parse out the optional label, keeping its case
+                let label = if chars.get(i) == Some(&'{') {
+                    let start = i;
+                    let end = chars[i..].iter().position(|&c| c == '}').map(|p| i + p);
+                    let label: String = chars[i + 1..end.unwrap_or(chars.len())].iter().collect();
+                    if end.is_none() || check_label(&label).is_err() {
+                        return Err(ReadStructureError::ReadSegmentLabelInvalid(
+                            ErrorMessageParts::new(
+                                &chars,
+                                start,
+                                end.map_or(chars.len(), |e| e + 1),
+                            ),
+                        ));
+                    }
+                    i = end.unwrap() + 1;
+                    Some(Arc::from(label))
+                } else {
+                    None
+                };
+                segs.push((ReadSegment { offset, length, kind }, label));
                 offset += length.unwrap_or(0);
             } else {
                 return Err(ReadStructureError::ReadStructureHadUnknownType(
@@ -231,21 +826,50 @@ impl std::str::FromStr for ReadStructure {
             }
         }
 
-        ReadStructure::new(segs)
+        ReadStructure::new_labelled(segs)
+    }
+}
+
+/// Checks that a segment label is non-empty, and contains only ASCII letters, digits, `_`, `.`
+/// and `-`.
+fn check_label(label: &str) -> Result<(), ReadStructureError> {
+    if !label.is_empty()
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    {
+        Ok(())
+    } else {
+        let chars: Vec<char> = label.chars().collect();
+        Err(ReadStructureError::ReadSegmentLabelInvalid(ErrorMessageParts::new(
+            &chars,
+            0,
+            chars.len(),
+        )))
     }
 }
 
 impl TryFrom<&[ReadSegment]> for ReadStructure {
     type Error = ReadStructureError;
-    /// Builds a new read structure from a slice of elements.
+    /// Builds a new read structure from a slice of elements, with no labels.
     fn try_from(elements: &[ReadSegment]) -> Result<Self, Self::Error> {
         Self::new(elements.to_vec())
     }
 }
 
+impl TryFrom<&[(ReadSegment, Option<&str>)]> for ReadStructure {
+    type Error = ReadStructureError;
+    /// Builds a new read structure from a slice of elements paired with their labels, as
+    /// returned by [`ReadStructure::labelled_segments`].
+    fn try_from(elements: &[(ReadSegment, Option<&str>)]) -> Result<Self, Self::Error> {
+        Self::new_labelled(elements.iter().map(|(s, l)| (*s, l.map(Arc::from))).collect())
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use crate::read_segment::ReadSegment;
     use crate::read_structure::ReadStructure;
+    use crate::segment_type::SegmentType;
+    use std::convert::TryFrom;
     use std::str::FromStr;
 
     #[test]
@@ -382,6 +1006,264 @@ mod test {
         test_read_structure_index_32: ("10T10B10B10S10C10M", 4, "10C", 40),
     }
 
+    #[test]
+    fn test_read_structure_extract() {
+        let rs = ReadStructure::from_str("2M3B+T").unwrap();
+        let extraction = rs.extract(b"AACCCGGGG").unwrap();
+        let bases: Vec<&[u8]> = extraction.iter().map(|s| s.bases).collect();
+        assert_eq!(bases, vec![&b"AA"[..], b"CCC", b"GGGG"]);
+        assert_eq!(extraction.sample_barcodes().next().unwrap().bases, b"CCC");
+        assert!(extraction.iter().all(|s| s.quals.is_none()));
+        assert!(rs.extract(b"AACC").is_err());
+    }
+
+    #[test]
+    fn test_read_structure_extract_with_quals() {
+        let rs = ReadStructure::from_str("2M3B+T").unwrap();
+        let extraction = rs.extract_with_quals(b"AACCCGGGG", b"123456789").unwrap();
+        let quals: Vec<&[u8]> = extraction.iter().map(|s| s.quals.unwrap()).collect();
+        assert_eq!(quals, vec![&b"12"[..], b"345", b"6789"]);
+        assert!(rs.extract_with_quals(b"AACCCGGGG", b"12345678").is_err());
+    }
+
+    #[test]
+    fn test_read_structure_builder() {
+        let rs = ReadStructure::builder()
+            .template(76)
+            .sample_barcode(8)
+            .umi(6)
+            .skip(1)
+            .cellular_barcode(4)
+            .rest_template()
+            .build()
+            .unwrap();
+        assert_eq!(rs, ReadStructure::from_str("76T8B6M1S4C+T").unwrap());
+        let offsets: Vec<usize> = rs.iter().map(|s| s.offset()).collect();
+        assert_eq!(offsets, vec![0, 76, 84, 90, 91, 95]);
+
+        let err = ReadStructure::builder().template(4).rest_template().umi(6).build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Read structure contains a non-terminal segment that has an indefinite length: +T"
+        );
+        let err =
+            ReadStructure::builder().sample_barcode(8).skip(0).template(4).build().unwrap_err();
+        assert_eq!(err.to_string(), "ReadSegment must have length > 0 or `+`: 8B[0]S");
+        assert!(ReadStructure::builder().build().is_err());
+
+        let rs = ReadStructure::builder()
+            .sample_barcode(8)
+            .label("i7")
+            .sample_barcode(8)
+            .label("i5")
+            .rest_template()
+            .build()
+            .unwrap();
+        assert_eq!(rs, ReadStructure::from_str("8B{i7}8B{i5}+T").unwrap());
+        assert!(ReadStructure::builder().label("i7").template(8).build().is_err());
+        assert!(ReadStructure::builder().skip(2).label("a b").build().is_err());
+        let err = ReadStructure::builder().umi(2).label("a").umi(2).label("a").build().unwrap_err();
+        assert_eq!(err.to_string(), "Read structure has more than one segment labelled: a");
+    }
+
+    #[test]
+    fn test_read_structure_slice() {
+        let rs = ReadStructure::from_str("4M8B+T").unwrap();
+        assert_eq!(rs.slice(2..6).unwrap().to_string(), "2M2B");
+        assert_eq!(rs.slice(4..=11).unwrap().to_string(), "8B");
+        assert_eq!(rs.slice(10..20).unwrap().to_string(), "2B8T");
+        assert_eq!(rs.slice(12..).unwrap().to_string(), "+T");
+        assert_eq!(rs.slice(20..).unwrap().to_string(), "+T");
+        let sliced = rs.slice(3..).unwrap();
+        assert_eq!(sliced.to_string(), "1M8B+T");
+        assert_eq!(sliced[1].offset(), 1);
+        assert_eq!(sliced.length_of_fixed_segments(), 9);
+        assert!(rs.slice(4..4).is_err());
+
+        let rs = ReadStructure::from_str("8B8T").unwrap();
+        assert_eq!(rs.slice(..).unwrap(), rs);
+        assert!(rs.slice(8..17).is_err());
+        let err = rs.slice(16..).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid range 16.. for read structure 8B8T");
+    }
+
+    #[test]
+    fn test_read_structure_split_at() {
+        let rs = ReadStructure::from_str("8B+T").unwrap();
+        let (index, template) = rs.split_at(8).unwrap();
+        assert_eq!((index.to_string(), template.to_string()), ("8B".to_owned(), "+T".to_owned()));
+        let (first, second) = rs.split_at(10).unwrap();
+        assert_eq!((first.to_string(), second.to_string()), ("8B2T".to_owned(), "+T".to_owned()));
+        assert_eq!(second[0].offset(), 0);
+        assert!(rs.split_at(0).is_err());
+        assert!(ReadStructure::from_str("8B").unwrap().split_at(8).is_err());
+    }
+
+    #[test]
+    fn test_read_structure_concat_and_without_kind() {
+        let rs = ReadStructure::from_str("8B").unwrap();
+        let merged = rs.concat(&ReadStructure::from_str("8B+T").unwrap()).unwrap();
+        assert_eq!(merged.to_string(), "8B8B+T");
+        assert_eq!(merged[2].offset(), 16);
+        assert_eq!(merged.length_of_fixed_segments(), 16);
+        assert!(merged.concat(&rs).is_err());
+
+        let rs = ReadStructure::from_str("2S6M2S+T").unwrap();
+        let trimmed = rs.without_kind(SegmentType::Skip).unwrap();
+        assert_eq!(trimmed.to_string(), "6M+T");
+        assert_eq!(trimmed[1].offset(), 6);
+        assert_eq!(trimmed.length_of_fixed_segments(), 6);
+        assert_eq!(rs.without_kind(SegmentType::CellularBarcode).unwrap(), rs);
+        assert!(ReadStructure::from_str("4S").unwrap().without_kind(SegmentType::Skip).is_err());
+    }
+
+    #[test]
+    fn test_read_structure_resolve() {
+        let rs = ReadStructure::from_str("8B+T").unwrap();
+        let resolved = rs.resolve(150).unwrap();
+        assert_eq!(resolved.to_string(), "8B142T");
+        assert_eq!(resolved.fixed_length(), Some(150));
+        assert_eq!(rs.resolve(9).unwrap().to_string(), "8B1T");
+        let err = rs.resolve(8).unwrap_err();
+        assert_eq!(err.to_string(), "Read structure 8B+T does not fit reads of length 8");
+
+        let rs = ReadStructure::from_str("8B10T").unwrap();
+        assert_eq!(rs.resolve(20).unwrap(), rs);
+        assert_eq!(rs.resolve(18).unwrap(), rs);
+        assert!(rs.resolve(17).is_err());
+    }
+
+    #[test]
+    fn test_read_structure_with_length() {
+        let rs = ReadStructure::from_str("8B6M+T").unwrap();
+        assert_eq!(rs.with_length(100).unwrap().to_string(), "8B6M86T");
+        assert_eq!(rs.with_length(14).unwrap().to_string(), "8B6M");
+        assert_eq!(rs.with_length(10).unwrap().to_string(), "8B2M");
+        assert_eq!(rs.with_length(8).unwrap().length_of_fixed_segments(), 8);
+        assert!(rs.with_length(0).is_err());
+
+        let rs = ReadStructure::from_str("8B10T").unwrap();
+        assert_eq!(rs.with_length(18).unwrap(), rs);
+        assert_eq!(rs.with_length(12).unwrap().to_string(), "8B4T");
+        assert!(rs.with_length(19).is_err());
+    }
+
+    #[test]
+    fn test_read_structure_with_variable_last_segment() {
+        let rs = ReadStructure::from_str("8B10T").unwrap();
+        let variable = rs.with_variable_last_segment();
+        assert_eq!(variable.to_string(), "8B+T");
+        assert_eq!(variable.length_of_fixed_segments(), 8);
+        assert!(!variable.has_fixed_length());
+        assert_eq!(variable.with_variable_last_segment(), variable);
+    }
+
+    #[test]
+    fn test_read_structure_reversed() {
+        let rs = ReadStructure::from_str("4M8B10T").unwrap();
+        let reversed = rs.reversed().unwrap();
+        assert_eq!(reversed.to_string(), "10T8B4M");
+        assert_eq!(reversed[2].offset(), 18);
+        assert_eq!(reversed.reversed().unwrap(), rs);
+
+        let rs = ReadStructure::from_str("4M+T").unwrap();
+        assert!(rs.reversed().is_err());
+        assert_eq!(rs.resolve(10).unwrap().reversed().unwrap().to_string(), "6T4M");
+        assert_eq!(ReadStructure::from_str("+T").unwrap().reversed().unwrap().to_string(), "+T");
+    }
+
+    #[test]
+    fn test_read_structure_extract_reverse_strand() {
+        // Sequenced as AACCCGGGGT, stored reverse complemented
+        let rs = ReadStructure::from_str("2M3B+T").unwrap();
+        let extraction = rs.extract_reverse_strand(b"ACCCCGGGTT").unwrap();
+        let bases: Vec<&[u8]> = extraction.iter().map(|s| s.bases).collect();
+        assert_eq!(bases, vec![&b"TT"[..], b"GGG", b"ACCCC"]);
+        let oriented: Vec<Vec<u8>> =
+            extraction.iter().map(|s| s.oriented_bases().into_owned()).collect();
+        assert_eq!(oriented, vec![b"AA".to_vec(), b"CCC".to_vec(), b"GGGGT".to_vec()]);
+        assert!(rs.extract_reverse_strand(b"ACCC").is_err());
+
+        let extraction =
+            rs.extract_reverse_strand_with_quals(b"ACCCCGGGTT", b"0123456789").unwrap();
+        let quals: Vec<&[u8]> = extraction.iter().map(|s| s.quals.unwrap()).collect();
+        assert_eq!(quals, vec![&b"89"[..], b"567", b"01234"]);
+        let barcode = extraction.sample_barcodes().next().unwrap();
+        assert_eq!(barcode.oriented_quals().unwrap().as_ref(), b"765");
+        assert!(rs.extract_reverse_strand_with_quals(b"ACCCCGGGTT", b"012345678").is_err());
+    }
+
+    #[test]
+    fn test_read_structure_segment_at() {
+        let rs = ReadStructure::from_str("2M3B+T").unwrap();
+        let segment_at = |cycle| rs.segment_at(cycle).map(|(i, s)| (i, s.to_string()));
+        assert_eq!(segment_at(0), Some((0, "2M".to_owned())));
+        assert_eq!(segment_at(4), Some((1, "3B".to_owned())));
+        assert_eq!(segment_at(5), Some((2, "+T".to_owned())));
+        assert_eq!(segment_at(500), Some((2, "+T".to_owned())));
+        assert_eq!(ReadStructure::from_str("2M3B").unwrap().segment_at(5), None);
+    }
+
+    #[test]
+    fn test_read_structure_labels() {
+        let rs = ReadStructure::from_str("8B{i7} 8b{I5} +T").unwrap();
+        assert_eq!(rs.to_string(), "8B{i7}8B{I5}+T");
+        assert_eq!(rs.labels().collect::<Vec<_>>(), vec![Some("i7"), Some("I5"), None]);
+        assert_eq!(rs.label(2), None);
+        assert_eq!(rs.segment_by_label("I5").map(|(i, s)| (i, s.offset())), Some((1, 8)));
+        assert_eq!(rs.segment_by_label("i5"), None);
+        assert_eq!(ReadStructure::from_str(&rs.to_string()).unwrap(), rs);
+        assert_ne!(rs, ReadStructure::from_str("8B8B+T").unwrap());
+
+        let err = ReadStructure::from_str("8B{i7!} +T").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid segment label: 8B[{i7!}]+T");
+        assert_eq!(err.error_message_parts().unwrap().error(), "{i7!}");
+        let err = ReadStructure::from_str("8B{i 7}+T").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid segment label: 8B[{i 7}]+T");
+        assert!(ReadStructure::from_str("8B{}+T").is_err());
+        assert!(ReadStructure::from_str("8B{i7+T").is_err());
+        assert!(ReadStructure::from_str("{i7}8B").is_err());
+        let err = ReadStructure::from_str("8B{i7}8B{i7}").unwrap_err();
+        assert_eq!(err.to_string(), "Read structure has more than one segment labelled: i7");
+
+        let pairs: Vec<(ReadSegment, Option<&str>)> =
+            rs.labelled_segments().map(|(s, l)| (*s, l)).collect();
+        assert_eq!(pairs[1].1, Some("I5"));
+        assert_eq!(ReadStructure::try_from(pairs.as_slice()).unwrap(), rs);
+
+        let rs = ReadStructure::from_str("8B8B+T").unwrap();
+        let labelled = rs.with_label(0, "i7").unwrap();
+        assert_eq!(labelled.to_string(), "8B{i7}8B+T");
+        assert!(labelled.with_label(1, "i7").is_err());
+        assert!(labelled.with_label(1, "").is_err());
+        assert_eq!(labelled.with_label(0, "idx").unwrap().label(0), Some("idx"));
+    }
+
+    #[test]
+    fn test_read_structure_labels_propagate() {
+        let rs = ReadStructure::from_str("4M{umi}8B{i7}+T{r1}").unwrap();
+        assert_eq!(rs.slice(2..6).unwrap().to_string(), "2M{umi}2B{i7}");
+        assert_eq!(rs.with_length(20).unwrap().to_string(), "4M{umi}8B{i7}8T{r1}");
+        assert_eq!(rs.with_length(10).unwrap().to_string(), "4M{umi}6B{i7}");
+        assert_eq!(rs.resolve(20).unwrap().reversed().unwrap().to_string(), "8T{r1}8B{i7}4M{umi}");
+        assert_eq!(
+            rs.without_kind(SegmentType::SampleBarcode).unwrap().to_string(),
+            "4M{umi}+T{r1}"
+        );
+        let fixed = ReadStructure::from_str("8B{i7}10T").unwrap();
+        assert_eq!(fixed.with_variable_last_segment().to_string(), "8B{i7}+T");
+        let other = ReadStructure::from_str("8B{i5}").unwrap();
+        assert_eq!(fixed.concat(&other).unwrap().to_string(), "8B{i7}10T8B{i5}");
+        assert!(fixed.concat(&fixed).is_err());
+
+        let extraction = rs.extract(b"AAAACCCCGGGGTT").unwrap();
+        assert_eq!(extraction.segment_by_label("i7").unwrap().bases, b"CCCCGGGG");
+        assert_eq!(extraction.segments()[2].label.as_deref(), Some("r1"));
+        assert!(extraction.segment_by_label("i5").is_none());
+        let extraction = rs.extract_reverse_strand(b"TTAAAACCCCGGGG").unwrap();
+        assert_eq!(extraction.segment_by_label("umi").unwrap().bases, b"GGGG");
+    }
+
     #[test]
     #[cfg(feature = "serde")]
     fn test_serde() {
@@ -0,0 +1,328 @@
+//! SAM Headers
+//!
+//! Functions for recording the read structures used to build a SAM/BAM file in its header, and
+//! recovering them, so downstream tools can verify the chemistry.  The read structures (one per
+//! read, separated by spaces) are written as `read-structures=<structures>`, either:
+//!
+//! - in the `DS` (description) field of an `@RG` line, appended to any existing description
+//!   with `; `; or
+//! - as a dedicated `@CO` line: `@CO\tread-structures=<structures>`.
+//!
+//! Both are conventions of this crate: neither the SAM specification nor fgbio defines where
+//! read structures are recorded.  fgbio's `FastqToBam` does, however, keep its command line in
+//! the `CL` field of its `@PG` line, so when recovering read structures that field is also
+//! searched for the `--read-structures` (or `-r`) option.  Since command lines may be quoted
+//! or otherwise not parse, read structures that cannot be recovered from them are skipped.
+//!
+//! The header is handled as SAM text (e.g. as produced by `samtools view -H`).
+//!
+//! # Example
+//!
+//! ```rust
+//! use std::str::FromStr;
+//! use read_structure::MultiReadStructure;
+//! use read_structure::sam::{add_read_structures, read_structures_from_header, HeaderLocation, HeaderSource};
+//!
+//! let mrs = MultiReadStructure::from_str("8M+T +T").unwrap();
+//! let header = "@HD\tVN:1.6\n@RG\tID:A\tSM:s1\n";
+//! let header = add_read_structures(header, &mrs, &HeaderLocation::ReadGroup("A".to_owned())).unwrap();
+//! assert_eq!(header, "@HD\tVN:1.6\n@RG\tID:A\tSM:s1\tDS:read-structures=8M+T +T\n");
+//!
+//! let found = read_structures_from_header(&header).unwrap();
+//! assert_eq!(found[0].source, HeaderSource::ReadGroup("A".to_owned()));
+//! assert_eq!(found[0].read_structures, mrs);
+//! ```
+
+use std::str::FromStr;
+
+use crate::multi_read_structure::MultiReadStructure;
+use crate::ReadStructureError;
+
+/// The key preceding the read structures in a `DS` field or `@CO` line.
+pub const READ_STRUCTURES_KEY: &str = "read-structures=";
+
+/// The separator between an existing read group description and the read structures.
+const DESCRIPTION_SEPARATOR: &str = "; ";
+
+/// Where in the header to record read structures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderLocation {
+    /// The `DS` field of the `@RG` line with the given read group identifier.
+    ReadGroup(String),
+    /// A dedicated `@CO` line.
+    Comment,
+}
+
+/// Where in the header read structures were found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderSource {
+    /// The `DS` field of the `@RG` line with the given read group identifier.
+    ReadGroup(String),
+    /// An `@CO` line.
+    Comment,
+    /// The command line of the `@PG` line with the given program identifier.
+    Program(String),
+}
+
+/// Read structures recovered from a SAM header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderReadStructures {
+    /// Where the read structures were found.
+    pub source: HeaderSource,
+    /// The read structures.
+    pub read_structures: MultiReadStructure,
+}
+
+/// Returns the SAM header with the read structures recorded at the given location, replacing
+/// any read structures previously recorded there.
+///
+/// # Errors
+///
+/// - If recording in a read group and no `@RG` line has the given identifier.
+pub fn add_read_structures(
+    header: &str,
+    read_structures: &MultiReadStructure,
+    location: &HeaderLocation,
+) -> Result<String, ReadStructureError> {
+    let value = format!("{}{}", READ_STRUCTURES_KEY, read_structures);
+    let mut lines: Vec<String> = Vec::new();
+    match location {
+        HeaderLocation::Comment => {
+            lines.extend(
+                header
+                    .lines()
+                    .filter(|line| comment_read_structures(line).is_none())
+                    .map(str::to_owned),
+            );
+            lines.push(format!("@CO\t{}", value));
+        }
+        HeaderLocation::ReadGroup(id) => {
+            let mut found = false;
+            for line in header.lines() {
+                if line.starts_with("@RG") && field(line, "ID") == Some(id.as_str()) {
+                    found = true;
+                    lines.push(with_description(line, &value));
+                } else {
+                    lines.push(line.to_owned());
+                }
+            }
+            if !found {
+                return Err(ReadStructureError::SamReadGroupNotFound(id.clone()));
+            }
+        }
+    }
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    Ok(updated)
+}
+
+/// Returns all read structures recorded in the SAM header, in the order they appear.  Read
+/// structures in `@PG` command lines that could not be parsed are skipped.
+///
+/// # Errors
+///
+/// - If any read structure recorded in a read group description or comment could not be
+///   parsed.
+pub fn read_structures_from_header(
+    header: &str,
+) -> Result<Vec<HeaderReadStructures>, ReadStructureError> {
+    let mut found = Vec::new();
+    for line in header.lines() {
+        let recorded = if line.starts_with("@RG") {
+            field(line, "DS").and_then(description_read_structures).map(|rs| {
+                (HeaderSource::ReadGroup(field(line, "ID").unwrap_or_default().to_owned()), rs)
+            })
+        } else if line.starts_with("@CO") {
+            comment_read_structures(line).map(|rs| (HeaderSource::Comment, rs))
+        } else if line.starts_with("@PG") {
+            field(line, "CL").and_then(command_line_read_structures).map(|rs| {
+                (HeaderSource::Program(field(line, "ID").unwrap_or_default().to_owned()), rs)
+            })
+        } else {
+            None
+        };
+        if let Some((source, read_structures)) = recorded {
+            let read_structures = match MultiReadStructure::from_str(&read_structures) {
+                Ok(read_structures) => read_structures,
+                // Command lines are a best-effort source, so skip those that do not parse
+                Err(_) if matches!(source, HeaderSource::Program(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            found.push(HeaderReadStructures { source, read_structures });
+        }
+    }
+    Ok(found)
+}
+
+/// Returns the read structures recorded for the read group with the given identifier, if any.
+///
+/// # Errors
+///
+/// - If the recorded read structure could not be parsed.
+pub fn read_group_read_structures(
+    header: &str,
+    id: &str,
+) -> Result<Option<MultiReadStructure>, ReadStructureError> {
+    let source = HeaderSource::ReadGroup(id.to_owned());
+    Ok(read_structures_from_header(header)?
+        .into_iter()
+        .find(|found| found.source == source)
+        .map(|found| found.read_structures))
+}
+
+/// Returns the value of the field with the given tag in a tab-delimited header line.
+fn field<'a>(line: &'a str, tag: &str) -> Option<&'a str> {
+    line.split('\t').skip(1).find_map(|f| f.strip_prefix(tag).and_then(|f| f.strip_prefix(':')))
+}
+
+/// Returns the header line with the read structures set in the `DS` field.
+fn with_description(line: &str, value: &str) -> String {
+    let mut fields: Vec<String> = line.split('\t').map(str::to_owned).collect();
+    match fields.iter_mut().skip(1).find(|f| f.starts_with("DS:")) {
+        Some(description) => {
+            let existing = &description["DS:".len()..];
+            *description = match existing.find(READ_STRUCTURES_KEY) {
+                // Replace the previous value, keeping any text before it and after the next `;`
+                Some(start) => {
+                    let after = &existing[start..];
+                    let rest = after.find(';').map_or("", |end| &after[end..]);
+                    format!("DS:{}{}{}", &existing[..start], value, rest)
+                }
+                None if existing.is_empty() => format!("DS:{}", value),
+                None => format!("DS:{}{}{}", existing, DESCRIPTION_SEPARATOR, value),
+            };
+        }
+        None => fields.push(format!("DS:{}", value)),
+    }
+    fields.join("\t")
+}
+
+/// Returns the read structures recorded in a read group description, if any.
+fn description_read_structures(description: &str) -> Option<String> {
+    let start = description.find(READ_STRUCTURES_KEY)? + READ_STRUCTURES_KEY.len();
+    let value = description[start..].split(';').next().unwrap_or_default();
+    Some(value.trim().to_owned())
+}
+
+/// Returns the read structures recorded in an `@CO` line, if any.
+fn comment_read_structures(line: &str) -> Option<String> {
+    let comment = line.strip_prefix("@CO\t")?;
+    comment.strip_prefix(READ_STRUCTURES_KEY).map(|value| value.trim().to_owned())
+}
+
+/// Returns the read structures given to fgbio's `FastqToBam` on its command line, if any.
+fn command_line_read_structures(command_line: &str) -> Option<String> {
+    if !command_line.contains("FastqToBam") {
+        return None;
+    }
+    let mut args = command_line.split_whitespace();
+    let mut values = Vec::new();
+    while let Some(arg) = args.next() {
+        if let Some(value) =
+            arg.strip_prefix("--read-structures=").or_else(|| arg.strip_prefix("-r="))
+        {
+            values.push(value);
+            values.extend(args.by_ref().take_while(|a| !a.starts_with('-')));
+            break;
+        } else if arg == "--read-structures" || arg == "-r" {
+            values.extend(args.by_ref().take_while(|a| !a.starts_with('-')));
+            break;
+        }
+    }
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::multi_read_structure::MultiReadStructure;
+    use crate::sam::{
+        add_read_structures, read_group_read_structures, read_structures_from_header,
+        HeaderLocation, HeaderSource,
+    };
+
+    const HEADER: &str = "@HD\tVN:1.6\tSO:unsorted\n\
+        @RG\tID:A\tSM:s1\tDS:library one\n\
+        @RG\tID:B\tSM:s2\n";
+
+    #[test]
+    fn test_add_to_read_group_description() {
+        let mrs = MultiReadStructure::from_str("8M+T 8M+T").unwrap();
+        let location = HeaderLocation::ReadGroup("A".to_owned());
+        let header = add_read_structures(HEADER, &mrs, &location).unwrap();
+        assert!(header.contains("@RG\tID:A\tSM:s1\tDS:library one; read-structures=8M+T 8M+T\n"));
+
+        // Replaces rather than appends when updated
+        let mrs2 = MultiReadStructure::from_str("+T 8B +T").unwrap();
+        let header = add_read_structures(&header, &mrs2, &location).unwrap();
+        assert!(header.contains("@RG\tID:A\tSM:s1\tDS:library one; read-structures=+T 8B +T\n"));
+        assert_eq!(read_group_read_structures(&header, "A").unwrap(), Some(mrs2.clone()));
+        assert_eq!(read_group_read_structures(&header, "B").unwrap(), None);
+
+        // Text after the read structures is kept
+        let header = "@RG\tID:A\tDS:read-structures=8M+T; library one\n";
+        let header = add_read_structures(header, &mrs2, &location).unwrap();
+        assert_eq!(header, "@RG\tID:A\tDS:read-structures=+T 8B +T; library one\n");
+        assert_eq!(read_group_read_structures(&header, "A").unwrap(), Some(mrs2));
+    }
+
+    #[test]
+    fn test_add_to_missing_read_group() {
+        let mrs = MultiReadStructure::from_str("+T").unwrap();
+        let location = HeaderLocation::ReadGroup("C".to_owned());
+        assert!(add_read_structures(HEADER, &mrs, &location).is_err());
+    }
+
+    #[test]
+    fn test_add_comment() {
+        let mrs = MultiReadStructure::from_str("16C12M +T").unwrap();
+        let header = add_read_structures(HEADER, &mrs, &HeaderLocation::Comment).unwrap();
+        let header = add_read_structures(&header, &mrs, &HeaderLocation::Comment).unwrap();
+        assert_eq!(header.matches("@CO").count(), 1);
+        assert!(header.ends_with("@CO\tread-structures=16C12M +T\n"));
+        let found = read_structures_from_header(&header).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].source, HeaderSource::Comment);
+        assert_eq!(found[0].read_structures, mrs);
+    }
+
+    #[test]
+    fn test_read_structures_from_fgbio_program() {
+        let header = "@HD\tVN:1.6\n\
+            @PG\tID:FastqToBam\tPN:fgbio\tCL:FastqToBam --input r1.fq r2.fq \
+            --read-structures 8M+T +T --sample s1 --library l1\n\
+            @PG\tID:other\tCL:FastqToBam -i r1.fq -r=8B --other\n\
+            @PG\tID:bwa\tCL:bwa mem -r 1.5 ref.fa\n\
+            @PG\tID:FastqToBam.1\tCL:FastqToBam --read-structures=8M+T +T --sample s1\n";
+        let found = read_structures_from_header(header).unwrap();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].source, HeaderSource::Program("FastqToBam".to_owned()));
+        assert_eq!(found[0].read_structures.to_string(), "8M+T +T");
+        assert_eq!(found[1].source, HeaderSource::Program("other".to_owned()));
+        assert_eq!(found[1].read_structures.to_string(), "8B");
+        assert_eq!(found[2].read_structures.to_string(), "8M+T +T");
+    }
+
+    #[test]
+    fn test_unparseable_program_read_structures_are_skipped() {
+        let header = "@HD\tVN:1.6\n\
+            @RG\tID:A\tDS:read-structures=8B+T\n\
+            @PG\tID:FastqToBam\tCL:FastqToBam --read-structures '8M+T' '+T' --sample s1\n";
+        let found = read_structures_from_header(header).unwrap();
+        assert_eq!(found.len(), 1);
+        let mrs = read_group_read_structures(header, "A").unwrap().unwrap();
+        assert_eq!(mrs.to_string(), "8B+T");
+    }
+
+    #[test]
+    fn test_invalid_read_structures_in_header() {
+        let header = "@CO\tread-structures=8X\n";
+        assert!(read_structures_from_header(header).is_err());
+        assert!(read_structures_from_header("@CO\tsome other comment\n").unwrap().is_empty());
+    }
+}
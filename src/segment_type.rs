@@ -13,7 +13,6 @@ use crate::ReadStructureError;
 /// The `SegmentType` type. See [the module level documentation](self) for more.
 #[non_exhaustive]
 #[derive(Debug, Copy, Clone, EnumIter, PartialEq, Eq, Hash, PartialOrd, Ord)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum SegmentType {
     /// Template: the bases in the segment are reads of template (e.g. genomic dna, rna, etc.)
@@ -34,6 +33,25 @@ impl SegmentType {
         let value = *self as u8;
         value as char
     }
+
+    /// Returns the snake case name of this segment type (e.g. `sample_barcode`), as used in
+    /// output file and column names.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SegmentType::Template => "template",
+            SegmentType::SampleBarcode => "sample_barcode",
+            SegmentType::MolecularBarcode => "molecular_barcode",
+            SegmentType::Skip => "skip",
+            SegmentType::CellularBarcode => "cellular_barcode",
+        }
+    }
+}
+
+impl std::fmt::Display for SegmentType {
+    /// Formats the [`SegmentType`] as its character representation.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.value())
+    }
 }
 
 impl TryFrom<char> for SegmentType {
@@ -99,10 +117,17 @@ mod test {
         assert_eq!(SegmentType::iter().len(), 5);
         for tpe in SegmentType::iter() {
             assert_eq!(SegmentType::try_from(tpe.value())?, tpe);
+            assert_eq!(SegmentType::from_str(&tpe.to_string())?, tpe);
         }
         Ok(())
     }
 
+    #[test]
+    fn test_segment_type_name() {
+        assert_eq!(SegmentType::Template.name(), "template");
+        assert_eq!(SegmentType::CellularBarcode.name(), "cellular_barcode");
+    }
+
     #[test]
     fn test_invalid_segment_type() {
         assert!(SegmentType::try_from(b'G').is_err());
@@ -120,6 +145,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::nonminimal_bool)]
     fn test_invalid_segment_type_string() {
         assert!(SegmentType::from_str("").is_err());
         assert!(SegmentType::from_str("GG").is_err());
@@ -0,0 +1,574 @@
+//! seqspec Assay Specifications
+//!
+//! Type [`Assay`] is a minimal model of a [seqspec](https://github.com/pachterlab/seqspec)
+//! assay specification.  Only the fields needed to map the reads in the `sequence_spec` onto
+//! the regions in the `library_spec` are modelled; all other fields are ignored when loading.
+//!
+//! Each read is mapped to a [`ReadStructure`] by locating the read's primer among the leaf
+//! regions of the library and walking the regions that follow it (or precede it, for reads on
+//! the negative strand) until the read's maximum length is consumed.  Region types are mapped
+//! onto [`SegmentType`]s as follows:
+//!
+//! - `barcode` → [`SegmentType::CellularBarcode`]
+//! - `umi` → [`SegmentType::MolecularBarcode`]
+//! - `index5`, `index7` → [`SegmentType::SampleBarcode`]
+//! - `cdna`, `gdna`, `dna`, `rna`, `atac`, `protein`, `tag`, `crispr`, `sgrna_target`, `hic`,
+//!   `methyl` → [`SegmentType::Template`]
+//! - `linker`, `poly_A`, `poly_C`, `poly_G`, `poly_T`, adapter and primer regions →
+//!   [`SegmentType::Skip`]
+//!
+//! Any other region type that would be covered by a read results in
+//! [`ReadStructureError::SeqspecUnsupportedRegionType`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use read_structure::seqspec::Assay;
+//!
+//! let yaml = r#"
+//! !Assay
+//! assay_id: example
+//! modalities: [rna]
+//! sequence_spec:
+//! - !Read
+//!   read_id: R1
+//!   modality: rna
+//!   primer_id: r1_primer
+//!   min_len: 28
+//!   max_len: 28
+//!   strand: pos
+//! library_spec:
+//! - !Region
+//!   region_id: rna
+//!   region_type: rna
+//!   min_len: 0
+//!   max_len: 0
+//!   regions:
+//!   - !Region {region_id: r1_primer, region_type: truseq_read1, min_len: 0, max_len: 0}
+//!   - !Region {region_id: cb, region_type: barcode, min_len: 16, max_len: 16}
+//!   - !Region {region_id: umi, region_type: umi, min_len: 12, max_len: 12}
+//!   - !Region {region_id: cdna, region_type: cdna, min_len: 1, max_len: 98}
+//! "#;
+//! let assay = Assay::from_yaml(yaml).unwrap();
+//! assert_eq!(assay.read_structure("R1").unwrap().to_string(), "16C12M");
+//! ```
+
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_yaml::value::{Tag, TaggedValue};
+use serde_yaml::Value;
+
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+use crate::ReadStructureError;
+
+/// The version of the seqspec specification written by [`Assay::to_yaml`].
+pub const SEQSPEC_VERSION: &str = "0.3.0";
+
+/// The maximum length written for a region or read that derives from an indefinite length
+/// ([`crate::ANY_LENGTH_STR`]) segment, since seqspec requires a concrete maximum length.
+pub const DEFAULT_MAX_VARIABLE_LENGTH: usize = 1000;
+
+/// The strand of the library that a read sequences.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strand {
+    /// The read sequences the regions following its primer.
+    Pos,
+    /// The read sequences the regions preceding its primer, in reverse order.
+    Neg,
+}
+
+/// A read in the `sequence_spec` of a seqspec [`Assay`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Read {
+    /// The unique identifier of the read.
+    pub read_id: String,
+    /// The human readable name of the read.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The modality (e.g. `rna`, `atac`) of the library the read sequences.
+    #[serde(default)]
+    pub modality: Option<String>,
+    /// The identifier of the region the read is primed from.
+    pub primer_id: String,
+    /// The minimum length of the read.
+    pub min_len: usize,
+    /// The maximum length of the read.
+    pub max_len: usize,
+    /// The strand the read sequences.
+    pub strand: Strand,
+}
+
+/// A region in the `library_spec` of a seqspec [`Assay`].  Regions are either leaves or
+/// contain an ordered list of sub-regions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    /// The unique identifier of the parent region, if any.
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// The unique identifier of the region.
+    pub region_id: String,
+    /// The type of the region (e.g. `barcode`, `umi`, `cdna`).
+    pub region_type: String,
+    /// The human readable name of the region.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The type of sequence (e.g. `fixed`, `random`, `onlist`, `joined`).
+    #[serde(default)]
+    pub sequence_type: Option<String>,
+    /// The sequence of the region.
+    #[serde(default)]
+    pub sequence: Option<String>,
+    /// The minimum length of the region.
+    #[serde(default)]
+    pub min_len: usize,
+    /// The maximum length of the region.
+    #[serde(default)]
+    pub max_len: usize,
+    /// The ordered sub-regions of this region.
+    #[serde(default)]
+    pub regions: Vec<Region>,
+}
+
+impl Region {
+    /// Returns the [`SegmentType`] that this region maps to, or `None` if the region type is
+    /// not supported.
+    pub fn segment_type(&self) -> Option<SegmentType> {
+        match self.region_type.as_str() {
+            "barcode" => Some(SegmentType::CellularBarcode),
+            "umi" => Some(SegmentType::MolecularBarcode),
+            "index5" | "index7" => Some(SegmentType::SampleBarcode),
+            "cdna" | "gdna" | "dna" | "rna" | "atac" | "protein" | "tag" | "crispr"
+            | "sgrna_target" | "hic" | "methyl" => Some(SegmentType::Template),
+            "linker" | "poly_A" | "poly_C" | "poly_G" | "poly_T" | "illumina_p5"
+            | "illumina_p7" | "truseq_read1" | "truseq_read2" | "nextera_read1"
+            | "nextera_read2" | "custom_primer" | "ME1" | "ME2" | "s5" | "s7"
+            | "sgrna_scaffold" => Some(SegmentType::Skip),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the region has no sub-regions.
+    pub fn is_leaf(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    /// Appends the leaf regions beneath (and including) this region to `leaves`, in order.
+    fn collect_leaves<'a>(&'a self, leaves: &mut Vec<&'a Region>) {
+        if self.is_leaf() {
+            leaves.push(self);
+        } else {
+            for region in &self.regions {
+                region.collect_leaves(leaves);
+            }
+        }
+    }
+}
+
+/// A seqspec assay specification.  See [the module level documentation](self) for more.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assay {
+    /// The version of the seqspec specification.
+    #[serde(default)]
+    pub seqspec_version: Option<String>,
+    /// The unique identifier of the assay.
+    pub assay_id: String,
+    /// The human readable name of the assay.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The modalities (e.g. `rna`, `atac`) of the assay.
+    #[serde(default)]
+    pub modalities: Vec<String>,
+    /// The reads sequenced.
+    #[serde(default)]
+    pub sequence_spec: Vec<Read>,
+    /// The library structure, one top-level region per modality.
+    #[serde(default)]
+    pub library_spec: Vec<Region>,
+}
+
+impl Assay {
+    /// Parses an [`Assay`] from a seqspec YAML string.  YAML tags (e.g. `!Assay`, `!Region`)
+    /// are accepted but not required.
+    ///
+    /// # Errors
+    ///
+    /// - If the YAML could not be parsed or does not describe an assay.
+    pub fn from_yaml(yaml: &str) -> Result<Self, ReadStructureError> {
+        let value: Value = serde_yaml::from_str(yaml)
+            .map_err(|e| ReadStructureError::SeqspecInvalid(e.to_string()))?;
+        serde_yaml::from_value(untag(value))
+            .map_err(|e| ReadStructureError::SeqspecInvalid(e.to_string()))
+    }
+
+    /// Reads an [`Assay`] from a local seqspec YAML file.
+    ///
+    /// # Errors
+    ///
+    /// - If the file could not be read.
+    /// - If the YAML could not be parsed or does not describe an assay.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, ReadStructureError> {
+        Self::from_yaml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Builds a minimal [`Assay`] with a single modality from named read structures.  Each read
+    /// is given a zero-length `custom_primer` region followed by one region per segment.
+    pub fn from_read_structures(
+        assay_id: &str,
+        modality: &str,
+        reads: &[(&str, &ReadStructure)],
+    ) -> Self {
+        let mut regions = Vec::new();
+        let mut sequence_spec = Vec::new();
+        for (read_id, rs) in reads {
+            let primer_id = format!("{}_primer", read_id);
+            regions.push(Region {
+                parent_id: Some(modality.to_owned()),
+                region_id: primer_id.clone(),
+                region_type: "custom_primer".to_owned(),
+                name: Some(primer_id.clone()),
+                sequence_type: Some("fixed".to_owned()),
+                sequence: Some(String::new()),
+                min_len: 0,
+                max_len: 0,
+                regions: vec![],
+            });
+            for (index, segment) in rs.iter().enumerate() {
+                let region_type = match segment.kind {
+                    SegmentType::Template if modality == "rna" => "cdna",
+                    SegmentType::Template => "gdna",
+                    SegmentType::SampleBarcode => "index7",
+                    SegmentType::MolecularBarcode => "umi",
+                    SegmentType::Skip => "linker",
+                    SegmentType::CellularBarcode => "barcode",
+                };
+                let (min_len, max_len) = match segment.length {
+                    Some(len) => (len, len),
+                    None => (1, DEFAULT_MAX_VARIABLE_LENGTH),
+                };
+                let region_id = format!("{}_{}", read_id, index + 1);
+                regions.push(Region {
+                    parent_id: Some(modality.to_owned()),
+                    region_id: region_id.clone(),
+                    region_type: region_type.to_owned(),
+                    name: Some(region_id),
+                    sequence_type: Some("random".to_owned()),
+                    sequence: Some("N".repeat(min_len)),
+                    min_len,
+                    max_len,
+                    regions: vec![],
+                });
+            }
+            let (min_len, max_len) = match rs.fixed_length() {
+                Some(len) => (len, len),
+                None => {
+                    let fixed: usize = rs.iter().filter_map(ReadSegment::length).sum();
+                    (fixed + 1, fixed + DEFAULT_MAX_VARIABLE_LENGTH)
+                }
+            };
+            sequence_spec.push(Read {
+                read_id: (*read_id).to_owned(),
+                name: Some((*read_id).to_owned()),
+                modality: Some(modality.to_owned()),
+                primer_id,
+                min_len,
+                max_len,
+                strand: Strand::Pos,
+            });
+        }
+        let library = Region {
+            parent_id: None,
+            region_id: modality.to_owned(),
+            region_type: modality.to_owned(),
+            name: Some(modality.to_owned()),
+            sequence_type: Some("joined".to_owned()),
+            sequence: None,
+            min_len: 0,
+            max_len: 0,
+            regions,
+        };
+        Assay {
+            seqspec_version: Some(SEQSPEC_VERSION.to_owned()),
+            assay_id: assay_id.to_owned(),
+            name: Some(assay_id.to_owned()),
+            modalities: vec![modality.to_owned()],
+            sequence_spec,
+            library_spec: vec![library],
+        }
+    }
+
+    /// Returns the read with the given identifier.
+    pub fn read(&self, read_id: &str) -> Option<&Read> {
+        self.sequence_spec.iter().find(|r| r.read_id == read_id)
+    }
+
+    /// Returns the [`ReadStructure`] for the read with the given identifier.
+    ///
+    /// # Errors
+    ///
+    /// - If no read with the given identifier exists.
+    /// - If the read's primer region could not be found in the library.
+    /// - If the read covers a region whose type is not supported.
+    /// - If the read covers no regions.
+    pub fn read_structure(&self, read_id: &str) -> Result<ReadStructure, ReadStructureError> {
+        let read = self
+            .read(read_id)
+            .ok_or_else(|| ReadStructureError::SeqspecMissingRead(read_id.to_owned()))?;
+
+        // Restrict the search to the read's modality if it has one, otherwise the whole library
+        let mut leaves = Vec::new();
+        for region in &self.library_spec {
+            let in_modality = read.modality.as_ref().map_or(true, |m| &region.region_id == m);
+            if in_modality {
+                region.collect_leaves(&mut leaves);
+            }
+        }
+        let primer_index = leaves
+            .iter()
+            .position(|r| r.region_id == read.primer_id)
+            .ok_or_else(|| ReadStructureError::SeqspecMissingRegion(read.primer_id.clone()))?;
+        let covered: Vec<&Region> = match read.strand {
+            Strand::Pos => leaves[primer_index + 1..].to_vec(),
+            Strand::Neg => leaves[..primer_index].iter().rev().copied().collect(),
+        };
+
+        let mut segments = Vec::new();
+        let mut remaining = read.max_len;
+        for region in covered {
+            if remaining == 0 {
+                break;
+            }
+            let kind = region.segment_type().ok_or_else(|| {
+                ReadStructureError::SeqspecUnsupportedRegionType {
+                    region_id: region.region_id.clone(),
+                    region_type: region.region_type.clone(),
+                }
+            })?;
+            let consumed = region.max_len.min(remaining);
+            if consumed == 0 {
+                continue;
+            }
+            remaining -= consumed;
+            // A variable length region that ends a variable length read becomes indefinite
+            let is_indefinite =
+                region.min_len != region.max_len && read.min_len != read.max_len && remaining == 0;
+            let length = if is_indefinite { None } else { Some(consumed) };
+            segments.push(ReadSegment { offset: 0, length, kind });
+            if is_indefinite {
+                break;
+            }
+        }
+        if segments.is_empty() {
+            return Err(ReadStructureError::SeqspecInvalid(format!(
+                "read {} does not cover any regions",
+                read_id
+            )));
+        }
+        ReadStructure::new(segments)
+    }
+
+    /// Returns the identifier and [`ReadStructure`] of every read in the assay, in the order
+    /// the reads appear in the `sequence_spec`.
+    ///
+    /// # Errors
+    ///
+    /// - If any read could not be mapped to a [`ReadStructure`].
+    pub fn read_structures(&self) -> Result<Vec<(String, ReadStructure)>, ReadStructureError> {
+        self.sequence_spec
+            .iter()
+            .map(|r| self.read_structure(&r.read_id).map(|rs| (r.read_id.clone(), rs)))
+            .collect()
+    }
+
+    /// Formats the [`Assay`] as seqspec YAML, including the `!Assay`, `!Read`, and `!Region`
+    /// tags expected by the seqspec tooling.
+    ///
+    /// # Errors
+    ///
+    /// - If the assay could not be serialized.
+    pub fn to_yaml(&self) -> Result<String, ReadStructureError> {
+        let to_value = |v| {
+            serde_yaml::to_value(v).map_err(|e| ReadStructureError::SeqspecInvalid(e.to_string()))
+        };
+        let mut value = to_value(self)?;
+        if let Value::Mapping(map) = &mut value {
+            if let Some(Value::Sequence(reads)) = map.get_mut("sequence_spec") {
+                for read in reads.iter_mut() {
+                    *read = tagged("Read", std::mem::take(read));
+                }
+            }
+            if let Some(Value::Sequence(regions)) = map.get_mut("library_spec") {
+                for region in regions.iter_mut() {
+                    tag_regions(region);
+                }
+            }
+        }
+        serde_yaml::to_string(&tagged("Assay", value))
+            .map_err(|e| ReadStructureError::SeqspecInvalid(e.to_string()))
+    }
+}
+
+impl FromStr for Assay {
+    type Err = ReadStructureError;
+
+    /// Parses an [`Assay`] from a seqspec YAML string.  See [`Assay::from_yaml`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_yaml(s)
+    }
+}
+
+/// Recursively removes YAML tags (e.g. `!Region`) from a value.
+fn untag(value: Value) -> Value {
+    match value {
+        Value::Tagged(tagged) => untag(tagged.value),
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(untag).collect()),
+        Value::Mapping(map) => {
+            Value::Mapping(map.into_iter().map(|(k, v)| (k, untag(v))).collect())
+        }
+        other => other,
+    }
+}
+
+/// Wraps a value in the given YAML tag.
+fn tagged(tag: &str, value: Value) -> Value {
+    Value::Tagged(Box::new(TaggedValue { tag: Tag::new(tag), value }))
+}
+
+/// Recursively tags a region and its sub-regions with `!Region`.
+fn tag_regions(region: &mut Value) {
+    if let Value::Mapping(map) = region {
+        if let Some(Value::Sequence(regions)) = map.get_mut("regions") {
+            for sub in regions.iter_mut() {
+                tag_regions(sub);
+            }
+        }
+    }
+    *region = tagged("Region", std::mem::take(region));
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::read_structure::ReadStructure;
+    use crate::seqspec::{Assay, Strand};
+    use crate::ReadStructureError;
+
+    const TENX_V3: &str = r#"
+!Assay
+seqspec_version: 0.3.0
+assay_id: 10x-3prime-v3
+name: 10x 3' v3
+modalities:
+- rna
+sequence_spec:
+- !Read
+  read_id: R1.fastq.gz
+  name: Read 1
+  modality: rna
+  primer_id: r1_primer
+  min_len: 28
+  max_len: 28
+  strand: pos
+- !Read
+  read_id: R2.fastq.gz
+  name: Read 2
+  modality: rna
+  primer_id: r2_primer
+  min_len: 90
+  max_len: 90
+  strand: neg
+- !Read
+  read_id: I1.fastq.gz
+  name: Index 1
+  modality: rna
+  primer_id: i7_primer
+  min_len: 1
+  max_len: 150
+  strand: pos
+library_spec:
+- !Region
+  parent_id: null
+  region_id: rna
+  region_type: rna
+  name: rna
+  sequence_type: joined
+  sequence: ''
+  min_len: 0
+  max_len: 0
+  onlist: null
+  regions:
+  - !Region {region_id: r1_primer, region_type: truseq_read1, min_len: 0, max_len: 0}
+  - !Region {region_id: cell_bc, region_type: barcode, min_len: 16, max_len: 16}
+  - !Region {region_id: umi, region_type: umi, min_len: 12, max_len: 12}
+  - !Region {region_id: poly_t, region_type: poly_T, min_len: 30, max_len: 30}
+  - !Region {region_id: cdna, region_type: cdna, min_len: 1, max_len: 98}
+  - !Region {region_id: r2_primer, region_type: truseq_read2, min_len: 0, max_len: 0}
+  - !Region {region_id: i7_primer, region_type: custom_primer, min_len: 0, max_len: 0}
+  - !Region {region_id: index7, region_type: index7, min_len: 10, max_len: 10}
+  - !Region {region_id: p7, region_type: illumina_p7, min_len: 24, max_len: 24}
+"#;
+
+    #[test]
+    fn test_read_structures_from_seqspec() {
+        let assay = Assay::from_yaml(TENX_V3).unwrap();
+        assert_eq!(assay.sequence_spec[1].strand, Strand::Neg);
+        let structures: Vec<String> = assay
+            .read_structures()
+            .unwrap()
+            .into_iter()
+            .map(|(id, rs)| format!("{}={}", id, rs))
+            .collect();
+        assert_eq!(structures, vec!["R1.fastq.gz=16C12M", "R2.fastq.gz=90T", "I1.fastq.gz=10B24S"]);
+    }
+
+    #[test]
+    fn test_missing_read_and_primer() {
+        let mut assay = Assay::from_yaml(TENX_V3).unwrap();
+        assert!(matches!(
+            assay.read_structure("R3"),
+            Err(ReadStructureError::SeqspecMissingRead(_))
+        ));
+        assay.sequence_spec[0].primer_id = "missing".to_owned();
+        assert!(matches!(
+            assay.read_structure("R1.fastq.gz"),
+            Err(ReadStructureError::SeqspecMissingRegion(_))
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_region_type() {
+        let yaml = TENX_V3.replace("region_type: umi", "region_type: named");
+        let assay = Assay::from_yaml(&yaml).unwrap();
+        // R2 is primed downstream of the unsupported region and does not reach it
+        assert!(assay.read_structure("R2.fastq.gz").is_ok());
+        let err = assay.read_structure("R1.fastq.gz").unwrap_err();
+        assert!(matches!(err, ReadStructureError::SeqspecUnsupportedRegionType { .. }));
+        assert_eq!(err.to_string(), "seqspec region umi has unsupported region type: named");
+    }
+
+    #[test]
+    fn test_invalid_yaml() {
+        assert!(Assay::from_yaml("assay_id: [").is_err());
+        assert!(Assay::from_str("name: missing-assay-id").is_err());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let r1 = ReadStructure::from_str("16C12M").unwrap();
+        let r2 = ReadStructure::from_str("10S+T").unwrap();
+        let assay = Assay::from_read_structures("assay", "rna", &[("R1", &r1), ("R2", &r2)]);
+        let yaml = assay.to_yaml().unwrap();
+        assert!(yaml.starts_with("!Assay"));
+        assert!(yaml.contains("!Region"));
+        assert!(yaml.contains("!Read"));
+        let parsed = Assay::from_yaml(&yaml).unwrap();
+        assert_eq!(parsed, assay);
+        assert_eq!(parsed.read_structure("R1").unwrap(), r1);
+        assert_eq!(parsed.read_structure("R2").unwrap(), r2);
+    }
+}
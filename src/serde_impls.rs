@@ -0,0 +1,378 @@
+//! Serde Support
+//!
+//! With the `serde` feature, [`ReadStructure`], [`MultiReadStructure`], [`ReadSegment`], and
+//! [`SegmentType`] serialize as their compact string forms (e.g. `"76T8B8B76T"`), and are
+//! validated by their [`FromStr`] implementations when deserialized.  Note that a
+//! [`ReadSegment`] deserialized from its string form always has an offset of zero.
+//!
+//! The [`structured`] module provides the field-by-field form for use with
+//! `#[serde(with = "...")]`.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::multi_read_structure::MultiReadStructure;
+use crate::read_segment::ReadSegment;
+use crate::read_structure::ReadStructure;
+use crate::segment_type::SegmentType;
+
+/// Implements `Serialize` via `Display` and `Deserialize` via `FromStr`.
+macro_rules! impl_serde_as_str {
+    ($($t:ty),*) => {
+        $(
+            impl Serialize for $t {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $t {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    from_str(deserializer)
+                }
+            }
+        )*
+    };
+}
+
+impl_serde_as_str!(ReadStructure, MultiReadStructure, ReadSegment, SegmentType);
+
+/// Deserializes a string and parses it with `FromStr`.
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+    T::from_str(&value).map_err(D::Error::custom)
+}
+
+/// The structured (field-by-field) serde forms, for use with `#[serde(with = "...")]`.
+///
+/// # Example
+///
+/// ```rust
+/// use read_structure::ReadStructure;
+///
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Config {
+///     #[serde(with = "read_structure::structured::read_structure")]
+///     read_structure: ReadStructure,
+/// }
+/// ```
+pub mod structured {
+    use serde::{Deserialize, Serialize};
+
+    use crate::read_segment::ReadSegment;
+    use crate::segment_type::SegmentType;
+
+    /// The structured form of a [`SegmentType`]: the name of the variant.
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "SegmentType")]
+    enum SegmentTypeDef {
+        Template,
+        SampleBarcode,
+        MolecularBarcode,
+        Skip,
+        CellularBarcode,
+    }
+
+    impl From<SegmentType> for SegmentTypeDef {
+        fn from(kind: SegmentType) -> Self {
+            match kind {
+                SegmentType::Template => SegmentTypeDef::Template,
+                SegmentType::SampleBarcode => SegmentTypeDef::SampleBarcode,
+                SegmentType::MolecularBarcode => SegmentTypeDef::MolecularBarcode,
+                SegmentType::Skip => SegmentTypeDef::Skip,
+                SegmentType::CellularBarcode => SegmentTypeDef::CellularBarcode,
+            }
+        }
+    }
+
+    impl From<SegmentTypeDef> for SegmentType {
+        fn from(kind: SegmentTypeDef) -> Self {
+            match kind {
+                SegmentTypeDef::Template => SegmentType::Template,
+                SegmentTypeDef::SampleBarcode => SegmentType::SampleBarcode,
+                SegmentTypeDef::MolecularBarcode => SegmentType::MolecularBarcode,
+                SegmentTypeDef::Skip => SegmentType::Skip,
+                SegmentTypeDef::CellularBarcode => SegmentType::CellularBarcode,
+            }
+        }
+    }
+
+    /// The structured form of a [`ReadSegment`].
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "ReadSegment")]
+    struct ReadSegmentDef {
+        offset: usize,
+        length: Option<usize>,
+        kind: SegmentTypeDef,
+    }
+
+    impl From<&ReadSegment> for ReadSegmentDef {
+        fn from(segment: &ReadSegment) -> Self {
+            ReadSegmentDef {
+                offset: segment.offset,
+                length: segment.length,
+                kind: segment.kind.into(),
+            }
+        }
+    }
+
+    impl From<ReadSegmentDef> for ReadSegment {
+        fn from(segment: ReadSegmentDef) -> Self {
+            ReadSegment {
+                offset: segment.offset,
+                length: segment.length,
+                kind: segment.kind.into(),
+            }
+        }
+    }
+
+    /// The structured form of a [`SegmentType`]: the name of the variant (e.g. `"Template"`).
+    pub mod segment_type {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        use super::SegmentTypeDef;
+        use crate::segment_type::SegmentType;
+
+        /// Serializes a [`SegmentType`] as the name of the variant.
+        ///
+        /// # Errors
+        ///
+        /// - If the serializer fails.
+        pub fn serialize<S: Serializer>(
+            kind: &SegmentType,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            SegmentTypeDef::from(*kind).serialize(serializer)
+        }
+
+        /// Deserializes a [`SegmentType`] from the name of the variant.
+        ///
+        /// # Errors
+        ///
+        /// - If the value is not the name of a variant.
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<SegmentType, D::Error> {
+            SegmentTypeDef::deserialize(deserializer).map(SegmentType::from)
+        }
+    }
+
+    /// The structured form of a [`ReadSegment`]: its offset, length, and kind.
+    pub mod read_segment {
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        use super::ReadSegmentDef;
+        use crate::read_segment::ReadSegment;
+
+        /// Serializes a [`ReadSegment`] as its offset, length, and kind.
+        ///
+        /// # Errors
+        ///
+        /// - If the serializer fails.
+        pub fn serialize<S: Serializer>(
+            segment: &ReadSegment,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            ReadSegmentDef::from(segment).serialize(serializer)
+        }
+
+        /// Deserializes a [`ReadSegment`] from its offset, length, and kind.
+        ///
+        /// # Errors
+        ///
+        /// - If any field is missing or invalid.
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<ReadSegment, D::Error> {
+            ReadSegmentDef::deserialize(deserializer).map(ReadSegment::from)
+        }
+    }
+
+    /// The structured form of a [`crate::read_structure::ReadStructure`]: its elements, the
+    /// combined length of its fixed length segments, and the label of each element (omitted
+    /// when no element is labelled).  The structure is validated with
+    /// [`crate::read_structure::ReadStructure::new`] when deserialized, and the offsets and
+    /// combined length must agree with those computed.
+    pub mod read_structure {
+        use std::sync::Arc;
+
+        use serde::de::Error;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        use super::ReadSegmentDef;
+        use crate::read_segment::ReadSegment;
+        use crate::read_structure::ReadStructure;
+
+        #[derive(Serialize, Deserialize)]
+        #[serde(rename = "ReadStructure")]
+        struct ReadStructureDef {
+            elements: Vec<ReadSegmentDef>,
+            length_of_fixed_segments: usize,
+            #[serde(default, skip_serializing_if = "no_labels")]
+            labels: Vec<Option<String>>,
+        }
+
+        /// Returns true if no element is labelled.
+        fn no_labels(labels: &[Option<String>]) -> bool {
+            labels.iter().all(Option::is_none)
+        }
+
+        /// Serializes a [`ReadStructure`] as its elements and the combined length of its fixed
+        /// length segments.
+        ///
+        /// # Errors
+        ///
+        /// - If the serializer fails.
+        pub fn serialize<S: Serializer>(
+            rs: &ReadStructure,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            ReadStructureDef {
+                elements: rs.iter().map(ReadSegmentDef::from).collect(),
+                length_of_fixed_segments: rs.length_of_fixed_segments(),
+                labels: rs.labels().map(|l| l.map(ToOwned::to_owned)).collect(),
+            }
+            .serialize(serializer)
+        }
+
+        /// Deserializes and validates a [`ReadStructure`] from its elements and the combined
+        /// length of its fixed length segments.
+        ///
+        /// # Errors
+        ///
+        /// - If the read structure is invalid.
+        /// - If the offsets or combined length disagree with the elements.
+        /// - If there are labels but not one per element, or the labels are invalid.
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<ReadStructure, D::Error> {
+            let def = ReadStructureDef::deserialize(deserializer)?;
+            let elements: Vec<ReadSegment> =
+                def.elements.into_iter().map(ReadSegment::from).collect();
+            let rs = ReadStructure::new(elements.clone()).map_err(D::Error::custom)?;
+            if rs.segments() != elements.as_slice()
+                || rs.length_of_fixed_segments() != def.length_of_fixed_segments
+            {
+                return Err(D::Error::custom(format!(
+                    "Inconsistent offsets or lengths for read structure: {}",
+                    rs
+                )));
+            }
+            if def.labels.is_empty() {
+                Ok(rs)
+            } else if def.labels.len() == rs.number_of_segments() {
+                rs.with_labels(def.labels.into_iter().map(|l| l.map(Arc::from)).collect())
+                    .map_err(D::Error::custom)
+            } else {
+                Err(D::Error::custom(format!(
+                    "Expected {} labels for read structure: {}",
+                    rs.number_of_segments(),
+                    rs
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use crate::multi_read_structure::MultiReadStructure;
+    use crate::read_segment::ReadSegment;
+    use crate::read_structure::ReadStructure;
+    use crate::segment_type::SegmentType;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Structured {
+        #[serde(with = "crate::structured::read_structure")]
+        rs: ReadStructure,
+        #[serde(with = "crate::structured::read_segment")]
+        segment: ReadSegment,
+        #[serde(with = "crate::structured::segment_type")]
+        kind: SegmentType,
+    }
+
+    #[test]
+    fn test_serde_as_str() {
+        let rs = ReadStructure::from_str("76T8B8B+T").unwrap();
+        assert_eq!(serde_json::to_string(&rs).unwrap(), "\"76T8B8B+T\"");
+        assert_eq!(serde_json::from_str::<ReadStructure>("\"76T8B8B+T\"").unwrap(), rs);
+        assert_eq!(serde_json::to_string(&rs[1]).unwrap(), "\"8B\"");
+        assert_eq!(serde_json::to_string(&SegmentType::Skip).unwrap(), "\"S\"");
+        assert_eq!(
+            serde_json::from_str::<SegmentType>("\"C\"").unwrap(),
+            SegmentType::CellularBarcode
+        );
+        let mrs = MultiReadStructure::from_str("16C12M +T").unwrap();
+        assert_eq!(serde_json::to_string(&mrs).unwrap(), "\"16C12M +T\"");
+        assert_eq!(serde_json::from_str::<MultiReadStructure>("\"16C12M +T\"").unwrap(), mrs);
+    }
+
+    #[test]
+    fn test_serde_as_str_validates() {
+        assert!(serde_json::from_str::<ReadStructure>("\"+T8B\"").is_err());
+        assert!(serde_json::from_str::<ReadSegment>("\"8B8B\"").is_err());
+        assert!(serde_json::from_str::<SegmentType>("\"X\"").is_err());
+        assert!(serde_json::from_str::<ReadStructure>("5").is_err());
+    }
+
+    #[test]
+    fn test_serde_structured() {
+        let rs = ReadStructure::from_str("10T2B").unwrap();
+        let value = Structured { rs: rs.clone(), segment: rs[1], kind: SegmentType::Template };
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(
+            json,
+            "{\"rs\":{\"elements\":[\
+            {\"offset\":0,\"length\":10,\"kind\":\"Template\"},\
+            {\"offset\":10,\"length\":2,\"kind\":\"SampleBarcode\"}],\
+            \"length_of_fixed_segments\":12},\
+            \"segment\":{\"offset\":10,\"length\":2,\"kind\":\"SampleBarcode\"},\
+            \"kind\":\"Template\"}"
+        );
+        assert_eq!(serde_json::from_str::<Structured>(&json).unwrap(), value);
+    }
+
+    #[test]
+    fn test_serde_structured_validates() {
+        let valid = "{\"rs\":{\"elements\":[\
+            {\"offset\":0,\"length\":10,\"kind\":\"Template\"},\
+            {\"offset\":10,\"length\":2,\"kind\":\"SampleBarcode\"}],\
+            \"length_of_fixed_segments\":12},\
+            \"segment\":{\"offset\":0,\"length\":2,\"kind\":\"Skip\"},\
+            \"kind\":\"Skip\"}";
+        assert!(serde_json::from_str::<Structured>(valid).is_ok());
+        let bad_offset = valid.replace("\"offset\":10", "\"offset\":3");
+        assert!(serde_json::from_str::<Structured>(&bad_offset).is_err());
+        let bad_length =
+            valid.replace("\"length_of_fixed_segments\":12", "\"length_of_fixed_segments\":7");
+        assert!(serde_json::from_str::<Structured>(&bad_length).is_err());
+        let bad_indefinite = valid.replace("\"length\":10", "\"length\":null");
+        assert!(serde_json::from_str::<Structured>(&bad_indefinite).is_err());
+    }
+
+    #[test]
+    fn test_serde_labels() {
+        let rs = ReadStructure::from_str("8B{i7}+T").unwrap();
+        assert_eq!(serde_json::to_string(&rs).unwrap(), "\"8B{i7}+T\"");
+        assert_eq!(serde_json::from_str::<ReadStructure>("\"8B{i7}+T\"").unwrap(), rs);
+
+        let value = Structured { rs: rs.clone(), segment: rs[0], kind: SegmentType::Template };
+        let json = serde_json::to_string(&value).unwrap();
+        assert!(json.contains("\"labels\":[\"i7\",null]"));
+        assert_eq!(serde_json::from_str::<Structured>(&json).unwrap(), value);
+        let bad_count = json.replace("[\"i7\",null]", "[\"i7\"]");
+        assert!(serde_json::from_str::<Structured>(&bad_count).is_err());
+        let duplicate = json.replace("[\"i7\",null]", "[\"i7\",\"i7\"]");
+        assert!(serde_json::from_str::<Structured>(&duplicate).is_err());
+    }
+}
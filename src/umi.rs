@@ -0,0 +1,422 @@
+//! UMI Grouping
+//!
+//! Type [`UmiAssigner`] groups the unique molecular identifiers (UMIs) of reads that share a
+//! position into molecules, following fgbio's `GroupReadsByUmi`.  The [`Strategy`] decides which
+//! UMIs are considered to come from the same molecule:
+//!
+//! - `identity`: only identical UMIs;
+//! - `edit`: UMIs linked by a chain of UMIs each within the maximum number of mismatches;
+//! - `adjacency`: fgbio's adjacency method, where UMIs are visited from most to least abundant,
+//!   and each UMI absorbs any unassigned UMI within the maximum number of mismatches that was
+//!   seen at most half (rounded down) plus one as many times;
+//! - `directional`: UMI-tools' directional method, as `adjacency` but a UMI absorbs another only
+//!   if it was seen at least twice, less one, as many times; and
+//! - `paired`: for duplex UMIs of the form `A-B`, as `adjacency` but treating `A-B` and `B-A` as
+//!   the same molecule.  Molecule IDs are suffixed with `/A` or `/B` for the two strands.
+//!
+//! UMIs are compared case-insensitively, and must all be the same length for all strategies but
+//! `identity`.  As in fgbio, reads whose UMIs contain no-calls should be filtered out before
+//! grouping.  Each UMI is compared with every other, so UMIs should be grouped per position.
+//!
+//! UMIs made of multiple molecular barcode segments (e.g. one per read of a pair) are joined with
+//! `-`, as by [`umi_from_extraction`].
+//!
+//! # Example
+//!
+//! ```rust
+//! use read_structure::umi::{Strategy, UmiAssigner};
+//!
+//! let assigner = UmiAssigner::new(Strategy::Adjacency, 1);
+//! let ids = assigner.assign(&["AAAA", "AAAA", "AAAA", "AAAT", "CCCC"]).unwrap();
+//! let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+//! assert_eq!(ids, ["0", "0", "0", "0", "1"]);
+//!
+//! let assigner = UmiAssigner::new(Strategy::Paired, 1);
+//! let ids = assigner.assign(&["AAAA-CCCC", "CCCC-AAAA", "GGGG-TTTT"]).unwrap();
+//! let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+//! assert_eq!(ids, ["0/A", "0/B", "1/A"]);
+//! ```
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, IntoStaticStr};
+
+use crate::extraction::Extraction;
+use crate::ReadStructureError;
+
+/// The separator between the parts of a UMI made of multiple segments.
+pub const UMI_SEPARATOR: u8 = b'-';
+
+/// A strategy for grouping UMIs into molecules.  See [the module level documentation](self) for
+/// more.
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, Display, EnumIter, IntoStaticStr, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Only identical UMIs.
+    #[strum(serialize = "identity")]
+    Identity,
+    /// UMIs linked by chains of UMIs within the maximum number of mismatches.
+    #[strum(serialize = "edit")]
+    Edit,
+    /// fgbio's count-directed adjacency.
+    #[strum(serialize = "adjacency")]
+    Adjacency,
+    /// UMI-tools' count-directed adjacency.
+    #[strum(serialize = "directional")]
+    Directional,
+    /// fgbio's adjacency for duplex `A-B` UMIs.
+    #[strum(serialize = "paired")]
+    Paired,
+}
+
+impl Strategy {
+    /// Returns the name of the strategy.
+    pub fn name(&self) -> &'static str {
+        self.into()
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = ReadStructureError;
+
+    /// Resolves a [`Strategy`] by name, ignoring case.
+    ///
+    /// # Errors
+    ///
+    /// - If no strategy has the given name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        Strategy::iter()
+            .find(|strategy| strategy.name() == name)
+            .ok_or_else(|| ReadStructureError::UmiStrategyUnknown(s.to_owned()))
+    }
+}
+
+/// The strand of a duplex molecule a read came from, for the `paired` strategy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Strand {
+    /// The strand whose UMI is in the same orientation as the molecule's.
+    A,
+    /// The strand whose UMI has its parts swapped relative to the molecule's.
+    B,
+}
+
+/// The molecule a UMI was assigned to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MoleculeId {
+    /// The ID of the molecule, numbered from zero in the order molecules are first seen.
+    pub id: usize,
+    /// The strand of the molecule, for the `paired` strategy only.
+    pub strand: Option<Strand>,
+}
+
+impl std::fmt::Display for MoleculeId {
+    /// Formats the [`MoleculeId`] as fgbio's `MI` tag: the ID, followed by `/A` or `/B` for
+    /// paired UMIs.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.strand {
+            None => write!(f, "{}", self.id),
+            Some(Strand::A) => write!(f, "{}/A", self.id),
+            Some(Strand::B) => write!(f, "{}/B", self.id),
+        }
+    }
+}
+
+/// Returns the number of mismatches between two UMIs of the same length, ignoring case.
+pub fn mismatches(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).filter(|(a, b)| !a.eq_ignore_ascii_case(b)).count()
+}
+
+/// Returns the UMI of the molecular barcode segments extracted from one or more reads: the
+/// bases of each segment in the orientation of its read (see
+/// [`crate::extraction::ExtractedSegment::oriented_bases`]), in order, joined with
+/// [`UMI_SEPARATOR`].
+pub fn umi_from_extraction(extraction: &Extraction) -> Vec<u8> {
+    let mut umi = Vec::new();
+    for segment in extraction.molecular_barcodes() {
+        if !umi.is_empty() {
+            umi.push(UMI_SEPARATOR);
+        }
+        umi.extend_from_slice(&segment.oriented_bases());
+    }
+    umi
+}
+
+/// Assigns UMIs to molecules.  See [the module level documentation](self) for more.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UmiAssigner {
+    /// The strategy for grouping UMIs.
+    strategy: Strategy,
+    /// The maximum number of mismatches between UMIs of the same molecule.
+    max_mismatches: usize,
+}
+
+impl UmiAssigner {
+    /// Builds a new [`UmiAssigner`].  The maximum number of mismatches is ignored by the
+    /// `identity` strategy.
+    pub fn new(strategy: Strategy, max_mismatches: usize) -> Self {
+        UmiAssigner { strategy, max_mismatches }
+    }
+
+    /// Returns the strategy for grouping UMIs.
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Returns the maximum number of mismatches between UMIs of the same molecule.
+    pub fn max_mismatches(&self) -> usize {
+        self.max_mismatches
+    }
+
+    /// Assigns each UMI to a molecule, returning the molecule IDs in the same order as the UMIs.
+    ///
+    /// # Errors
+    ///
+    /// - If the UMIs differ in length, for all strategies but `identity`.
+    /// - If any UMI does not have exactly two parts, for the `paired` strategy.
+    pub fn assign<U: AsRef<[u8]>>(
+        &self,
+        umis: &[U],
+    ) -> Result<Vec<MoleculeId>, ReadStructureError> {
+        let umis: Vec<Vec<u8>> = umis.iter().map(|u| u.as_ref().to_ascii_uppercase()).collect();
+        if self.strategy != Strategy::Identity {
+            if let Some(umi) = umis.iter().find(|u| u.len() != umis[0].len()) {
+                return Err(invalid(umi, &format!("expected a UMI of length {}", umis[0].len())));
+            }
+        }
+        let keys = if self.strategy == Strategy::Paired {
+            umis.iter().map(|u| canonical(u)).collect::<Result<Vec<_>, _>>()?
+        } else {
+            umis.clone()
+        };
+
+        // The distinct UMIs (or canonical paired UMIs), in the order first seen, with counts
+        let mut indices = HashMap::new();
+        let mut distinct: Vec<&[u8]> = Vec::new();
+        let mut counts: Vec<usize> = Vec::new();
+        let of: Vec<usize> = keys
+            .iter()
+            .map(|key| {
+                let index = *indices.entry(key.as_slice()).or_insert_with(|| {
+                    distinct.push(key);
+                    counts.push(0);
+                    distinct.len() - 1
+                });
+                counts[index] += 1;
+                index
+            })
+            .collect();
+
+        let k = self.max_mismatches;
+        let roots = match self.strategy {
+            Strategy::Identity => (0..distinct.len()).collect(),
+            Strategy::Edit => single_linkage(&distinct, k),
+            Strategy::Adjacency => {
+                directed(&distinct, &counts, |p, c| c <= p / 2 + 1, |a, b| mismatches(a, b) <= k)
+            }
+            Strategy::Directional => {
+                directed(&distinct, &counts, |p, c| p + 1 >= 2 * c, |a, b| mismatches(a, b) <= k)
+            }
+            Strategy::Paired => directed(
+                &distinct,
+                &counts,
+                |p, c| c <= p / 2 + 1,
+                |a, b| mismatches(a, b).min(mismatches(a, &swap(b))) <= k,
+            ),
+        };
+
+        // Number molecules in the order they are first seen
+        let mut ids = HashMap::new();
+        let molecules = umis
+            .iter()
+            .zip(of)
+            .map(|(umi, index)| {
+                let root = roots[index];
+                let next = ids.len();
+                let id = *ids.entry(root).or_insert(next);
+                let strand = (self.strategy == Strategy::Paired).then(|| {
+                    let molecule = distinct[root];
+                    if mismatches(umi, molecule) <= mismatches(umi, &swap(molecule)) {
+                        Strand::A
+                    } else {
+                        Strand::B
+                    }
+                });
+                MoleculeId { id, strand }
+            })
+            .collect();
+        Ok(molecules)
+    }
+}
+
+/// Returns an error for an invalid UMI.
+fn invalid(umi: &[u8], message: &str) -> ReadStructureError {
+    ReadStructureError::UmiInvalid(format!("{}: {}", message, String::from_utf8_lossy(umi)))
+}
+
+/// Returns the paired UMI `A-B` as `B-A`.  UMIs without a separator are returned unchanged.
+fn swap(umi: &[u8]) -> Vec<u8> {
+    match umi.iter().position(|&b| b == UMI_SEPARATOR) {
+        Some(i) => [&umi[i + 1..], &[UMI_SEPARATOR], &umi[..i]].concat(),
+        None => umi.to_vec(),
+    }
+}
+
+/// Returns the lesser of the paired UMI `A-B` and `B-A`.
+fn canonical(umi: &[u8]) -> Result<Vec<u8>, ReadStructureError> {
+    if umi.iter().filter(|&&b| b == UMI_SEPARATOR).count() != 1 {
+        return Err(invalid(umi, "expected a paired UMI of the form A-B"));
+    }
+    let swapped = swap(umi);
+    Ok(if swapped.as_slice() < umi { swapped } else { umi.to_vec() })
+}
+
+/// Returns the index of the root of each UMI's group, where groups are the connected components
+/// of UMIs within `max_mismatches` of each other.
+fn single_linkage(umis: &[&[u8]], max_mismatches: usize) -> Vec<usize> {
+    let mut parents: Vec<usize> = (0..umis.len()).collect();
+    fn find(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+    for i in 0..umis.len() {
+        for j in i + 1..umis.len() {
+            if mismatches(umis[i], umis[j]) <= max_mismatches {
+                let (a, b) = (find(&mut parents, i), find(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    (0..umis.len()).map(|i| find(&mut parents, i)).collect()
+}
+
+/// Returns the index of the root of each UMI's group, visiting UMIs from most to least abundant.
+/// Each unassigned UMI becomes a root, and each UMI in its group absorbs any unassigned UMI it
+/// `matches` whose count it `can_absorb`.
+fn directed<A, M>(umis: &[&[u8]], counts: &[usize], can_absorb: A, matches: M) -> Vec<usize>
+where
+    A: Fn(usize, usize) -> bool,
+    M: Fn(&[u8], &[u8]) -> bool,
+{
+    let mut order: Vec<usize> = (0..umis.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(counts[i]));
+    let mut roots: Vec<Option<usize>> = vec![None; umis.len()];
+    for &root in &order {
+        if roots[root].is_some() {
+            continue;
+        }
+        roots[root] = Some(root);
+        let mut working = vec![root];
+        while let Some(parent) = working.pop() {
+            for &child in &order {
+                if roots[child].is_none()
+                    && can_absorb(counts[parent], counts[child])
+                    && matches(umis[parent], umis[child])
+                {
+                    roots[child] = Some(root);
+                    working.push(child);
+                }
+            }
+        }
+    }
+    roots.into_iter().map(|root| root.unwrap_or_default()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use strum::IntoEnumIterator;
+
+    use crate::read_structure::ReadStructure;
+    use crate::umi::{umi_from_extraction, MoleculeId, Strand, Strategy, UmiAssigner};
+
+    /// Returns the molecule IDs, as strings, assigned to the UMIs.
+    fn assign(strategy: Strategy, max_mismatches: usize, umis: &[&str]) -> Vec<String> {
+        let assigner = UmiAssigner::new(strategy, max_mismatches);
+        assigner.assign(umis).unwrap().iter().map(MoleculeId::to_string).collect()
+    }
+
+    #[test]
+    fn test_strategy_from_str() {
+        for strategy in Strategy::iter() {
+            assert_eq!(Strategy::from_str(strategy.name()).unwrap(), strategy);
+        }
+        assert_eq!(Strategy::from_str("Paired").unwrap(), Strategy::Paired);
+        assert!(Strategy::from_str("cluster").is_err());
+    }
+
+    #[test]
+    fn test_identity() {
+        let ids = assign(Strategy::Identity, 1, &["AAAA", "aaaa", "AAAT", "AAA", "AAAA"]);
+        assert_eq!(ids, ["0", "0", "1", "2", "0"]);
+        assert!(assign(Strategy::Identity, 0, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_edit() {
+        // AAAA and AATT are linked through AAAT
+        let ids = assign(Strategy::Edit, 1, &["AAAA", "CCCC", "AATT", "AAAT", "CCCC"]);
+        assert_eq!(ids, ["0", "1", "0", "0", "1"]);
+        let ids = assign(Strategy::Edit, 0, &["AAAA", "AAAT"]);
+        assert_eq!(ids, ["0", "1"]);
+        let ids = assign(Strategy::Edit, 2, &["AAAA", "AATT"]);
+        assert_eq!(ids, ["0", "0"]);
+        assert!(UmiAssigner::new(Strategy::Edit, 1).assign(&["AAAA", "AAA"]).is_err());
+    }
+
+    #[test]
+    fn test_adjacency() {
+        // AAAA (4) absorbs AAAT (3 <= 4/2 + 1), which absorbs AATT (1 <= 3/2 + 1)
+        let mut umis = vec!["AAAA"; 4];
+        umis.extend(["AAAT"; 3]);
+        umis.extend(["AATT", "GGGG"]);
+        let ids = assign(Strategy::Adjacency, 1, &umis);
+        assert_eq!(ids, ["0", "0", "0", "0", "0", "0", "0", "0", "1"]);
+        // AAAT (3) is too abundant to be absorbed by AAAA (3): 3 > 3/2 + 1
+        let umis = ["AAAA", "AAAA", "AAAA", "AAAT", "AAAT", "AAAT"];
+        assert_eq!(assign(Strategy::Adjacency, 1, &umis), ["0", "0", "0", "1", "1", "1"]);
+        // With no mismatches allowed, adjacency is identity
+        let umis = ["AAAA", "AAAA", "AAAT"];
+        assert_eq!(assign(Strategy::Adjacency, 0, &umis), ["0", "0", "1"]);
+    }
+
+    #[test]
+    fn test_directional() {
+        // AAAA (4) absorbs AAAT (2) under both, but only adjacency absorbs AAAT (3)
+        let umis = ["AAAA", "AAAA", "AAAA", "AAAA", "AAAT", "AAAT"];
+        assert_eq!(assign(Strategy::Directional, 1, &umis), ["0", "0", "0", "0", "0", "0"]);
+        let umis = ["AAAA", "AAAA", "AAAA", "AAAA", "AAAT", "AAAT", "AAAT"];
+        assert_eq!(assign(Strategy::Directional, 1, &umis), ["0", "0", "0", "0", "1", "1", "1"]);
+        assert_eq!(assign(Strategy::Adjacency, 1, &umis), ["0", "0", "0", "0", "0", "0", "0"]);
+    }
+
+    #[test]
+    fn test_paired() {
+        let umis = ["AAAA-CCCC", "CCCC-AAAA", "AAAA-CCCC", "CCCC-AAAT", "GGGG-TTTT", "TTTT-GGGG"];
+        let ids = assign(Strategy::Paired, 1, &umis);
+        assert_eq!(ids, ["0/A", "0/B", "0/A", "0/B", "1/A", "1/B"]);
+        let assigner = UmiAssigner::new(Strategy::Paired, 1);
+        let ids = assigner.assign(&["TTTT-GGGG"]).unwrap();
+        assert_eq!(ids, [MoleculeId { id: 0, strand: Some(Strand::B) }]);
+        assert!(assigner.assign(&["AAAACCCC"]).is_err());
+        assert!(assigner.assign(&["AA-AA-CCCC"]).is_err());
+    }
+
+    #[test]
+    fn test_umi_from_extraction() {
+        let rs = ReadStructure::from_str("4M2S3M+T").unwrap();
+        let extraction = rs.extract(b"AAAAGGCCCTTTT").unwrap();
+        assert_eq!(umi_from_extraction(&extraction), b"AAAA-CCC");
+        // The same read, stored reverse complemented
+        let extraction = rs.extract_reverse_strand(b"AAAAGGGCCTTTT").unwrap();
+        assert_eq!(umi_from_extraction(&extraction), b"AAAA-CCC");
+        let rs = ReadStructure::from_str("+T").unwrap();
+        assert!(umi_from_extraction(&rs.extract(b"ACGT").unwrap()).is_empty());
+    }
+}